@@ -15,5 +15,19 @@ pub fn run_app() {
 
     log::info!("Bootstrapping main...");
 
-    yew::start_app_with_props::<MainView>(Properties {})
+    let document = yew::utils::document();
+
+    if let Some(load_error) = document.get_element_by_id("load-error") {
+        load_error.remove();
+    }
+
+    yew::initialize();
+
+    // the server renders the current page into `#app`, mounting replaces it with the live view
+    match document.get_element_by_id("app") {
+        Some(element) => yew::App::<MainView>::new().mount_with_props(element, Properties {}),
+        None => yew::App::<MainView>::new().mount_to_body_with_props(Properties {}),
+    };
+
+    yew::run_loop();
 }
@@ -0,0 +1,242 @@
+use nova_common::api::{PostDetail, PostList, PostQuery, PostSummary};
+use nova_common::chrono::SecondsFormat;
+use nova_common::Endpoint;
+use yew::prelude::*;
+use yew::services::fetch::FetchTask;
+use yew::virtual_dom::VNode;
+use yew_router::prelude::*;
+use yewtil::NeqAssign;
+
+use super::{fetch_json, AppRoute};
+
+/// Date and tags line shared by the list and the post, tags are clickable when `on_tag` is given
+fn post_meta(post: &PostSummary, on_tag: Option<&Callback<String>>) -> Html {
+    html! {
+        <p class="text-muted">
+            <time datetime={post.date.to_rfc3339_opts(SecondsFormat::AutoSi, true)}>{ post.date.format("%Y-%m-%d").to_string() }</time>
+            { for post.tags.iter().map(|tag| {
+                let onclick = on_tag.map(|on_tag| {
+                    let tag = tag.clone();
+                    on_tag.reform(move |_| tag.clone())
+                });
+
+                html! { <>{" "}<span class="badge badge-secondary blog-tag" onclick=onclick.unwrap_or_default()>{ tag }</span></> }
+            }) }
+        </p>
+    }
+}
+
+pub struct BlogView {
+    pub link: ComponentLink<Self>,
+
+    pub list: Option<PostList>,
+    pub failed: bool,
+    pub page: usize,
+    pub tag: Option<String>,
+
+    pub task: Option<FetchTask>,
+}
+
+pub enum BlogMsg {
+    Loaded(Option<PostList>),
+    Filter(Option<String>),
+    Older,
+    Newer,
+}
+
+impl BlogView {
+    fn fetch(&mut self) {
+        let url = Endpoint::Posts.query(&PostQuery {
+            page: Some(self.page),
+            per_page: None,
+            tag: self.tag.clone(),
+        });
+
+        self.task = fetch_json(&self.link, &url, BlogMsg::Loaded);
+    }
+}
+
+impl Component for BlogView {
+    type Message = BlogMsg;
+    type Properties = ();
+
+    fn create(_: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut view = BlogView {
+            link,
+            list: None,
+            failed: false,
+            page: 1,
+            tag: None,
+            task: None,
+        };
+
+        view.fetch();
+        view
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            BlogMsg::Loaded(list) => {
+                self.task = None;
+                self.failed = list.is_none();
+                self.list = list;
+                return true;
+            }
+            BlogMsg::Filter(tag) => {
+                self.tag = tag;
+                self.page = 1;
+            }
+            BlogMsg::Older => self.page += 1,
+            BlogMsg::Newer => self.page = self.page.saturating_sub(1).max(1),
+        }
+
+        self.fetch();
+        false
+    }
+
+    fn change(&mut self, _: Self::Properties) -> ShouldRender {
+        false
+    }
+
+    fn view(&self) -> Html {
+        let list = match self.list {
+            Some(ref list) => list,
+            None if self.failed => {
+                return html! { <div class="container blog"><h1>{"Blog"}</h1><p class="text-muted">{"Posts could not be loaded."}</p></div> }
+            }
+            None => return html! { <div class="container blog"><h1>{"Blog"}</h1><p class="text-muted">{"Loading…"}</p></div> },
+        };
+
+        let on_tag = self.link.callback(|tag| BlogMsg::Filter(Some(tag)));
+
+        html! {
+            <div class="container blog">
+                <h1>{"Blog"}</h1>
+                { match list.tag {
+                    Some(ref tag) => html! {
+                        <p class="blog-filter">
+                            {"Posts tagged "}<span class="badge badge-secondary">{ tag }</span>{" "}
+                            <button type="button" class="btn btn-link" onclick=self.link.callback(|_| BlogMsg::Filter(None))>{"Show all"}</button>
+                        </p>
+                    },
+                    None => html! {},
+                } }
+                { if list.posts.is_empty() { html! { <p class="text-muted">{"Nothing here yet."}</p> } } else { html! {} } }
+                <ul class="list-unstyled blog-posts">
+                    { for list.posts.iter().map(|post| html! {
+                        <li class="blog-post">
+                            <h2><RouterAnchor<AppRoute> route=AppRoute::Post(post.slug.clone())>{ &post.title }</RouterAnchor<AppRoute>></h2>
+                            { post_meta(post, Some(&on_tag)) }
+                            <p>{ &post.summary }</p>
+                        </li>
+                    }) }
+                </ul>
+                <nav class="blog-pages">
+                    { if list.page > 1 {
+                        html! { <button type="button" class="btn btn-link" onclick=self.link.callback(|_| BlogMsg::Newer)>{"Newer posts"}</button> }
+                    } else { html! {} } }
+                    { if list.page < list.total_pages {
+                        html! { <button type="button" class="btn btn-link" onclick=self.link.callback(|_| BlogMsg::Older)>{"Older posts"}</button> }
+                    } else { html! {} } }
+                </nav>
+            </div>
+        }
+    }
+}
+
+pub struct PostView {
+    pub link: ComponentLink<Self>,
+    pub props: PostViewProps,
+
+    pub post: Option<PostDetail>,
+    pub failed: bool,
+
+    pub task: Option<FetchTask>,
+}
+
+#[derive(Clone, Properties, PartialEq)]
+pub struct PostViewProps {
+    pub slug: String,
+}
+
+pub enum PostMsg {
+    Loaded(Option<PostDetail>),
+}
+
+impl PostView {
+    fn fetch(&mut self) {
+        let url = Endpoint::Posts.item(&self.props.slug);
+
+        self.task = fetch_json(&self.link, &url, PostMsg::Loaded);
+    }
+}
+
+impl Component for PostView {
+    type Message = PostMsg;
+    type Properties = PostViewProps;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut view = PostView {
+            link,
+            props,
+            post: None,
+            failed: false,
+            task: None,
+        };
+
+        view.fetch();
+        view
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            PostMsg::Loaded(post) => {
+                self.task = None;
+                self.failed = post.is_none();
+                self.post = post;
+            }
+        }
+
+        true
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        if self.props.neq_assign(props) {
+            self.post = None;
+            self.failed = false;
+            self.fetch();
+            return true;
+        }
+
+        false
+    }
+
+    fn view(&self) -> Html {
+        let post = match self.post {
+            Some(ref post) => post,
+            None if self.failed => {
+                return html! {
+                    <div class="container blog">
+                        <h1>{"Post not found"}</h1>
+                        <p><RouterAnchor<AppRoute> route=AppRoute::Blog>{"All posts"}</RouterAnchor<AppRoute>></p>
+                    </div>
+                }
+            }
+            None => return html! { <div class="container blog"><p class="text-muted">{"Loading…"}</p></div> },
+        };
+
+        // the server renders the Markdown, so it is inserted as-is
+        let content = yew::utils::document().create_element("div").unwrap();
+        content.set_class_name("blog-content");
+        content.set_inner_html(&post.html);
+
+        html! {
+            <article class="container blog-post">
+                <h1>{ &post.summary.title }</h1>
+                { post_meta(&post.summary, None) }
+                { VNode::VRef(content.into()) }
+                <p><RouterAnchor<AppRoute> route=AppRoute::Blog>{"All posts"}</RouterAnchor<AppRoute>></p>
+            </article>
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+
+use nova_common::api::{ContactForm, Outcome, Token};
+use nova_common::Endpoint;
+use yew::format::Json;
+use yew::prelude::*;
+use yew::services::fetch::{FetchService, FetchTask, Request, Response};
+
+use super::fetch_json;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Name,
+    Email,
+    Subject,
+    Message,
+    Website,
+}
+
+pub struct ContactView {
+    pub link: ComponentLink<Self>,
+
+    pub form: ContactForm,
+    pub errors: BTreeMap<String, String>,
+    pub sending: bool,
+    pub sent: bool,
+
+    pub task: Option<FetchTask>,
+}
+
+pub enum ContactMsg {
+    Token(Option<Token>),
+    Input(Field, String),
+    Submit,
+    Done(Option<Outcome>),
+}
+
+impl ContactView {
+    fn fetch_token(&mut self) {
+        self.task = fetch_json(&self.link, &Endpoint::Contact.path(), ContactMsg::Token);
+    }
+
+    fn submit(&mut self) {
+        let request = Request::post(Endpoint::Contact.path()).header("Content-Type", "application/json").body(Json(&self.form));
+
+        let callback = self.link.callback(|response: Response<Json<Result<Outcome, anyhow::Error>>>| {
+            let Json(outcome) = response.into_body();
+
+            ContactMsg::Done(outcome.ok())
+        });
+
+        self.task = request.ok().and_then(|request| FetchService::new().fetch(request, callback).ok());
+        self.sending = self.task.is_some();
+    }
+
+    fn field(&self, field: Field, id: &str, label: &str, kind: &str) -> Html {
+        let (name, value) = match field {
+            Field::Name => ("name", &self.form.name),
+            Field::Email => ("email", &self.form.email),
+            Field::Subject => ("subject", &self.form.subject),
+            Field::Message => ("message", &self.form.message),
+            Field::Website => ("website", &self.form.website),
+        };
+
+        let error = self.errors.get(name);
+        let class = if error.is_some() { "form-control is-invalid" } else { "form-control" };
+        let oninput = self.link.callback(move |e: InputData| ContactMsg::Input(field, e.value));
+
+        let input = match kind {
+            "textarea" => html! { <textarea id=id name=name class=class rows=6 value=value oninput=oninput/> },
+            kind => html! { <input id=id name=name type=kind class=class value=value oninput=oninput/> },
+        };
+
+        html! {
+            <div class="form-group">
+                <label for=id>{ label }</label>
+                { input }
+                { match error {
+                    Some(error) => html! { <div class="invalid-feedback">{ error }</div> },
+                    None => html! {},
+                } }
+            </div>
+        }
+    }
+}
+
+impl Component for ContactView {
+    type Message = ContactMsg;
+    type Properties = ();
+
+    fn create(_: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut view = ContactView {
+            link,
+            form: ContactForm::default(),
+            errors: BTreeMap::new(),
+            sending: false,
+            sent: false,
+            task: None,
+        };
+
+        view.fetch_token();
+        view
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            ContactMsg::Token(token) => {
+                self.task = None;
+
+                match token {
+                    Some(token) => self.form.token = token.token,
+                    None => {
+                        self.errors.insert("form".to_owned(), "The form could not be loaded, please reload the page.".to_owned());
+                    }
+                }
+            }
+            ContactMsg::Input(field, value) => {
+                let target = match field {
+                    Field::Name => &mut self.form.name,
+                    Field::Email => &mut self.form.email,
+                    Field::Subject => &mut self.form.subject,
+                    Field::Message => &mut self.form.message,
+                    Field::Website => &mut self.form.website,
+                };
+
+                *target = value;
+                return false;
+            }
+            ContactMsg::Submit if self.sending => return false,
+            ContactMsg::Submit => self.submit(),
+            ContactMsg::Done(outcome) => {
+                self.task = None;
+                self.sending = false;
+
+                let outcome = outcome.unwrap_or_else(|| Outcome {
+                    errors: std::iter::once(("form".to_owned(), "The message could not be sent, please try again later.".to_owned())).collect(),
+                    ..Outcome::default()
+                });
+
+                self.sent = outcome.status.as_deref() == Some("sent");
+                self.errors = outcome.errors;
+
+                // an expired token is replaced so that sending again works
+                if self.errors.contains_key("form") && !self.sent {
+                    self.fetch_token();
+                }
+            }
+        }
+
+        true
+    }
+
+    fn change(&mut self, _: Self::Properties) -> ShouldRender {
+        false
+    }
+
+    fn view(&self) -> Html {
+        if self.sent {
+            return html! {
+                <div class="container contact">
+                    <h1>{"Contact"}</h1>
+                    <p class="alert alert-success">{"Thanks, your message is on its way."}</p>
+                </div>
+            };
+        }
+
+        let onsubmit = self.link.callback(|e: FocusEvent| {
+            e.prevent_default();
+            ContactMsg::Submit
+        });
+
+        html! {
+            <div class="container contact">
+                <h1>{"Contact"}</h1>
+                <form class="contact-form" novalidate=true onsubmit=onsubmit>
+                    { match self.errors.get("form") {
+                        Some(error) => html! { <div class="alert alert-danger">{ error }</div> },
+                        None => html! {},
+                    } }
+                    { self.field(Field::Name, "contact-name", "Name", "text") }
+                    { self.field(Field::Email, "contact-email", "Email", "email") }
+                    { self.field(Field::Subject, "contact-subject", "Subject (optional)", "text") }
+                    { self.field(Field::Message, "contact-message", "Message", "textarea") }
+                    <div class="contact-hp" aria-hidden="true">
+                        <label>{"Leave this empty"}
+                            <input type="text" name="website" tabindex="-1" autocomplete="off" value=&self.form.website
+                                oninput=self.link.callback(|e: InputData| ContactMsg::Input(Field::Website, e.value))/>
+                        </label>
+                    </div>
+                    <button type="submit" class="btn btn-primary" disabled=self.sending>{ if self.sending { "Sending…" } else { "Send" } }</button>
+                </form>
+            </div>
+        }
+    }
+}
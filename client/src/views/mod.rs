@@ -1,11 +1,16 @@
+use nova_common::api::Hit;
+use nova_common::{Endpoint, Page};
 use wasm_bindgen::prelude::*;
+use yew::format::{Json, Nothing};
 use yew::prelude::*;
+use yew::services::fetch::{FetchService, FetchTask, Request, Response};
 use yew_router::agent::{RouteAgentBridge, RouteRequest};
 use yew_router::prelude::*;
-use yew_router::switch::{AllowMissing, Permissive};
 use yewtil::NeqAssign;
 
 pub mod about;
+pub mod blog;
+pub mod contact;
 pub mod index;
 pub mod portfolio;
 
@@ -15,11 +20,16 @@ pub struct MainView {
 
     pub route: AppRoute,
     pub router_agent: RouteAgentBridge,
+
+    /// Path of the last page view reported to `/api/hit`, `None` until the first one
+    pub reported: Option<String>,
+    pub hit_task: Option<FetchTask>,
 }
 
 #[derive(Clone)]
 pub enum MainMsg {
     Navigate(Route),
+    Reported,
 }
 
 #[derive(Clone, Properties, Serialize, Deserialize, PartialEq)]
@@ -31,28 +41,92 @@ use crate::components::bootstrap::{
     progress::{Progress, ProgressBar},
 };
 
-#[derive(Clone, Switch, PartialEq)]
+/// The client's side of [`Page`], which the server routes and renders the same way
+#[derive(Clone, Debug, PartialEq)]
 pub enum AppRoute {
-    #[to = "/#"]
     Index,
-
-    #[to = "/portfolio"]
+    Project(String),
     Portfolio,
-
-    #[to = "/about"]
     About,
+    Post(String),
+    Blog,
+    Contact,
+
+    /// The path that was not found, or empty for a generic not-found page
+    PageNotFound(String),
+}
 
-    #[to = "/not-found"]
-    PageNotFound(Permissive<String>),
+impl From<Page> for AppRoute {
+    fn from(page: Page) -> AppRoute {
+        match page {
+            Page::Index => AppRoute::Index,
+            Page::Portfolio => AppRoute::Portfolio,
+            Page::Project(slug) => AppRoute::Project(slug),
+            Page::About => AppRoute::About,
+            Page::Blog => AppRoute::Blog,
+            Page::Post(slug) => AppRoute::Post(slug),
+            Page::Contact => AppRoute::Contact,
+            Page::NotFound(path) => AppRoute::PageNotFound(path),
+        }
+    }
 }
 
-fn redirect(route: Route) -> AppRoute {
-    match route.as_str() {
-        "" | "/" | "/#" => AppRoute::Index,
-        _ => AppRoute::PageNotFound(Permissive(Some(route.route))),
+impl From<AppRoute> for Page {
+    fn from(route: AppRoute) -> Page {
+        match route {
+            AppRoute::Index => Page::Index,
+            AppRoute::Portfolio => Page::Portfolio,
+            AppRoute::Project(slug) => Page::Project(slug),
+            AppRoute::About => Page::About,
+            AppRoute::Blog => Page::Blog,
+            AppRoute::Post(slug) => Page::Post(slug),
+            AppRoute::Contact => Page::Contact,
+            AppRoute::PageNotFound(path) => Page::NotFound(path),
+        }
     }
 }
 
+/// Matches and builds paths with the shared route table, so links always point where the server expects them
+impl Switch for AppRoute {
+    fn from_route_part<STATE>(part: String, state: Option<STATE>) -> (Option<Self>, Option<STATE>) {
+        (Some(Page::from_route(&part).into()), state)
+    }
+
+    fn build_route_section<STATE>(self, route: &mut String) -> Option<STATE> {
+        route.push_str(&Page::from(self).path());
+        None
+    }
+}
+
+/// GETs `url` and hands the parsed JSON to `msg`, or `None` if the request failed
+pub fn fetch_json<T, M>(link: &ComponentLink<M>, url: &str, msg: impl Fn(Option<T>) -> M::Message + 'static) -> Option<FetchTask>
+where
+    T: serde::de::DeserializeOwned + 'static,
+    M: Component,
+{
+    let request = Request::get(url).body(Nothing).ok()?;
+
+    let callback = link.callback(move |response: Response<Json<Result<T, anyhow::Error>>>| {
+        let (meta, Json(data)) = response.into_parts();
+
+        msg(if meta.status.is_success() { data.ok() } else { None })
+    });
+
+    FetchService::new().fetch(request, callback).ok()
+}
+
+/// Reports a page view to the server's own analytics, which count views per day without identifying anyone
+fn report_hit(link: &ComponentLink<MainView>, route: &str, referrer: Option<String>) -> Option<FetchTask> {
+    let hit = Hit {
+        route: route.to_owned(),
+        referrer,
+    };
+
+    let request = Request::post(Endpoint::Hit.path()).header("Content-Type", "application/json").body(Json(&hit)).ok()?;
+
+    FetchService::new().fetch(request, link.callback(|_: Response<Nothing>| MainMsg::Reported)).ok()
+}
+
 impl Component for MainView {
     type Message = MainMsg;
     type Properties = Properties;
@@ -67,13 +141,32 @@ impl Component for MainView {
             props,
             route: AppRoute::Index,
             router_agent,
+            reported: None,
+            hit_task: None,
         }
     }
 
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
         match msg {
             MainMsg::Navigate(route) => {
-                self.route = AppRoute::switch(route.clone()).unwrap_or_else(|| redirect(route));
+                let path = route.as_str().split(|c| c == '?' || c == '#').next().unwrap_or_default().to_owned();
+
+                if self.reported.as_ref() != Some(&path) {
+                    // the referrer only says where the visit came from, later navigations happen within the site
+                    let referrer = match self.reported {
+                        None => yew::utils::document().referrer(),
+                        Some(_) => String::new(),
+                    };
+
+                    self.hit_task = report_hit(&self.link, &path, Some(referrer).filter(|referrer| !referrer.is_empty()));
+                    self.reported = Some(path);
+                }
+
+                self.route = Page::from_route(route.as_str()).into();
+            }
+            MainMsg::Reported => {
+                self.hit_task = None;
+                return false;
             }
         }
 
@@ -107,6 +200,16 @@ impl Component for MainView {
                             classes={navlink(AppRoute::Portfolio)}>
                             {"Portfolio"}
                         </RouterAnchor<AppRoute>></NavItem>
+                        <NavItem><RouterAnchor<AppRoute>
+                            route=AppRoute::Blog
+                            classes={navlink(AppRoute::Blog)}>
+                            {"Blog"}
+                        </RouterAnchor<AppRoute>></NavItem>
+                        <NavItem><RouterAnchor<AppRoute>
+                            route=AppRoute::Contact
+                            classes={navlink(AppRoute::Contact)}>
+                            {"Contact"}
+                        </RouterAnchor<AppRoute>></NavItem>
                     </Nav>
                     <hr/>
                     <span class="navbar-text">
@@ -119,11 +222,15 @@ impl Component for MainView {
 
                 <Router<AppRoute>
                     render = Router::render(|switch: AppRoute| {
-                        use self::{index::IndexView, portfolio::PortfolioView, about::AboutView};
+                        use self::{index::IndexView, portfolio::{PortfolioView, ProjectView}, about::AboutView, blog::{BlogView, PostView}, contact::ContactView};
 
                         match switch {
-                            AppRoute::PageNotFound(Permissive(None)) => return html!{"Page not found"},
-                            AppRoute::PageNotFound(Permissive(Some(missed_route))) => return html!{format!("Page '{}' not found", missed_route)},
+                            AppRoute::PageNotFound(path) if path.is_empty() => return html!{"Page not found"},
+                            AppRoute::PageNotFound(missed_route) => return html!{format!("Page '{}' not found", missed_route)},
+                            AppRoute::Blog => return html!{ <BlogView/> },
+                            AppRoute::Post(slug) => return html!{ <PostView slug=slug/> },
+                            AppRoute::Project(slug) => return html!{ <ProjectView slug=slug/> },
+                            AppRoute::Contact => return html!{ <ContactView/> },
                             _ => {}
                         }
 
@@ -135,7 +242,6 @@ impl Component for MainView {
                             </>
                         }
                     })
-                    redirect = Router::redirect(redirect)
                 />
             </>
         }
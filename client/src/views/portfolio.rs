@@ -1,41 +1,255 @@
+use nova_common::api::{Project, ProjectList};
+use nova_common::chrono::SecondsFormat;
+use nova_common::Endpoint;
 use yew::prelude::*;
-use yewtil::{NeqAssign, Pure, PureComponent};
+use yew::services::fetch::FetchTask;
+use yew_router::prelude::*;
+use yewtil::NeqAssign;
+
+use super::{fetch_json, AppRoute};
 
 pub mod doom_fire;
 
+/// Date and tags line of a card and the detail page
+fn project_meta(project: &Project) -> Html {
+    html! {
+        <p class="text-muted">
+            <time datetime={project.date.to_rfc3339_opts(SecondsFormat::AutoSi, true)}>{ project.date.format("%Y-%m-%d").to_string() }</time>
+            { for project.tags.iter().map(|tag| html! { <>{" "}<span class="badge badge-secondary">{ tag }</span></> }) }
+        </p>
+    }
+}
+
+/// The interactive demo a project embeds by its `demo` id
+fn demo(id: &str) -> Html {
+    use doom_fire::DoomFire;
+
+    match id {
+        "doom-fire" => html! { <DoomFire width=600, height=400 running=true shared=true/> },
+        _ => html! {},
+    }
+}
+
+pub struct PortfolioView {
+    pub link: ComponentLink<Self>,
+    pub props: PortfolioViewProps,
+
+    pub list: Option<ProjectList>,
+    pub failed: bool,
+    pub tag: Option<String>,
+
+    pub task: Option<FetchTask>,
+}
+
 #[derive(Clone, Properties, PartialEq)]
 pub struct PortfolioViewProps {
     #[prop_or(true)]
     pub running: bool,
 }
 
-impl PureComponent for PortfolioViewProps {
-    fn render(&self) -> Html {
-        use crate::components::bootstrap::{
-            grid::{Col, Container, Row},
-            navbar::{Nav, NavItem, Navbar, NavbarBrand, NavbarCollapse, NavbarCollapseToggler},
-            progress::{Progress, ProgressBar},
-        };
+pub enum PortfolioMsg {
+    Loaded(Option<ProjectList>),
+    Filter(Option<String>),
+}
 
-        use doom_fire::DoomFire;
+impl Component for PortfolioView {
+    type Message = PortfolioMsg;
+    type Properties = PortfolioViewProps;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let task = fetch_json(&link, &Endpoint::Projects.path(), PortfolioMsg::Loaded);
+
+        PortfolioView {
+            link,
+            props,
+            list: None,
+            failed: false,
+            tag: None,
+            task,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            PortfolioMsg::Loaded(list) => {
+                self.task = None;
+                self.failed = list.is_none();
+                self.list = list;
+                true
+            }
+            // filtering happens here rather than through `?tag=`, the whole list is already loaded
+            PortfolioMsg::Filter(tag) => self.tag.neq_assign(tag),
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.props.neq_assign(props)
+    }
+
+    fn view(&self) -> Html {
+        let content = match self.list {
+            Some(ref list) => self.grid(list),
+            None if self.failed => html! { <p class="text-muted">{"Projects could not be loaded."}</p> },
+            None => html! { <p class="text-muted">{"Loading…"}</p> },
+        };
 
         html! {
-            <div class={if self.running {""} else {"hidden"} }>
-                <Container size="fluid">
-                    <Row>
-                        <Col>
-                            <div>{"Hello, Portfolio!"}</div>
-                        </Col>
-                    </Row>
-                    <Row>
-                        <Col>
-                            <DoomFire width=600, height=400 running={self.running}/>
-                        </Col>
-                    </Row>
-                </Container>
+            <div class={if self.props.running {""} else {"hidden"} }>
+                <div class="container portfolio">
+                    <h1>{"Portfolio"}</h1>
+                    { content }
+                </div>
             </div>
         }
     }
 }
 
-pub type PortfolioView = Pure<PortfolioViewProps>;
+impl PortfolioView {
+    fn filter_button(&self, label: &str, tag: Option<String>) -> Html {
+        let class = if self.tag == tag { "btn btn-sm btn-secondary" } else { "btn btn-sm btn-outline-secondary" };
+
+        html! {
+            <button type="button" class=class onclick=self.link.callback(move |_| PortfolioMsg::Filter(tag.clone()))>{ label }</button>
+        }
+    }
+
+    fn grid(&self, list: &ProjectList) -> Html {
+        let tag = self.tag.as_ref();
+        let projects = list.projects.iter().filter(|project| tag.map_or(true, |tag| project.tags.contains(tag)));
+
+        html! {
+            <>
+                { if list.tags.is_empty() { html! {} } else { html! {
+                    <nav class="portfolio-filter">
+                        { self.filter_button("All", None) }
+                        { for list.tags.iter().map(|tag| html! { <>{" "}{ self.filter_button(tag, Some(tag.clone())) }</> }) }
+                    </nav>
+                } } }
+                { if list.projects.is_empty() { html! { <p class="text-muted">{"Nothing here yet."}</p> } } else { html! {} } }
+                <div class="row portfolio-grid">
+                    { for projects.map(|project| html! {
+                        <div class="col-sm-6 col-lg-4 mb-4">
+                            <div class="card h-100 project-card">
+                                { match project.thumbnail {
+                                    Some(ref src) => html! { <img class="card-img-top" src=src alt=&project.title/> },
+                                    None => html! {},
+                                } }
+                                <div class="card-body">
+                                    <h5 class="card-title">
+                                        <RouterAnchor<AppRoute> route=AppRoute::Project(project.slug.clone())>{ &project.title }</RouterAnchor<AppRoute>>
+                                    </h5>
+                                    <p class="card-text">{ &project.description }</p>
+                                    { project_meta(project) }
+                                </div>
+                            </div>
+                        </div>
+                    }) }
+                </div>
+            </>
+        }
+    }
+}
+
+pub struct ProjectView {
+    pub link: ComponentLink<Self>,
+    pub props: ProjectViewProps,
+
+    pub project: Option<Project>,
+    pub failed: bool,
+
+    pub task: Option<FetchTask>,
+}
+
+#[derive(Clone, Properties, PartialEq)]
+pub struct ProjectViewProps {
+    pub slug: String,
+}
+
+pub enum ProjectMsg {
+    Loaded(Option<Project>),
+}
+
+impl ProjectView {
+    fn fetch(&mut self) {
+        let url = Endpoint::Projects.item(&self.props.slug);
+
+        self.task = fetch_json(&self.link, &url, ProjectMsg::Loaded);
+    }
+}
+
+impl Component for ProjectView {
+    type Message = ProjectMsg;
+    type Properties = ProjectViewProps;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut view = ProjectView {
+            link,
+            props,
+            project: None,
+            failed: false,
+            task: None,
+        };
+
+        view.fetch();
+        view
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            ProjectMsg::Loaded(project) => {
+                self.task = None;
+                self.failed = project.is_none();
+                self.project = project;
+            }
+        }
+
+        true
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        if self.props.neq_assign(props) {
+            self.project = None;
+            self.failed = false;
+            self.fetch();
+            return true;
+        }
+
+        false
+    }
+
+    fn view(&self) -> Html {
+        let project = match self.project {
+            Some(ref project) => project,
+            None if self.failed => {
+                return html! {
+                    <div class="container project">
+                        <h1>{"Project not found"}</h1>
+                        <p><RouterAnchor<AppRoute> route=AppRoute::Portfolio>{"All projects"}</RouterAnchor<AppRoute>></p>
+                    </div>
+                }
+            }
+            None => return html! { <div class="container project"><p class="text-muted">{"Loading…"}</p></div> },
+        };
+
+        let media = match (project.demo.as_deref(), project.thumbnail.as_ref()) {
+            (Some(id), _) => html! { <div class="project-demo">{ demo(id) }</div> },
+            (None, Some(src)) => html! { <img class="img-fluid project-thumbnail" src=src alt=&project.title/> },
+            (None, None) => html! {},
+        };
+
+        html! {
+            <article class="container project">
+                <h1>{ &project.title }</h1>
+                { project_meta(project) }
+                { media }
+                <p>{ &project.description }</p>
+                <ul class="list-inline project-links">
+                    { for project.links.iter().map(|link| html! {
+                        <li class="list-inline-item"><a href=&link.url target="_blank" rel="noopener">{ &link.label }</a></li>
+                    }) }
+                </ul>
+                <p><RouterAnchor<AppRoute> route=AppRoute::Portfolio>{"All projects"}</RouterAnchor<AppRoute>></p>
+            </article>
+        }
+    }
+}
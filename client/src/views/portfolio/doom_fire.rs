@@ -2,10 +2,15 @@ use std::time::Duration;
 
 use wasm_bindgen::prelude::*;
 
+use nova_common::api::{FireEvent, Stroke};
+use nova_common::Socket;
+
+use yew::format::Json;
 use yew::prelude::*;
 use yew::services::{
     interval::{IntervalService, IntervalTask},
     render::{RenderService, RenderTask},
+    websocket::{WebSocketService, WebSocketStatus, WebSocketTask},
 };
 use yewtil::{NeqAssign, Pure, PureComponent};
 
@@ -36,8 +41,18 @@ pub struct DoomFire {
     pub is_drawing: bool,
     pub pos: Vector2,
     pub last_pos: Vector2,
+
+    /// Connection to `/ws/fire` when shared, dropped if it closes
+    pub socket: Option<WebSocketTask>,
+    pub connected: bool,
+
+    /// Strokes from the rest of the room, splatted on the next tick
+    pub remote: Vec<Stroke>,
 }
 
+/// Remote strokes kept between ticks, any more are dropped
+const MAX_REMOTE: usize = 256;
+
 #[derive(Clone, Copy, Properties, PartialEq)]
 pub struct DoomFireProps {
     pub width: usize,
@@ -45,6 +60,10 @@ pub struct DoomFireProps {
 
     #[prop_or(true)]
     pub running: bool,
+
+    /// Draw together with everyone else viewing a shared fire
+    #[prop_or_default]
+    pub shared: bool,
 }
 
 pub enum DoomFireMsg {
@@ -56,6 +75,8 @@ pub enum DoomFireMsg {
     TouchMove(web_sys::TouchEvent),
     Tick,
     Draw,
+    Remote(Option<FireEvent>),
+    Socket(WebSocketStatus),
 }
 
 #[wasm_bindgen]
@@ -64,6 +85,57 @@ extern "C" {
     fn now() -> f64;
 }
 
+/// Opens the `/ws/fire` socket on the page's own host
+fn connect(link: &ComponentLink<DoomFire>) -> Option<WebSocketTask> {
+    let location = yew::utils::window().location();
+    let scheme = if location.protocol().ok()? == "https:" { "wss" } else { "ws" };
+    let url = format!("{}://{}{}", scheme, location.host().ok()?, Socket::Fire.path());
+
+    let callback = link.callback(|Json(event): Json<Result<FireEvent, anyhow::Error>>| DoomFireMsg::Remote(event.ok()));
+
+    WebSocketService::new().connect(&url, callback, link.callback(DoomFireMsg::Socket)).ok()
+}
+
+impl DoomFire {
+    /// Splats fire along the segment from `a` to `b` with a simple 2D line SDF
+    fn splat(&mut self, a: Vector2, b: Vector2) {
+        const FALLOFF: f32 = 1.0 / 8.0;
+        const SIZE: f32 = 15.0;
+        let norm = 36f32.powf(FALLOFF);
+
+        let ba = b - a;
+        let ba_dot_ba = ba.dot(ba);
+
+        // only the pixels within SIZE of the segment can catch fire
+        let x_range = (a.x.min(b.x) - SIZE).max(0.0) as usize..((a.x.max(b.x) + SIZE).ceil().max(0.0) as usize).min(self.props.width);
+        let y_range = (a.y.min(b.y) - SIZE).max(0.0) as usize..((a.y.max(b.y) + SIZE).ceil().max(0.0) as usize).min(self.props.height);
+
+        for y in y_range {
+            for x in x_range.clone() {
+                let pa = Vector2::new(x as f32, y as f32) - a;
+                let h = (pa.dot(ba) / ba_dot_ba).min(1.0).max(0.0);
+                let d = (pa - ba * h).norm();
+
+                if d < SIZE {
+                    let pixel = unsafe { self.pixels.get_unchecked_mut(y * self.props.width + x) };
+
+                    let fire = ((36.0 - d).powf(FALLOFF) / norm * 35.0) as usize + 1;
+                    *pixel = fire.max(*pixel).min(36);
+                }
+            }
+        }
+    }
+
+    /// A point on the canvas as fractions of its size, as strokes are shared
+    fn to_shared(&self, pos: Vector2) -> [f32; 2] {
+        [(pos.x / self.props.width as f32).max(0.0).min(1.0), (pos.y / self.props.height as f32).max(0.0).min(1.0)]
+    }
+
+    fn from_shared(&self, pos: [f32; 2]) -> Vector2 {
+        Vector2::new(pos[0] * self.props.width as f32, pos[1] * self.props.height as f32)
+    }
+}
+
 impl Component for DoomFire {
     type Message = DoomFireMsg;
     type Properties = DoomFireProps;
@@ -78,6 +150,7 @@ impl Component for DoomFire {
 
         let tick = IntervalService::new().spawn(Duration::from_secs_f32(1.0 / 30.0), link.callback(|_| DoomFireMsg::Tick));
         let draw = RenderService::new().request_animation_frame(link.callback(|_| DoomFireMsg::Draw));
+        let socket = if props.shared { connect(&link) } else { None };
 
         DoomFire {
             link,
@@ -90,11 +163,15 @@ impl Component for DoomFire {
             is_drawing: false,
             pos: Vector2::ZERO,
             last_pos: Vector2::ZERO,
+            socket,
+            connected: false,
+            remote: Vec::new(),
         }
     }
 
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
-        if !self.props.running {
+        // the socket stays up while paused, so keep track of it
+        if !self.props.running && !matches!(msg, DoomFireMsg::Socket(_)) {
             return false;
         }
 
@@ -145,35 +222,28 @@ impl Component for DoomFire {
             }
             DoomFireMsg::Tick => unsafe {
                 if self.is_drawing {
-                    const FALLOFF: f32 = 1.0 / 8.0;
-                    const SIZE: f32 = 15.0;
-                    let norm = 36f32.powf(FALLOFF);
+                    let (a, b) = (self.last_pos, self.pos);
+                    self.splat(a, b);
 
-                    // Use a simple 2D line SDF to splat fire
-                    let a = self.last_pos;
-                    let b = self.pos;
+                    if self.connected {
+                        let stroke = Stroke {
+                            last_pos: self.to_shared(a),
+                            pos: self.to_shared(b),
+                        };
 
-                    let ba = b - a;
-                    let ba_dot_ba = ba.dot(ba);
-
-                    for y in 0..self.props.height {
-                        for x in 0..self.props.width {
-                            let pa = Vector2::new(x as f32, y as f32) - a;
-                            let h = (pa.dot(ba) / ba_dot_ba).min(1.0).max(0.0);
-                            let d = (pa - ba * h).norm();
-
-                            if d < SIZE {
-                                let pixel = self.pixels.get_unchecked_mut(y * self.props.width + x);
-
-                                let fire = ((36.0 - d).powf(FALLOFF) / norm * 35.0) as usize + 1;
-                                *pixel = fire.max(*pixel).min(36);
-                            }
+                        if let Some(ref mut socket) = self.socket {
+                            socket.send(Json(&stroke));
                         }
                     }
 
                     self.last_pos = self.pos;
                 }
 
+                for stroke in std::mem::take(&mut self.remote) {
+                    let (a, b) = (self.from_shared(stroke.last_pos), self.from_shared(stroke.pos));
+                    self.splat(a, b);
+                }
+
                 for x in 0..self.props.width {
                     for y in 1..self.props.height {
                         let idx = y * self.props.width + x;
@@ -233,6 +303,17 @@ impl Component for DoomFire {
                     .unwrap();
                 }
             },
+            DoomFireMsg::Remote(Some(FireEvent::Stroke(stroke))) => {
+                if stroke.is_valid() && self.remote.len() < MAX_REMOTE {
+                    self.remote.push(stroke);
+                }
+            }
+            DoomFireMsg::Socket(WebSocketStatus::Opened) => self.connected = true,
+            DoomFireMsg::Socket(_) => {
+                // closed or failed, a full room included, so carry on alone
+                self.connected = false;
+                self.socket = None;
+            }
             _ => {}
         }
 
@@ -1,3 +1,7 @@
+// the error stays hidden unless loading fails, visitors without JavaScript get the page the server rendered
 import("../bin/app/pkg")
-  .catch(e => console.error("Error importing `index.js`:", e))
-  .then((app) => app.run_app());
\ No newline at end of file
+  .then((app) => app.run_app())
+  .catch(e => {
+    console.error("Error importing `index.js`:", e);
+    document.getElementById("load-error").hidden = false;
+  });
\ No newline at end of file
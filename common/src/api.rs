@@ -0,0 +1,213 @@
+//! Payloads of the JSON API, by [`Endpoint`](crate::Endpoint), and the messages of each [`Socket`](crate::Socket)
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Query of `GET /api/posts`, every field optional
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PostQuery {
+    /// 1-based
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+
+    /// Clamped by the server to its configured maximum
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_page: Option<usize>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostSummary {
+    pub slug: String,
+    pub title: String,
+    pub date: DateTime<Utc>,
+    pub tags: Vec<String>,
+    pub summary: String,
+}
+
+/// Body of `GET /api/posts/{slug}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostDetail {
+    #[serde(flatten)]
+    pub summary: PostSummary,
+    pub html: String,
+}
+
+/// Body of `GET /api/posts`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostList {
+    pub posts: Vec<PostSummary>,
+    pub page: usize,
+    pub per_page: usize,
+    pub total: usize,
+    pub total_pages: usize,
+
+    /// The tag the list was filtered by
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+/// Query of `GET /api/projects`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProjectQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+/// Also the format of links in the server's project manifest, hence no unknown fields
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectLink {
+    pub label: String,
+    pub url: String,
+}
+
+/// Body of `GET /api/projects/{slug}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Project {
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub date: DateTime<Utc>,
+    pub tags: Vec<String>,
+    pub links: Vec<ProjectLink>,
+
+    /// Image path below the site root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+
+    /// Name of a demo the client can run in place, such as `doom-fire`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub demo: Option<String>,
+}
+
+/// Body of `GET /api/projects`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectList {
+    pub projects: Vec<Project>,
+
+    /// Every tag in use, for the filter
+    pub tags: Vec<String>,
+
+    /// The tag the list was filtered by
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+/// Body of `GET /api/contact`, to be sent back with the form
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Token {
+    pub token: String,
+}
+
+/// Body of `POST /api/contact`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContactForm {
+    pub name: String,
+    pub email: String,
+
+    #[serde(default)]
+    pub subject: String,
+    pub message: String,
+
+    /// Honeypot, hidden from people and left empty by them
+    #[serde(default)]
+    pub website: String,
+
+    pub token: String,
+}
+
+/// Body of every `POST /api/contact` response
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Outcome {
+    /// `sent` once the message is on its way
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+
+    /// Messages by field name, `form` for those not tied to a field
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<String, String>,
+}
+
+/// Body of `POST /api/hit`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hit {
+    /// Path the client navigated to
+    pub route: String,
+
+    /// `document.referrer`, only sent for the first route of a visit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referrer: Option<String>,
+}
+
+/// Query of `GET /api/stats`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StatsQuery {
+    /// Number of days up to and including today
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub days: Option<u32>,
+}
+
+/// Counts for one day, or summed over a range
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Counts {
+    pub routes: BTreeMap<String, u64>,
+    pub referrers: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DayCounts {
+    pub date: NaiveDate,
+
+    #[serde(flatten)]
+    pub counts: Counts,
+}
+
+/// Body of `GET /api/stats`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub total: Counts,
+    pub days: Vec<DayCounts>,
+}
+
+/// Query of `/ws/fire`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FireQuery {
+    /// Room to join, a shared default when `None`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub room: Option<String>,
+}
+
+/// One tick of drawing on the fire, from `last_pos` to `pos`
+///
+/// Coordinates are fractions of the canvas width and height, so canvases of different sizes can share a room.
+/// Sent to `/ws/fire` by the client that drew it and relayed to everyone else in the room.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Stroke {
+    pub last_pos: [f32; 2],
+    pub pos: [f32; 2],
+}
+
+impl Stroke {
+    /// Whether both ends lie on the canvas
+    pub fn is_valid(&self) -> bool {
+        self.last_pos.iter().chain(&self.pos).all(|c| (0.0..=1.0).contains(c))
+    }
+}
+
+/// Message from `/ws/fire` to the client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum FireEvent {
+    /// Someone else in the room drew
+    Stroke(Stroke),
+
+    /// How many are in the room, including the receiver, sent on joining and whenever it changes
+    Peers { count: usize },
+}
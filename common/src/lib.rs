@@ -0,0 +1,11 @@
+//! Types shared by the server and the client
+//!
+//! [`routes`] is the table of client pages, API endpoints and sockets, [`api`] the payloads exchanged with the endpoints.
+//! Both crates compile against them, so changing a route or a payload on one side without the other fails to build.
+
+pub mod api;
+pub mod routes;
+
+pub use chrono;
+
+pub use routes::{Endpoint, Page, Socket};
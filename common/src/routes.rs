@@ -0,0 +1,216 @@
+//! The route table: pages the client routes to, and the API endpoints it fetches from
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::Serialize;
+
+/// What `encodeURIComponent` leaves alone
+const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'!')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')');
+
+/// A page of the site, routed to by the client's `AppRoute` and rendered by the server
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Page {
+    Index,
+    Portfolio,
+    Project(String),
+    About,
+    Blog,
+    Post(String),
+    Contact,
+
+    /// The path that was not found, or empty for a generic not-found page
+    NotFound(String),
+}
+
+impl Page {
+    /// The pages that exist whatever the content
+    pub fn fixed() -> [Page; 5] {
+        [Page::Index, Page::Portfolio, Page::About, Page::Blog, Page::Contact]
+    }
+
+    /// Recognizes a request path, ignoring a trailing slash
+    pub fn from_path(path: &str) -> Page {
+        let trimmed = path.trim_end_matches('/');
+
+        match trimmed {
+            "" => Page::Index,
+            "/portfolio" => Page::Portfolio,
+            "/about" => Page::About,
+            "/blog" => Page::Blog,
+            "/contact" => Page::Contact,
+            _ => {
+                let slug = |prefix| trimmed.strip_prefix(prefix).filter(|slug: &&str| !slug.is_empty() && !slug.contains('/'));
+
+                match (slug("/portfolio/"), slug("/blog/")) {
+                    (Some(slug), _) => Page::Project(slug.to_owned()),
+                    (_, Some(slug)) => Page::Post(slug.to_owned()),
+                    _ => Page::NotFound(path.to_owned()),
+                }
+            }
+        }
+    }
+
+    /// Recognizes a full client route, which may carry a query string and fragment
+    pub fn from_route(route: &str) -> Page {
+        Page::from_path(route.split(['?', '#']).next().unwrap_or_default())
+    }
+
+    /// Canonical path of the page, as linked by the navbar
+    pub fn path(&self) -> String {
+        match self {
+            Page::Index => "/".to_owned(),
+            Page::Portfolio => "/portfolio".to_owned(),
+            Page::Project(slug) => format!("/portfolio/{}", slug),
+            Page::About => "/about".to_owned(),
+            Page::Blog => "/blog".to_owned(),
+            Page::Post(slug) => format!("/blog/{}", slug),
+            Page::Contact => "/contact".to_owned(),
+            Page::NotFound(path) => path.clone(),
+        }
+    }
+}
+
+/// An endpoint of the JSON API, see [`api`](crate::api) for what each one takes and returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    /// `GET` a [`PostList`](crate::api::PostList), or a [`PostDetail`](crate::api::PostDetail) by slug
+    Posts,
+
+    /// `GET` a [`ProjectList`](crate::api::ProjectList), or a [`Project`](crate::api::Project) by slug
+    Projects,
+
+    /// `GET` a [`Token`](crate::api::Token), `POST` a [`ContactForm`](crate::api::ContactForm) for an
+    /// [`Outcome`](crate::api::Outcome)
+    Contact,
+
+    /// `POST` a [`Hit`](crate::api::Hit)
+    Hit,
+
+    /// `GET` the [`Stats`](crate::api::Stats), with a bearer token
+    Stats,
+}
+
+impl Endpoint {
+    /// First segment of every endpoint's path
+    pub const PREFIX: &'static str = "api";
+
+    /// Segment after [`PREFIX`](Self::PREFIX) naming the endpoint
+    pub fn segment(self) -> &'static str {
+        match self {
+            Endpoint::Posts => "posts",
+            Endpoint::Projects => "projects",
+            Endpoint::Contact => "contact",
+            Endpoint::Hit => "hit",
+            Endpoint::Stats => "stats",
+        }
+    }
+
+    pub fn path(self) -> String {
+        format!("/{}/{}", Endpoint::PREFIX, self.segment())
+    }
+
+    /// Path of one item of the endpoint, the slug percent-encoded
+    pub fn item(self, slug: &str) -> String {
+        format!("{}/{}", self.path(), utf8_percent_encode(slug, COMPONENT))
+    }
+
+    /// Path of the endpoint with `query` as its query string, leaving out fields that are `None`
+    pub fn query<Q: Serialize>(self, query: &Q) -> String {
+        match serde_urlencoded::to_string(query) {
+            Ok(query) if !query.is_empty() => format!("{}?{}", self.path(), query),
+            _ => self.path(),
+        }
+    }
+}
+
+/// A WebSocket endpoint, exchanging JSON text messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Socket {
+    /// Relays [`Stroke`](crate::api::Stroke)s between the visitors drawing on the same fire, see
+    /// [`FireEvent`](crate::api::FireEvent). Takes a [`FireQuery`](crate::api::FireQuery).
+    Fire,
+}
+
+impl Socket {
+    /// First segment of every socket's path
+    pub const PREFIX: &'static str = "ws";
+
+    /// Segment after [`PREFIX`](Self::PREFIX) naming the socket
+    pub fn segment(self) -> &'static str {
+        match self {
+            Socket::Fire => "fire",
+        }
+    }
+
+    pub fn path(self) -> String {
+        format!("/{}/{}", Socket::PREFIX, self.segment())
+    }
+
+    /// Path of the socket with `query` as its query string, like [`Endpoint::query`]
+    pub fn query<Q: Serialize>(self, query: &Q) -> String {
+        match serde_urlencoded::to_string(query) {
+            Ok(query) if !query.is_empty() => format!("{}?{}", self.path(), query),
+            _ => self.path(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{FireQuery, PostQuery};
+
+    #[test]
+    fn paths_round_trip() {
+        let mut pages = Page::fixed().to_vec();
+        pages.extend(vec![Page::Project("doom-fire".to_owned()), Page::Post("hello".to_owned())]);
+
+        for page in pages {
+            assert_eq!(Page::from_path(&page.path()), page);
+            assert_eq!(Page::from_path(&format!("{}/", page.path())), page);
+        }
+    }
+
+    #[test]
+    fn unknown_paths() {
+        assert_eq!(Page::from_path("/blog/a/b"), Page::NotFound("/blog/a/b".to_owned()));
+        assert_eq!(Page::from_path("/portfolio/"), Page::Portfolio);
+        assert_eq!(Page::from_path("/nope"), Page::NotFound("/nope".to_owned()));
+    }
+
+    #[test]
+    fn routes_ignore_query_and_fragment() {
+        assert_eq!(Page::from_route("/#"), Page::Index);
+        assert_eq!(Page::from_route("/blog?page=2#top"), Page::Blog);
+        assert_eq!(Page::from_route("/blog/hello#comments"), Page::Post("hello".to_owned()));
+    }
+
+    #[test]
+    fn endpoint_paths() {
+        assert_eq!(Endpoint::Hit.path(), "/api/hit");
+        assert_eq!(Endpoint::Posts.item("a b/c"), "/api/posts/a%20b%2Fc");
+
+        let query = PostQuery {
+            page: Some(2),
+            per_page: None,
+            tag: Some("rust & wasm".to_owned()),
+        };
+
+        assert_eq!(Endpoint::Posts.query(&query), "/api/posts?page=2&tag=rust+%26+wasm");
+        assert_eq!(Endpoint::Posts.query(&PostQuery::default()), "/api/posts");
+    }
+
+    #[test]
+    fn socket_paths() {
+        assert_eq!(Socket::Fire.path(), "/ws/fire");
+        assert_eq!(Socket::Fire.query(&FireQuery { room: Some("doom-fire".to_owned()) }), "/ws/fire?room=doom-fire");
+    }
+}
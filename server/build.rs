@@ -0,0 +1,154 @@
+//! Embeds the client bundle into the binary when the `embed` feature is enabled, see `src/embed.rs`
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+/// Same as `compression::is_compressible`, which the build script cannot reach
+fn is_compressible(mime: &mime_guess::Mime) -> bool {
+    match (mime.type_().as_str(), mime.subtype().as_str()) {
+        ("text", _) => true,
+        ("image", "svg") => true,
+        ("application", "javascript") | ("application", "json") | ("application", "wasm") | ("application", "xml") => true,
+        ("application", subtype) => subtype.ends_with("+xml") || subtype.ends_with("+json"),
+        _ => false,
+    }
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = fs::read_dir(dir).unwrap_or_else(|e| panic!("unable to read {:?}: {}", dir, e));
+
+    for entry in entries {
+        let path = entry.unwrap_or_else(|e| panic!("unable to read {:?}: {}", dir, e)).path();
+
+        if path.is_dir() {
+            walk(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(data).expect("gzip in memory");
+    encoder.finish().expect("gzip in memory")
+}
+
+fn brotli(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let params = brotli::enc::BrotliEncoderParams {
+        quality: 11,
+        ..Default::default()
+    };
+
+    brotli::BrotliCompress(&mut &data[..], &mut out, &params).expect("brotli in memory");
+    out
+}
+
+/// A precompressed sibling from dist, or the file compressed here if that makes it smaller
+fn variant(path: &Path, extension: &str, data: &[u8], compressible: bool, compress: fn(&[u8]) -> Vec<u8>) -> Option<Vec<u8>> {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(".");
+    sibling.push(extension);
+
+    if let Ok(precompressed) = fs::read(&sibling) {
+        return Some(precompressed);
+    }
+
+    if !compressible {
+        return None;
+    }
+
+    Some(compress(data)).filter(|compressed| compressed.len() < data.len())
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=NOVA_EMBED_DIST");
+
+    if env::var_os("CARGO_FEATURE_EMBED").is_none() {
+        return;
+    }
+
+    let manifest_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").expect("set by cargo"));
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("set by cargo"));
+
+    let dist = env::var_os("NOVA_EMBED_DIST").map(PathBuf::from).unwrap_or_else(|| manifest_dir.join("../client/dist"));
+    let dist = dist.canonicalize().unwrap_or_else(|e| panic!("the embed feature needs the built client at {:?}: {}", dist, e));
+
+    if !dist.join("index.html").is_file() {
+        panic!("the embed feature needs the built client, {:?} has no index.html", dist);
+    }
+
+    println!("cargo:rerun-if-changed={}", dist.display());
+
+    let mut paths = Vec::new();
+    walk(&dist, &mut paths);
+    paths.sort();
+
+    let variants = out_dir.join("embedded");
+    fs::create_dir_all(&variants).expect("create the variants directory");
+
+    let mut table = String::from("pub static FILES: &[EmbeddedFile] = &[\n");
+
+    for (i, path) in paths.iter().enumerate() {
+        let relative = path.strip_prefix(&dist).expect("below dist");
+        let name = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+
+        // precompressed siblings are variants of their original, not files of their own
+        let is_sibling = [".gz", ".br"].iter().any(|ext| name.strip_suffix(ext).is_some_and(|original| dist.join(original).is_file()));
+
+        if is_sibling {
+            continue;
+        }
+
+        let data = fs::read(path).unwrap_or_else(|e| panic!("unable to read {:?}: {}", path, e));
+        let mime = mime_guess::from_path(path).first_or_octet_stream();
+        let hash: String = Sha256::digest(&data).iter().take(16).map(|b| format!("{:02x}", b)).collect();
+
+        let compressible = is_compressible(&mime);
+
+        let include = |extension: &str, compress: fn(&[u8]) -> Vec<u8>| match variant(path, extension, &data, compressible, compress) {
+            Some(compressed) => {
+                let file = variants.join(format!("{}.{}", i, extension));
+                fs::write(&file, compressed).unwrap_or_else(|e| panic!("unable to write {:?}: {}", file, e));
+                format!("Some(include_bytes!({:?}))", file)
+            }
+            None => "None".to_owned(),
+        };
+
+        let gzip = include("gz", gzip);
+        let brotli = include("br", brotli);
+
+        let _ = writeln!(
+            table,
+            "    EmbeddedFile {{ path: {:?}, mime: {:?}, hash: {:?}, data: include_bytes!({:?}), gzip: {}, brotli: {} }},",
+            name,
+            mime.to_string(),
+            hash,
+            path,
+            gzip,
+            brotli
+        );
+    }
+
+    table.push_str("];\n");
+
+    // reproducible builds pin the time through SOURCE_DATE_EPOCH
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let embedded_at = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default());
+
+    let _ = writeln!(table, "pub const EMBEDDED_AT: u64 = {};", embedded_at);
+
+    fs::write(out_dir.join("embedded.rs"), table).expect("write the embedded file table");
+}
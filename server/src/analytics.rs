@@ -0,0 +1,483 @@
+//! Page-view analytics without tracking
+//!
+//! The client reports each route it shows to `POST /api/hit`, along with the referring page on the first view of a
+//! visit. Only daily counts per page and per referring host are kept, in a sled database next to the server; no
+//! addresses, cookies or user agents are stored, and browsers sending `DNT: 1` or `Sec-GPC: 1` are not counted at
+//! all. The counts are available from `GET /api/stats` with the configured bearer token.
+//!
+//! Anyone can post hits, so the database is kept bounded: only pages that exist are counted, and referring hosts
+//! beyond `max_referrers` in a day are counted together as `other`.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{Duration as Days, NaiveDate, Utc};
+use nova_common::api::{Counts, DayCounts, Hit, Stats, StatsQuery};
+use nova_common::{Endpoint, Page};
+use sha2::{Digest, Sha256};
+use warp::http::{header, HeaderMap, HeaderValue, StatusCode, Uri};
+use warp::{Filter, Rejection, Reply};
+
+use crate::blog::{self, Blog};
+use crate::projects::{self, Projects};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalyticsConfig {
+    /// Off unless asked for, since anyone can post hits and reading them back needs a token
+    pub enabled: bool,
+
+    /// Directory of the sled database
+    pub database: PathBuf,
+
+    /// Bearer token for `/api/stats`, falls back to the NOVA_STATS_TOKEN environment variable.
+    /// Without one the stats endpoint is disabled.
+    pub token: Option<String>,
+
+    /// Days of counts to keep, older ones are deleted
+    pub retention_days: u32,
+
+    /// Referring hosts counted separately per day, the rest are counted as `other`
+    pub max_referrers: usize,
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        AnalyticsConfig {
+            enabled: false,
+            database: PathBuf::from("../analytics"),
+            token: None,
+            retention_days: 400,
+            max_referrers: 200,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AnalyticsError {
+    #[error("unable to open the analytics database {0:?}: {1}")]
+    Open(PathBuf, #[source] sled::Error),
+}
+
+/// Referrer the hosts beyond `max_referrers` are counted as
+const OTHER: &str = "other";
+
+/// Counter key, dates first so that a range of days is a range of keys
+fn key(date: NaiveDate, name: &str) -> Vec<u8> {
+    format!("{}\0{}", date, name).into_bytes()
+}
+
+fn parse_key(key: &[u8]) -> Option<(NaiveDate, String)> {
+    let key = std::str::from_utf8(key).ok()?;
+    let (date, name) = key.split_at(key.find('\0')?);
+
+    Some((date.parse().ok()?, name[1..].to_owned()))
+}
+
+fn increment(old: Option<&[u8]>) -> Option<Vec<u8>> {
+    let count = old.and_then(|old| <[u8; 8]>::try_from(old).ok()).map_or(0, u64::from_be_bytes);
+
+    Some((count + 1).to_be_bytes().to_vec())
+}
+
+/// Path of a page that exists, without query or fragment, so arbitrary paths cannot fill the database
+fn normalize_route(route: &str, posts: &blog::Index, projects: &projects::Index) -> Option<String> {
+    let path = route.split(['?', '#']).next().unwrap_or_default();
+
+    if !path.starts_with('/') {
+        return None;
+    }
+
+    match Page::from_path(path) {
+        Page::NotFound(_) => None,
+        Page::Post(ref slug) if posts.get(slug).is_none() => None,
+        Page::Project(ref slug) if projects.get(slug).is_none() => None,
+        page => Some(page.path()),
+    }
+}
+
+/// Host of an external referrer, lowercase and without `www.`
+fn referrer_host(referrer: &str, own_host: Option<&str>) -> Option<String> {
+    let uri = referrer.parse::<Uri>().ok()?;
+
+    if !matches!(uri.scheme_str(), Some("http") | Some("https")) {
+        return None;
+    }
+
+    let host = uri.host()?.to_ascii_lowercase();
+
+    // navigating within the site is not a referral
+    if own_host.is_some_and(|own| own.split(':').next().unwrap_or(own).eq_ignore_ascii_case(&host)) {
+        return None;
+    }
+
+    Some(host.strip_prefix("www.").map(str::to_owned).unwrap_or(host))
+}
+
+/// Whether the browser asked not to be tracked
+fn opted_out(headers: &HeaderMap) -> bool {
+    ["dnt", "sec-gpc"].iter().any(|name| headers.get(*name).is_some_and(|value| value == "1"))
+}
+
+/// Compares digests of both strings, so the time taken reveals neither how much of a guess was right nor its length
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    Sha256::digest(a).iter().zip(Sha256::digest(b).iter()).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+pub struct Analytics {
+    config: AnalyticsConfig,
+    token: Option<String>,
+    routes: sled::Tree,
+    referrers: sled::Tree,
+
+    /// Held while adding a referrer, so concurrent hits cannot go over `max_referrers`
+    new_referrer: Mutex<()>,
+
+    blog: Arc<Blog>,
+    projects: Arc<Projects>,
+}
+
+impl Analytics {
+    pub fn new(config: AnalyticsConfig, blog: Arc<Blog>, projects: Arc<Projects>) -> Result<Analytics, AnalyticsError> {
+        let db = sled::open(&config.database).map_err(|e| AnalyticsError::Open(config.database.clone(), e))?;
+
+        Analytics::with_db(config, db, blog, projects)
+    }
+
+    fn with_db(config: AnalyticsConfig, db: sled::Db, blog: Arc<Blog>, projects: Arc<Projects>) -> Result<Analytics, AnalyticsError> {
+        let open = |e| AnalyticsError::Open(config.database.clone(), e);
+
+        let routes = db.open_tree("routes").map_err(open)?;
+        let referrers = db.open_tree("referrers").map_err(open)?;
+
+        let token = config.token.clone().filter(|token| !token.is_empty());
+
+        match token {
+            Some(_) => log::info!("counting page views in {:?}", config.database),
+            None => log::warn!("counting page views in {:?}, but /api/stats is disabled without a token", config.database),
+        }
+
+        Ok(Analytics {
+            config,
+            token,
+            routes,
+            referrers,
+            new_referrer: Mutex::new(()),
+            blog,
+            projects,
+        })
+    }
+
+    /// Counts a view of `route` on `date`. Called from a blocking thread.
+    pub fn record(&self, date: NaiveDate, route: &str, referrer: Option<&str>) -> sled::Result<()> {
+        self.routes.update_and_fetch(key(date, route), increment)?;
+
+        if let Some(referrer) = referrer {
+            let _adding = self.new_referrer.lock().unwrap();
+            let mut counter = key(date, referrer);
+
+            if !self.referrers.contains_key(&counter)? && self.referrers.scan_prefix(format!("{}\0", date)).count() >= self.config.max_referrers {
+                counter = key(date, OTHER);
+            }
+
+            self.referrers.update_and_fetch(counter, increment)?;
+        }
+
+        Ok(())
+    }
+
+    /// Counts from `from` to `to`, both inclusive. Called from a blocking thread.
+    pub fn stats(&self, from: NaiveDate, to: NaiveDate) -> sled::Result<Stats> {
+        let mut days: BTreeMap<NaiveDate, Counts> = BTreeMap::new();
+        let mut total = Counts::default();
+
+        let start = format!("{}\0", from).into_bytes();
+        let end = format!("{}\u{1}", to).into_bytes();
+
+        for (tree, referrers) in &[(&self.routes, false), (&self.referrers, true)] {
+            for entry in tree.range(start.clone()..end.clone()) {
+                let (key, value) = entry?;
+
+                let (date, name) = match parse_key(&key) {
+                    Some(parsed) => parsed,
+                    None => continue,
+                };
+
+                let count = <[u8; 8]>::try_from(&value[..]).map_or(0, u64::from_be_bytes);
+                let day = days.entry(date).or_default();
+
+                let (day, total) = match referrers {
+                    true => (&mut day.referrers, &mut total.referrers),
+                    false => (&mut day.routes, &mut total.routes),
+                };
+
+                day.insert(name.clone(), count);
+                *total.entry(name).or_default() += count;
+            }
+        }
+
+        Ok(Stats {
+            from,
+            to,
+            total,
+            days: days.into_iter().map(|(date, counts)| DayCounts { date, counts }).collect(),
+        })
+    }
+
+    /// Deletes the counts of days before `before`, returning how many counters were removed
+    pub fn prune(&self, before: NaiveDate) -> sled::Result<usize> {
+        let end = format!("{}\0", before).into_bytes();
+        let mut removed = 0;
+
+        for tree in &[&self.routes, &self.referrers] {
+            for key in tree.range(..end.clone()).keys() {
+                tree.remove(key?)?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Spawns a task that deletes expired counts once a day
+    pub fn watch(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(24 * 60 * 60));
+
+        tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+
+                let analytics = self.clone();
+                let before = Utc::now().date_naive() - Days::days(i64::from(self.config.retention_days));
+
+                match tokio::task::spawn_blocking(move || analytics.prune(before)).await {
+                    Ok(Ok(0)) => {}
+                    Ok(Ok(removed)) => log::info!("deleted {} page view counters from before {}", removed, before),
+                    Ok(Err(e)) => log::warn!("unable to prune the analytics database: {}", e),
+                    Err(_) => {}
+                }
+            }
+        });
+    }
+
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        let given = headers.get(header::AUTHORIZATION).and_then(|value| value.to_str().ok()).and_then(|value| value.strip_prefix("Bearer "));
+
+        match (&self.token, given) {
+            (Some(token), Some(given)) => constant_time_eq(token.as_bytes(), given.trim().as_bytes()),
+            _ => false,
+        }
+    }
+}
+
+fn respond<T: serde::Serialize>(value: &T, status: StatusCode) -> warp::reply::Response {
+    let mut res = warp::reply::with_status(warp::reply::json(value), status).into_response();
+    res.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    res
+}
+
+fn no_content() -> warp::reply::Response {
+    warp::reply::with_status(warp::reply(), StatusCode::NO_CONTENT).into_response()
+}
+
+async fn hit(analytics: Arc<Analytics>, hit: Hit, headers: HeaderMap) -> warp::reply::Response {
+    // the answer is the same whether or not the view was counted
+    if opted_out(&headers) {
+        return no_content();
+    }
+
+    let route = match normalize_route(&hit.route, &analytics.blog.index(), &analytics.projects.index()) {
+        Some(route) => route,
+        None => return no_content(),
+    };
+
+    let own_host = headers.get(header::HOST).and_then(|value| value.to_str().ok());
+    let referrer = hit.referrer.as_deref().and_then(|referrer| referrer_host(referrer, own_host));
+    let today = Utc::now().date_naive();
+
+    let recorded = tokio::task::spawn_blocking(move || analytics.record(today, &route, referrer.as_deref())).await;
+
+    if let Ok(Err(e)) = recorded {
+        log::warn!("unable to count a page view: {}", e);
+    }
+
+    no_content()
+}
+
+async fn stats(analytics: Arc<Analytics>, query: StatsQuery, headers: HeaderMap) -> warp::reply::Response {
+    if !analytics.authorized(&headers) {
+        let mut res = respond(&serde_json::json!({ "error": "unauthorized" }), StatusCode::UNAUTHORIZED);
+        res.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer realm=\"stats\""));
+        return res;
+    }
+
+    let to = Utc::now().date_naive();
+    let from = to - Days::days(i64::from(query.days.unwrap_or(30).clamp(1, 366)) - 1);
+
+    match tokio::task::spawn_blocking(move || analytics.stats(from, to)).await {
+        Ok(Ok(stats)) => respond(&stats, StatusCode::OK),
+        Ok(Err(e)) => {
+            log::warn!("unable to read the analytics database: {}", e);
+            respond(&serde_json::json!({ "error": "unavailable" }), StatusCode::SERVICE_UNAVAILABLE)
+        }
+        Err(_) => respond(&serde_json::json!({ "error": "unavailable" }), StatusCode::SERVICE_UNAVAILABLE),
+    }
+}
+
+/// `POST /api/hit` and `GET /api/stats`, or nothing when analytics are disabled
+pub fn routes(analytics: Option<Arc<Analytics>>) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    let analytics = warp::any().and_then(move || {
+        let analytics = analytics.clone();
+
+        async move { analytics.ok_or_else(warp::reject::not_found) }
+    });
+
+    let hits = warp::path(Endpoint::PREFIX)
+        .and(warp::path(Endpoint::Hit.segment()))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(analytics.clone())
+        .and(warp::body::content_length_limit(2 * 1024))
+        .and(warp::body::json())
+        .and(warp::header::headers_cloned())
+        .and_then(|analytics: Arc<Analytics>, body: Hit, headers: HeaderMap| async move { Ok::<_, Rejection>(hit(analytics, body, headers).await) });
+
+    let stats = warp::path(Endpoint::PREFIX)
+        .and(warp::path(Endpoint::Stats.segment()))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(analytics.and_then(|analytics: Arc<Analytics>| async move {
+            match analytics.token {
+                Some(_) => Ok(analytics),
+                None => Err(warp::reject::not_found()),
+            }
+        }))
+        .and(warp::query::<StatsQuery>())
+        .and(warp::header::headers_cloned())
+        .and_then(|analytics: Arc<Analytics>, query: StatsQuery, headers: HeaderMap| async move {
+            Ok::<_, Rejection>(stats(analytics, query, headers).await)
+        });
+
+    hits.or(stats).unify()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::blog::{BlogConfig, Post};
+    use crate::projects::ProjectsConfig;
+
+    fn analytics(max_referrers: usize) -> Analytics {
+        let config = AnalyticsConfig {
+            token: Some("secret".to_owned()),
+            max_referrers,
+            ..AnalyticsConfig::default()
+        };
+
+        let db = sled::Config::new().temporary(true).open().expect("temporary database");
+        let nowhere = std::env::temp_dir().join("nova-test-missing");
+
+        let blog = Arc::new(Blog::new(BlogConfig {
+            content: nowhere.clone(),
+            ..BlogConfig::default()
+        }));
+
+        let projects = Arc::new(Projects::new(ProjectsConfig {
+            manifest: nowhere.join("projects.toml"),
+            ..ProjectsConfig::default()
+        }));
+
+        Analytics::with_db(config, db, blog, projects).expect("analytics")
+    }
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2020, 7, 1).expect("valid date")
+    }
+
+    #[test]
+    fn only_existing_pages_are_counted() {
+        let post = Post::parse(Path::new("posts/hello.md"), "+++\ntitle = \"Hello\"\ndate = 2020-07-01\n+++\n").expect("valid test post");
+        let posts = blog::Index::new(vec![Arc::new(post)]);
+        let projects = projects::Index::parse(Path::new("projects.toml"), "[[project]]\nslug = \"doom-fire\"\ntitle = \"Doom Fire\"\ndescription = \"\"\ndate = 2020-06-14\n")
+            .expect("valid test manifest");
+
+        let route = |route| normalize_route(route, &posts, &projects);
+
+        assert_eq!(route("/about/?tab=1#top").as_deref(), Some("/about"));
+        assert_eq!(route("/blog/hello").as_deref(), Some("/blog/hello"));
+        assert_eq!(route("/portfolio/doom-fire").as_deref(), Some("/portfolio/doom-fire"));
+
+        assert_eq!(route("/blog/made-up"), None);
+        assert_eq!(route("/portfolio/made-up"), None);
+        assert_eq!(route("/wp-admin"), None);
+        assert_eq!(route("about"), None);
+    }
+
+    #[test]
+    fn referrers_are_external_hosts() {
+        assert_eq!(referrer_host("https://www.Example.com/a?b", Some("nova.dev")).as_deref(), Some("example.com"));
+        assert_eq!(referrer_host("https://nova.dev/blog", Some("nova.dev:443")), None);
+        assert_eq!(referrer_host("android-app://com.example", None), None);
+        assert_eq!(referrer_host("not a url", None), None);
+    }
+
+    #[test]
+    fn referrers_beyond_the_cap_are_other() {
+        let analytics = analytics(2);
+
+        for host in &["a.example", "b.example", "c.example", "d.example", "a.example"] {
+            analytics.record(date(), "/", Some(host)).expect("recorded");
+        }
+
+        let stats = analytics.stats(date(), date()).expect("stats");
+        let referrers: Vec<_> = stats.total.referrers.iter().map(|(host, &count)| (host.as_str(), count)).collect();
+
+        assert_eq!(referrers, [("a.example", 2), ("b.example", 1), ("other", 2)]);
+        assert_eq!(stats.total.routes["/"], 5);
+    }
+
+    #[test]
+    fn old_days_are_pruned() {
+        let analytics = analytics(10);
+        let yesterday = date().pred_opt().expect("valid date");
+
+        analytics.record(yesterday, "/", Some("a.example")).expect("recorded");
+        analytics.record(date(), "/", None).expect("recorded");
+
+        assert_eq!(analytics.prune(date()).expect("pruned"), 2);
+
+        let stats = analytics.stats(yesterday, date()).expect("stats");
+        assert_eq!(stats.days.iter().map(|day| day.date).collect::<Vec<_>>(), [date()]);
+    }
+
+    #[test]
+    fn stats_need_the_exact_token() {
+        let analytics = analytics(10);
+
+        let bearer = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, HeaderValue::from_str(value).expect("valid header"));
+            headers
+        };
+
+        assert!(analytics.authorized(&bearer("Bearer secret")));
+        assert!(!analytics.authorized(&bearer("Bearer secre")));
+        assert!(!analytics.authorized(&bearer("Bearer secrets")));
+        assert!(!analytics.authorized(&bearer("secret")));
+        assert!(!analytics.authorized(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn opting_out_is_honored() {
+        let mut headers = HeaderMap::new();
+        assert!(!opted_out(&headers));
+
+        headers.insert("sec-gpc", HeaderValue::from_static("1"));
+        assert!(opted_out(&headers));
+    }
+}
@@ -0,0 +1,533 @@
+//! Static file serving for the client bundle
+//!
+//! Files are served with content negotiation against precompressed siblings (`app_bg.wasm.br`, `main.css.gz`)
+//! and compressed copies cached in memory, falling back to the identity encoding with range support. Files on disk
+//! are streamed in chunks rather than read into memory, unless they are compressed on the fly.
+//! Every representation carries a strong ETag and the `Cache-Control` chosen by the [`CachePolicy`].
+//! With the `embed` feature the same is served from the files compiled into the binary, see [`crate::embed`].
+
+use std::collections::HashMap;
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use headers::{
+    AcceptRanges, ContentLength, ContentRange, ContentType, ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, IfRange, LastModified, Range,
+};
+use hyper::body::{Body, Bytes};
+use percent_encoding::percent_decode_str;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+use warp::http::{header, HeaderMap, HeaderValue, Response, StatusCode};
+use warp::path::Tail;
+use warp::{Filter, Rejection};
+
+use crate::cache::CachePolicy;
+use crate::compression::{self, CompressionCache, CompressionConfig, Encoding};
+use crate::embed::EmbeddedFile;
+
+pub struct Assets {
+    root: PathBuf,
+
+    /// Files compiled into the binary, served instead of reading `root`
+    embedded: Option<Embedded>,
+
+    compression: CompressionConfig,
+    cache: CompressionCache,
+    policy: CachePolicy,
+    etags: Mutex<HashMap<PathBuf, CachedEtag>>,
+}
+
+struct CachedEtag {
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: String,
+}
+
+struct Embedded {
+    files: HashMap<&'static str, &'static EmbeddedFile>,
+
+    /// When the files were embedded, their modification time as far as clients are concerned
+    modified: SystemTime,
+}
+
+struct Asset {
+    path: PathBuf,
+    len: u64,
+    modified: Option<SystemTime>,
+    mime: mime_guess::Mime,
+    embedded: Option<&'static EmbeddedFile>,
+}
+
+/// Where the bytes of a compressed representation come from
+enum Variant {
+    Precompressed(PathBuf),
+    OnTheFly,
+    Embedded(&'static [u8]),
+}
+
+/// Bytes read from a file at a time while streaming it
+const CHUNK: u64 = 64 * 1024;
+
+/// Streams `len` bytes of the file at `path`, starting at `start`
+async fn stream_file(path: &Path, start: u64, len: u64) -> io::Result<Body> {
+    let mut file = fs::File::open(path).await?;
+
+    if start > 0 {
+        file.seek(SeekFrom::Start(start)).await?;
+    }
+
+    let chunks = futures::stream::unfold(Some((file, len)), |state| async move {
+        let (mut file, remaining) = state.filter(|&(_, remaining)| remaining > 0)?;
+        let mut buf = vec![0; remaining.min(CHUNK) as usize];
+
+        match file.read(&mut buf).await {
+            Ok(0) => Some((Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file shrank while being served")), None)),
+            Ok(read) => {
+                buf.truncate(read);
+                Some((Ok(Bytes::from(buf)), Some((file, remaining - read as u64))))
+            }
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+
+    Ok(Body::wrap_stream(chunks))
+}
+
+/// Hex digest used for ETags, truncated to keep headers short
+pub fn content_hash(data: &[u8]) -> String {
+    Sha256::digest(data).iter().take(16).map(|b| format!("{:02x}", b)).collect()
+}
+
+impl Assets {
+    pub fn new(root: impl Into<PathBuf>, compression: CompressionConfig, policy: CachePolicy) -> Assets {
+        Assets {
+            root: root.into(),
+            embedded: None,
+            cache: CompressionCache::new(compression.cache_limit),
+            compression,
+            policy,
+            etags: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Serves `files` from memory instead of a directory, see [`embed::files`](crate::embed::files)
+    pub fn embedded(files: &'static [EmbeddedFile], modified: SystemTime, compression: CompressionConfig, policy: CachePolicy) -> Assets {
+        let embedded = Embedded {
+            files: files.iter().map(|file| (file.path, file)).collect(),
+            modified,
+        };
+
+        Assets {
+            embedded: Some(embedded),
+            ..Assets::new(PathBuf::new(), compression, policy)
+        }
+    }
+
+    pub fn is_embedded(&self) -> bool {
+        self.embedded.is_some()
+    }
+
+    /// `Cache-Control` for a path relative to the dist directory
+    pub fn cache_control(&self, path: &str) -> Option<&HeaderValue> {
+        self.policy.cache_control(path)
+    }
+
+    /// Maps a request path onto the dist directory, refusing anything that could escape it
+    fn resolve(&self, tail: &str) -> Option<PathBuf> {
+        let decoded = percent_decode_str(tail).decode_utf8().ok()?;
+
+        let mut path = self.root.clone();
+
+        for segment in decoded.split('/') {
+            if segment.starts_with("..") || segment.contains('\\') || segment.contains('\0') || Path::new(segment).is_absolute() {
+                return None;
+            }
+
+            path.push(segment);
+        }
+
+        Some(path)
+    }
+
+    fn lookup_embedded(&self, embedded: &Embedded, path: &Path) -> Option<Asset> {
+        let name = self.relative(path);
+
+        let file = match embedded.files.get(name.as_str()) {
+            Some(file) => *file,
+            None if name.is_empty() => embedded.files.get("index.html")?,
+            None => embedded.files.get(format!("{}/index.html", name).as_str())?,
+        };
+
+        Some(Asset {
+            path: PathBuf::from(file.path),
+            len: file.data.len() as u64,
+            modified: Some(embedded.modified),
+            mime: file.mime.parse().unwrap_or(mime_guess::mime::APPLICATION_OCTET_STREAM),
+            embedded: Some(file),
+        })
+    }
+
+    async fn lookup(&self, tail: &str) -> Option<Asset> {
+        let mut path = self.resolve(tail)?;
+
+        if let Some(ref embedded) = self.embedded {
+            return self.lookup_embedded(embedded, &path);
+        }
+
+        let mut metadata = fs::metadata(&path).await.ok()?;
+
+        if metadata.is_dir() {
+            path.push("index.html");
+            metadata = fs::metadata(&path).await.ok()?;
+        }
+
+        if !metadata.is_file() {
+            return None;
+        }
+
+        Some(Asset {
+            mime: mime_guess::from_path(&path).first_or_octet_stream(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            path,
+            embedded: None,
+        })
+    }
+
+    /// Modification time of the file at `path`, relative to the dist directory, or `None` if there is no such file
+    pub async fn modified(&self, path: &str) -> Option<Option<SystemTime>> {
+        self.lookup(path).await.map(|asset| asset.modified)
+    }
+
+    /// Contents of the file at `path`, relative to the dist directory
+    pub async fn read(&self, path: &str) -> Option<Bytes> {
+        let asset = self.lookup(path).await?;
+
+        match asset.embedded {
+            Some(file) => Some(Bytes::from_static(file.data)),
+            None => fs::read(&asset.path).await.ok().map(Bytes::from),
+        }
+    }
+
+    /// Path relative to the dist directory with `/` separators, as matched by the cache policy
+    fn relative(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+
+        relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+    }
+
+    /// Hash of the identity representation, recomputed only when the file changes
+    async fn etag(&self, asset: &Asset) -> Option<String> {
+        if let Some(file) = asset.embedded {
+            return Some(file.hash.to_owned());
+        }
+
+        if let Some(cached) = self.etags.lock().unwrap().get(&asset.path) {
+            if cached.modified == asset.modified && cached.len == asset.len {
+                return Some(cached.hash.clone());
+            }
+        }
+
+        let data = fs::read(&asset.path).await.ok()?;
+        let hash = content_hash(&data);
+
+        let cached = CachedEtag {
+            modified: asset.modified,
+            len: asset.len,
+            hash: hash.clone(),
+        };
+
+        self.etags.lock().unwrap().insert(asset.path.clone(), cached);
+
+        Some(hash)
+    }
+
+    /// Determines how a compressed representation of the asset could be produced, if at all
+    async fn variant(&self, asset: &Asset, encoding: Encoding) -> Option<Variant> {
+        // embedded variants were either precompressed in dist or compressed when building
+        if let Some(file) = asset.embedded {
+            if !(self.compression.precompressed || self.compression.on_the_fly) {
+                return None;
+            }
+
+            let data = match encoding {
+                Encoding::Brotli => file.brotli,
+                Encoding::Gzip => file.gzip,
+            };
+
+            return data.map(Variant::Embedded);
+        }
+
+        if self.compression.precompressed {
+            let sibling = encoding.sibling(&asset.path);
+
+            if let Ok(metadata) = fs::metadata(&sibling).await {
+                // ignore siblings left over from an older build
+                let fresh = match (metadata.modified(), asset.modified) {
+                    (Ok(sibling), Some(original)) => sibling >= original,
+                    _ => true,
+                };
+
+                if metadata.is_file() && fresh {
+                    return Some(Variant::Precompressed(sibling));
+                }
+            }
+        }
+
+        if self.compression.on_the_fly
+            && asset.modified.is_some()
+            && asset.len >= self.compression.min_size
+            && compression::is_compressible(&asset.mime)
+        {
+            return Some(Variant::OnTheFly);
+        }
+
+        None
+    }
+
+    /// The body of a compressed representation and its length
+    async fn load_variant(&self, asset: &Asset, encoding: Encoding, variant: Variant) -> Option<(Body, u64)> {
+        let data = match variant {
+            Variant::Precompressed(sibling) => {
+                let len = fs::metadata(&sibling).await.ok()?.len();
+                return Some((stream_file(&sibling, 0, len).await.ok()?, len));
+            }
+            Variant::Embedded(data) => Bytes::from_static(data),
+            Variant::OnTheFly => self.compress(asset, encoding).await?,
+        };
+
+        let len = data.len() as u64;
+        Some((Body::from(data), len))
+    }
+
+    /// The asset compressed with `encoding`, cached in memory
+    async fn compress(&self, asset: &Asset, encoding: Encoding) -> Option<Bytes> {
+        let modified = asset.modified?;
+
+        if let Some(data) = self.cache.get(&asset.path, encoding, modified) {
+            return Some(data);
+        }
+
+        let data = fs::read(&asset.path).await.ok()?;
+        let config = self.compression.clone();
+
+        let compressed = tokio::task::spawn_blocking(move || encoding.compress(&data, &config)).await.ok()?.ok()?;
+        let compressed = Bytes::from(compressed);
+
+        self.cache.insert(&asset.path, encoding, modified, compressed.clone());
+
+        Some(compressed)
+    }
+
+    /// Serves the file at `tail`, relative to the dist directory
+    pub async fn serve(&self, tail: &str, headers: &HeaderMap) -> Result<Response<Body>, Rejection> {
+        let asset = self.lookup(tail).await.ok_or_else(warp::reject::not_found)?;
+
+        let accept_encoding = headers.get(header::ACCEPT_ENCODING).and_then(|value| value.to_str().ok());
+
+        let mut chosen = None;
+
+        for encoding in compression::negotiate(accept_encoding) {
+            if let Some(variant) = self.variant(&asset, encoding).await {
+                chosen = Some((encoding, variant));
+                break;
+            }
+        }
+
+        let hash = self.etag(&asset).await.ok_or_else(warp::reject::not_found)?;
+
+        let etag = strong_etag(&hash, chosen.as_ref().map(|(encoding, _)| *encoding));
+        let last_modified = asset.modified.map(LastModified::from);
+
+        let mut res = Response::new(Body::empty());
+
+        res.headers_mut().typed_insert(ContentType::from(asset.mime.clone()));
+        res.headers_mut().insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+        res.headers_mut().typed_insert(etag.clone());
+
+        if let Some(last_modified) = last_modified {
+            res.headers_mut().typed_insert(last_modified);
+        }
+
+        if let Some(cache_control) = self.policy.cache_control(&self.relative(&asset.path)) {
+            res.headers_mut().insert(header::CACHE_CONTROL, cache_control.clone());
+        }
+
+        // If-Modified-Since is only consulted when If-None-Match is absent
+        let not_modified = match headers.typed_get::<IfNoneMatch>() {
+            Some(if_none_match) => !if_none_match.precondition_passes(&etag),
+            None => match (headers.typed_get::<IfModifiedSince>(), asset.modified) {
+                (Some(since), Some(modified)) => !since.is_modified(modified),
+                _ => false,
+            },
+        };
+
+        if not_modified {
+            *res.status_mut() = StatusCode::NOT_MODIFIED;
+            return Ok(res);
+        }
+
+        if let Some((encoding, variant)) = chosen {
+            let (body, len) = self.load_variant(&asset, encoding, variant).await.ok_or_else(warp::reject::not_found)?;
+
+            res.headers_mut().insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding.as_str()));
+            res.headers_mut().typed_insert(ContentLength(len));
+            *res.body_mut() = body;
+
+            return Ok(res);
+        }
+
+        let len = asset.len;
+
+        res.headers_mut().typed_insert(AcceptRanges::bytes());
+
+        let range = match headers.typed_get::<IfRange>() {
+            Some(if_range) if if_range.is_modified(Some(&etag), last_modified.as_ref()) => None,
+            _ => headers.typed_get::<Range>(),
+        };
+
+        // several ranges would need a multipart body, and ignoring the header to send everything is allowed
+        let range = range.filter(|range| range.iter().count() == 1);
+
+        let (start, end) = match range.map(|range| satisfiable_range(&range, len)) {
+            None => (0, len),
+            Some(Some((start, end))) => {
+                *res.status_mut() = StatusCode::PARTIAL_CONTENT;
+                res.headers_mut().typed_insert(ContentRange::bytes(start..end, len).expect("valid range"));
+                (start, end)
+            }
+            Some(None) => {
+                *res.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+                res.headers_mut().typed_insert(ContentRange::unsatisfied_bytes(len));
+                return Ok(res);
+            }
+        };
+
+        res.headers_mut().typed_insert(ContentLength(end - start));
+
+        *res.body_mut() = match asset.embedded {
+            Some(file) => Body::from(Bytes::from_static(&file.data[start as usize..end as usize])),
+            None => stream_file(&asset.path, start, end - start).await.map_err(|_| warp::reject::not_found())?,
+        };
+
+        Ok(res)
+    }
+
+    /// Serves content generated at request time with the validators, cache policy and compression of a file at
+    /// `path`, relative to the dist directory. Ranges are not supported.
+    pub async fn serve_generated(&self, path: &str, mime: mime_guess::Mime, data: Bytes, headers: &HeaderMap) -> Response<Body> {
+        let accept_encoding = headers.get(header::ACCEPT_ENCODING).and_then(|value| value.to_str().ok());
+
+        let compress = self.compression.on_the_fly && data.len() as u64 >= self.compression.min_size && compression::is_compressible(&mime);
+        let encoding = if compress { compression::negotiate(accept_encoding).into_iter().next() } else { None };
+
+        let etag = strong_etag(&content_hash(&data), encoding);
+
+        let mut res = Response::new(Body::empty());
+
+        res.headers_mut().typed_insert(ContentType::from(mime));
+        res.headers_mut().insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+        res.headers_mut().typed_insert(etag.clone());
+
+        if let Some(cache_control) = self.policy.cache_control(path) {
+            res.headers_mut().insert(header::CACHE_CONTROL, cache_control.clone());
+        }
+
+        if let Some(if_none_match) = headers.typed_get::<IfNoneMatch>() {
+            if !if_none_match.precondition_passes(&etag) {
+                *res.status_mut() = StatusCode::NOT_MODIFIED;
+                return res;
+            }
+        }
+
+        let body = match encoding {
+            Some(encoding) => {
+                let config = self.compression.clone();
+                let input = data.clone();
+
+                match tokio::task::spawn_blocking(move || encoding.compress(&input, &config)).await {
+                    Ok(Ok(compressed)) => {
+                        res.headers_mut().insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding.as_str()));
+                        Bytes::from(compressed)
+                    }
+                    // the identity body would not match the ETag of the compressed representation
+                    _ => {
+                        res.headers_mut().typed_insert(strong_etag(&content_hash(&data), None));
+                        data
+                    }
+                }
+            }
+            None => data,
+        };
+
+        res.headers_mut().typed_insert(ContentLength(body.len() as u64));
+        *res.body_mut() = Body::from(body);
+
+        res
+    }
+
+    /// [`serve_generated`](Assets::serve_generated) for an API response
+    pub async fn serve_json<T: serde::Serialize>(&self, path: &str, value: &T, headers: &HeaderMap) -> Response<Body> {
+        let body = serde_json::to_vec(value).expect("serializable response");
+
+        self.serve_generated(path, mime_guess::mime::APPLICATION_JSON, Bytes::from(body), headers).await
+    }
+}
+
+/// Strong validators are per-representation, so compressed bodies are tagged separately
+fn strong_etag(hash: &str, encoding: Option<Encoding>) -> ETag {
+    let etag = match encoding {
+        Some(encoding) => format!("\"{}-{}\"", hash, encoding.extension()),
+        None => format!("\"{}\"", hash),
+    };
+
+    etag.parse().expect("valid etag")
+}
+
+/// Resolves the only range of a `Range` header into a half-open byte range, if it can be satisfied
+fn satisfiable_range(range: &Range, len: u64) -> Option<(u64, u64)> {
+    use std::ops::Bound;
+
+    let (start, end) = range.iter().next()?;
+
+    let (start, end) = match (start, end) {
+        (Bound::Included(start), Bound::Included(end)) => (start, end.saturating_add(1).min(len)),
+        (Bound::Included(start), Bound::Unbounded) => (start, len),
+        // suffix range, the last `n` bytes
+        (Bound::Unbounded, Bound::Included(n)) => (len.saturating_sub(n), len),
+        _ => return None,
+    };
+
+    if start < end && end <= len {
+        Some((start, end))
+    } else {
+        None
+    }
+}
+
+/// GET and HEAD only, like `warp::fs`
+fn get_or_head() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::get().or(warp::head()).unify()
+}
+
+/// Serves any file below the dist directory
+pub fn dir(assets: Arc<Assets>) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    get_or_head()
+        .and(warp::path::tail())
+        .and(warp::header::headers_cloned())
+        .and_then(move |tail: Tail, headers: HeaderMap| {
+            let assets = assets.clone();
+            async move { assets.serve(tail.as_str(), &headers).await }
+        })
+}
+
+/// Serves a single file, relative to the dist directory
+pub fn file(assets: Arc<Assets>, path: &'static str) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    get_or_head().and(warp::header::headers_cloned()).and_then(move |headers: HeaderMap| {
+        let assets = assets.clone();
+        async move { assets.serve(path, &headers).await }
+    })
+}
@@ -0,0 +1,430 @@
+//! Markdown blog posts from `content/posts`
+//!
+//! Each post is a `.md` file whose name is its slug, of lowercase letters, digits and dashes, starting with TOML front matter between `+++` lines:
+//!
+//! ```text
+//! +++
+//! title = "Hello, World"
+//! date = 2020-07-01
+//! tags = ["rust", "wasm"]
+//! summary = "Optional, defaults to the first paragraph"
+//! updated = 2020-07-02  # optional, when the post was last revised
+//! draft = false
+//! +++
+//! ```
+//!
+//! Posts are parsed when the server starts and reparsed whenever a file in the directory changes.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use nova_common::api::{PostDetail, PostList, PostQuery, PostSummary};
+use nova_common::Endpoint;
+use pulldown_cmark::{html, Event, Options, Parser, Tag};
+use warp::http::HeaderMap;
+use warp::{Filter, Rejection};
+
+use crate::assets::Assets;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlogConfig {
+    /// Directory containing `posts/`
+    pub content: PathBuf,
+
+    /// Publish posts marked as drafts, for previewing
+    pub drafts: bool,
+
+    /// Default and maximum page size of `/api/posts`
+    pub per_page: usize,
+    pub max_per_page: usize,
+
+    /// How often to check the posts for changes, in seconds. Zero disables reloading.
+    pub reload_interval: u64,
+}
+
+impl Default for BlogConfig {
+    fn default() -> Self {
+        BlogConfig {
+            content: PathBuf::from("../content"),
+            drafts: false,
+            per_page: 10,
+            max_per_page: 50,
+            reload_interval: 2,
+        }
+    }
+}
+
+impl BlogConfig {
+    /// Whether pages hold at least one post and the default fits under the maximum
+    pub fn is_valid(&self) -> bool {
+        self.per_page >= 1 && self.max_per_page >= self.per_page
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PostError {
+    #[error("unable to read {0:?}: {1}")]
+    Read(PathBuf, #[source] io::Error),
+
+    #[error("{0:?} does not start with `+++` front matter")]
+    MissingFrontMatter(PathBuf),
+
+    #[error("invalid front matter in {0:?}: {1}")]
+    FrontMatter(PathBuf, #[source] toml::de::Error),
+
+    #[error("invalid date {1:?} in {0:?}, expected YYYY-MM-DD or RFC 3339")]
+    Date(PathBuf, String),
+
+    #[error("invalid post slug {0:?}, expected lowercase letters, digits and dashes")]
+    Slug(PathBuf),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FrontMatter {
+    title: String,
+    date: toml::value::Datetime,
+
+    #[serde(default)]
+    tags: Vec<String>,
+
+    #[serde(default)]
+    draft: bool,
+
+    summary: Option<String>,
+    updated: Option<toml::value::Datetime>,
+}
+
+#[derive(Debug)]
+pub struct Post {
+    pub summary: PostSummary,
+    pub html: String,
+    pub draft: bool,
+
+    /// Last revision, the publication date unless given in the front matter
+    pub updated: DateTime<Utc>,
+}
+
+/// `YYYY-MM-DD`, a local datetime taken as UTC, or RFC 3339
+pub fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.with_timezone(&Utc));
+    }
+
+    if let Ok(date) = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S") {
+        return Some(Utc.from_utc_datetime(&date));
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0)).map(|date| Utc.from_utc_datetime(&date))
+}
+
+/// Lowercase letters, digits and dashes, which need no escaping in a path
+pub fn valid_slug(slug: &str) -> bool {
+    !slug.is_empty() && slug.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+}
+
+/// Plain text of the first paragraph
+fn first_paragraph(markdown: &str) -> String {
+    let mut text = String::new();
+    let mut inside = false;
+
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::Paragraph) => inside = true,
+            Event::End(Tag::Paragraph) if inside => break,
+            Event::Text(t) | Event::Code(t) if inside => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak if inside => text.push(' '),
+            _ => {}
+        }
+    }
+
+    text
+}
+
+impl Post {
+    pub fn parse(path: &Path, source: &str) -> Result<Post, PostError> {
+        let slug = path.file_stem().and_then(|stem| stem.to_str()).filter(|stem| valid_slug(stem)).ok_or_else(|| PostError::Slug(path.to_owned()))?;
+
+        let source = source.trim_start_matches('\u{feff}');
+
+        let rest = source
+            .strip_prefix("+++\n")
+            .or_else(|| source.strip_prefix("+++\r\n"))
+            .ok_or_else(|| PostError::MissingFrontMatter(path.to_owned()))?;
+
+        let end = rest.find("\n+++").ok_or_else(|| PostError::MissingFrontMatter(path.to_owned()))?;
+
+        let front: FrontMatter = toml::from_str(&rest[..end]).map_err(|e| PostError::FrontMatter(path.to_owned(), e))?;
+
+        // skip the closing delimiter and the rest of its line
+        let body = &rest[end + "\n+++".len()..];
+        let body = body.find('\n').map_or("", |newline| &body[newline + 1..]);
+
+        let date = front.date.to_string();
+        let date = parse_date(&date).ok_or_else(|| PostError::Date(path.to_owned(), date))?;
+
+        let updated = match front.updated {
+            Some(updated) => {
+                let updated = updated.to_string();
+                parse_date(&updated).ok_or_else(|| PostError::Date(path.to_owned(), updated))?
+            }
+            None => date,
+        };
+
+        let mut html = String::new();
+        html::push_html(&mut html, Parser::new_ext(body, Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_STRIKETHROUGH));
+
+        Ok(Post {
+            summary: PostSummary {
+                slug: slug.to_owned(),
+                title: front.title,
+                date,
+                tags: front.tags,
+                summary: front.summary.unwrap_or_else(|| first_paragraph(body)),
+            },
+            html,
+            draft: front.draft,
+            updated,
+        })
+    }
+
+    pub fn detail(&self) -> PostDetail {
+        PostDetail {
+            summary: self.summary.clone(),
+            html: self.html.clone(),
+        }
+    }
+}
+
+/// Published posts, newest first
+#[derive(Debug, Default)]
+pub struct Index {
+    pub posts: Vec<Arc<Post>>,
+    by_slug: HashMap<String, usize>,
+}
+
+impl Index {
+    pub fn new(mut posts: Vec<Arc<Post>>) -> Index {
+        posts.sort_by(|a, b| b.summary.date.cmp(&a.summary.date).then_with(|| a.summary.slug.cmp(&b.summary.slug)));
+
+        let by_slug = posts.iter().enumerate().map(|(i, post)| (post.summary.slug.clone(), i)).collect();
+
+        Index { posts, by_slug }
+    }
+
+    pub fn get(&self, slug: &str) -> Option<&Arc<Post>> {
+        self.by_slug.get(slug).map(|&i| &self.posts[i])
+    }
+
+    /// Posts carrying `tag`, or all posts
+    pub fn tagged<'a>(&'a self, tag: Option<&'a str>) -> impl Iterator<Item = &'a Arc<Post>> + 'a {
+        self.posts.iter().filter(move |post| tag.is_none_or(|tag| post.summary.tags.iter().any(|t| t == tag)))
+    }
+
+    /// One page of posts, 1-based
+    pub fn list(&self, page: usize, per_page: usize, tag: Option<&str>) -> PostList {
+        let total = self.tagged(tag).count();
+
+        PostList {
+            posts: self.tagged(tag).skip(page.saturating_sub(1).saturating_mul(per_page)).take(per_page).map(|post| post.summary.clone()).collect(),
+            page,
+            per_page,
+            total,
+            total_pages: total.div_ceil(per_page),
+            tag: tag.map(str::to_owned),
+        }
+    }
+}
+
+/// Name, length and modification time of every post file, to detect changes
+type Signature = Vec<(PathBuf, u64, Option<SystemTime>)>;
+
+pub struct Blog {
+    config: BlogConfig,
+    index: RwLock<Arc<Index>>,
+    signature: RwLock<Option<Signature>>,
+}
+
+impl Blog {
+    pub fn new(config: BlogConfig) -> Blog {
+        let blog = Blog {
+            config,
+            index: RwLock::new(Arc::default()),
+            signature: RwLock::new(None),
+        };
+
+        blog.reload_if_changed();
+        blog
+    }
+
+    pub fn config(&self) -> &BlogConfig {
+        &self.config
+    }
+
+    fn posts_dir(&self) -> PathBuf {
+        self.config.content.join("posts")
+    }
+
+    pub fn index(&self) -> Arc<Index> {
+        self.index.read().unwrap().clone()
+    }
+
+    fn signature(&self) -> Signature {
+        let mut signature: Signature = fs::read_dir(self.posts_dir())
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let metadata = fs::metadata(&path).ok()?;
+
+                match path.extension() {
+                    Some(ext) if ext == "md" && metadata.is_file() => Some((path, metadata.len(), metadata.modified().ok())),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        signature.sort();
+        signature
+    }
+
+    /// Reparses every post if any file was added, removed or modified. Invalid posts are logged and left out.
+    pub fn reload_if_changed(&self) -> bool {
+        let signature = self.signature();
+
+        if self.signature.read().unwrap().as_ref() == Some(&signature) {
+            return false;
+        }
+
+        let mut posts = Vec::new();
+
+        for (path, _, _) in &signature {
+            let parsed = fs::read_to_string(path).map_err(|e| PostError::Read(path.clone(), e)).and_then(|source| Post::parse(path, &source));
+
+            match parsed {
+                Ok(post) if post.draft && !self.config.drafts => {}
+                Ok(post) => posts.push(Arc::new(post)),
+                Err(e) => log::warn!("skipping post: {}", e),
+            }
+        }
+
+        log::info!("indexed {} posts from {:?}", posts.len(), self.posts_dir());
+
+        *self.index.write().unwrap() = Arc::new(Index::new(posts));
+        *self.signature.write().unwrap() = Some(signature);
+
+        true
+    }
+
+    /// Spawns a task that periodically reindexes the posts
+    pub fn watch(self: Arc<Self>) {
+        if self.config.reload_interval == 0 {
+            return;
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.reload_interval));
+
+        tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+
+                let blog = self.clone();
+                let _ = tokio::task::spawn_blocking(move || blog.reload_if_changed()).await;
+            }
+        });
+    }
+}
+
+/// `GET /api/posts` and `GET /api/posts/{slug}`
+pub fn routes(blog: Arc<Blog>, assets: Arc<Assets>) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    let list = {
+        let blog = blog.clone();
+        let assets = assets.clone();
+
+        warp::path::end().and(warp::query::<PostQuery>()).and(warp::header::headers_cloned()).and_then(move |query: PostQuery, headers: HeaderMap| {
+            let blog = blog.clone();
+            let assets = assets.clone();
+
+            async move {
+                let config = blog.config();
+                let page = query.page.unwrap_or(1).max(1);
+                let per_page = query.per_page.unwrap_or(config.per_page).clamp(1, config.max_per_page);
+
+                let list = blog.index().list(page, per_page, query.tag.as_deref());
+
+                Ok::<_, Rejection>(assets.serve_json("api/posts", &list, &headers).await)
+            }
+        })
+    };
+
+    let post = warp::path::param().and(warp::path::end()).and(warp::header::headers_cloned()).and_then(move |slug: String, headers: HeaderMap| {
+        let blog = blog.clone();
+        let assets = assets.clone();
+
+        async move {
+            let index = blog.index();
+            let post = index.get(&slug).ok_or_else(warp::reject::not_found)?;
+
+            Ok::<_, Rejection>(assets.serve_json("api/posts", &post.detail(), &headers).await)
+        }
+    });
+
+    warp::get().and(warp::path(Endpoint::PREFIX)).and(warp::path(Endpoint::Posts.segment())).and(list.or(post).unify())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(slug: &str, date: &str) -> Arc<Post> {
+        let source = format!("+++\ntitle = \"{}\"\ndate = {}\n+++\n\nBody.\n", slug, date);
+
+        Arc::new(Post::parse(Path::new(&format!("posts/{}.md", slug)), &source).expect("valid test post"))
+    }
+
+    #[test]
+    fn pages_past_the_end_are_empty() {
+        let index = Index::new(vec![post("a", "2020-01-01"), post("b", "2020-01-02"), post("c", "2020-01-03")]);
+
+        let list = index.list(2, 2, None);
+        assert_eq!(list.posts.iter().map(|post| post.slug.as_str()).collect::<Vec<_>>(), ["a"]);
+        assert_eq!(list.total_pages, 2);
+
+        assert!(index.list(3, 2, None).posts.is_empty());
+        assert!(index.list(usize::MAX, 50, None).posts.is_empty());
+    }
+
+    #[test]
+    fn page_sizes_are_validated() {
+        assert!(BlogConfig::default().is_valid());
+
+        let config = |per_page, max_per_page| BlogConfig {
+            per_page,
+            max_per_page,
+            ..BlogConfig::default()
+        };
+
+        assert!(!config(0, 50).is_valid());
+        assert!(!config(10, 0).is_valid());
+        assert!(!config(20, 10).is_valid());
+        assert!(config(10, 10).is_valid());
+    }
+
+    #[test]
+    fn slugs_need_no_escaping() {
+        let source = "+++\ntitle = \"Café\"\ndate = 2020-01-01\n+++\n";
+
+        for name in &["my post.md", "café.md", "Upper.md", ".md"] {
+            let parsed = Post::parse(&Path::new("posts").join(name), source);
+            assert!(matches!(parsed, Err(PostError::Slug(_))), "{}", name);
+        }
+
+        assert_eq!(Post::parse(Path::new("posts/cafe-2.md"), source).expect("valid slug").summary.slug, "cafe-2");
+    }
+}
@@ -0,0 +1,194 @@
+//! `Cache-Control` policy for static files
+//!
+//! Rules are matched against the file's path relative to the dist directory, first match wins. Files without
+//! a matching rule fall back to the content-hashed policy if their name contains a hash, then to the default.
+
+use globset::{Glob, GlobMatcher};
+use warp::http::HeaderValue;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheRule {
+    pub glob: String,
+    pub cache_control: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub rules: Vec<CacheRule>,
+
+    /// `Cache-Control` for content-hashed filenames such as `app-8b4d1e2f3a4c5d6e_bg.wasm`
+    pub hashed: Option<String>,
+
+    /// Hex digits a part of a filename needs to count as a content hash, see [`is_content_hashed`]
+    pub min_hash_len: usize,
+
+    /// `Cache-Control` for everything else
+    pub default: Option<String>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            rules: vec![CacheRule {
+                glob: "*.html".to_owned(),
+                cache_control: "no-cache".to_owned(),
+            }],
+            hashed: Some("public, max-age=31536000, immutable".to_owned()),
+            min_hash_len: 16,
+            default: Some("no-cache".to_owned()),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CachePolicyError {
+    #[error("invalid glob {0:?}: {1}")]
+    Glob(String, #[source] globset::Error),
+
+    #[error("invalid Cache-Control value {0:?}")]
+    Value(String),
+}
+
+pub struct CachePolicy {
+    rules: Vec<(GlobMatcher, HeaderValue)>,
+    hashed: Option<HeaderValue>,
+    min_hash_len: usize,
+    default: Option<HeaderValue>,
+}
+
+fn header_value(value: &str) -> Result<HeaderValue, CachePolicyError> {
+    HeaderValue::from_str(value).map_err(|_| CachePolicyError::Value(value.to_owned()))
+}
+
+impl CachePolicy {
+    pub fn new(config: &CacheConfig) -> Result<CachePolicy, CachePolicyError> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| {
+                let glob = Glob::new(&rule.glob).map_err(|e| CachePolicyError::Glob(rule.glob.clone(), e))?;
+                Ok((glob.compile_matcher(), header_value(&rule.cache_control)?))
+            })
+            .collect::<Result<_, CachePolicyError>>()?;
+
+        Ok(CachePolicy {
+            rules,
+            hashed: config.hashed.as_deref().map(header_value).transpose()?,
+            min_hash_len: config.min_hash_len,
+            default: config.default.as_deref().map(header_value).transpose()?,
+        })
+    }
+
+    /// `Cache-Control` value for a path relative to the dist directory, using `/` as separator
+    pub fn cache_control(&self, path: &str) -> Option<&HeaderValue> {
+        for (glob, value) in &self.rules {
+            if glob.is_match(path) {
+                return Some(value);
+            }
+        }
+
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+
+        match self.hashed {
+            Some(ref hashed) if is_content_hashed(file_name, self.min_hash_len) => Some(hashed),
+            _ => self.default.as_ref(),
+        }
+    }
+}
+
+/// Whether a filename carries a content hash of at least `min_len` hex digits, like trunk's `main-1a2b3c4d5e6f7a8b.css`
+/// and `app-8b4d1e2f3a4c5d6e_bg.wasm`, or `app_bg.3f2a.wasm` between the stem and extension.
+///
+/// Shorter runs of hex digits are left alone. The default of 16 is what trunk and wasm-bindgen use, and keeps version
+/// numbers and dates like `jquery-1234.min.js` from being mistaken for hashes and cached for a year. Bundlers with
+/// shorter hashes, like the 4 digits of `app_bg.3f2a.wasm`, need a lower `min_hash_len`.
+pub fn is_content_hashed(file_name: &str, min_len: usize) -> bool {
+    let is_hash = |part: &str| part.len() >= min_len && part.bytes().all(|b| b.is_ascii_hexdigit()) && part.bytes().any(|b| b.is_ascii_digit());
+
+    let mut parts: Vec<&str> = file_name.split('.').collect();
+
+    if parts.len() < 2 {
+        return false;
+    }
+
+    // drop the extension
+    parts.pop();
+
+    // trunk appends `_bg` to the hashed name of the wasm binary
+    let dashed = match parts[0].rfind('-') {
+        Some(idx) => is_hash(parts[0][idx + 1..].trim_end_matches("_bg")),
+        None => false,
+    };
+
+    dashed || parts.iter().skip(1).any(|part| is_hash(part))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_are_recognized() {
+        for name in &["main-1a2b3c4d5e6f7a8b.css", "app-8b4d1e2f3a4c5d6e_bg.wasm", "app-8b4d1e2f3a4c5d6e.js", "app_bg.8b4d1e2f3a4c5d6e.wasm", "chunk.0123456789abcdef0123.js"] {
+            assert!(is_content_hashed(name, 16), "{}", name);
+        }
+    }
+
+    #[test]
+    fn short_hashes_need_a_lower_minimum() {
+        for name in &["app_bg.3f2a.wasm", "main-3f2a9c.css"] {
+            assert!(!is_content_hashed(name, 16), "{}", name);
+            assert!(is_content_hashed(name, 4), "{}", name);
+        }
+
+        let policy = CachePolicy::new(&CacheConfig {
+            min_hash_len: 4,
+            ..CacheConfig::default()
+        })
+        .expect("cache policy");
+
+        assert_eq!(policy.cache_control("app_bg.3f2a.wasm").unwrap(), "public, max-age=31536000, immutable");
+        assert_eq!(policy.cache_control("jquery-123.min.js").unwrap(), "no-cache");
+    }
+
+    #[test]
+    fn versions_and_dates_are_not_hashes() {
+        for name in &[
+            "jquery-1234.min.js",
+            "logo.2024.svg",
+            "v1.0.1234.js",
+            "deadbeefcafebabe.js",
+            "main-deadbeefcafebabe.css",
+            "index.html",
+            "8b4d1e2f3a4c5d6e",
+        ] {
+            assert!(!is_content_hashed(name, 16), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rules_come_before_hashes() {
+        let policy = CachePolicy::new(&CacheConfig::default()).expect("default cache policy");
+
+        assert_eq!(policy.cache_control("index.html").unwrap(), "no-cache");
+        assert_eq!(policy.cache_control("main-1a2b3c4d5e6f7a8b.css").unwrap(), "public, max-age=31536000, immutable");
+        assert_eq!(policy.cache_control("vendor/jquery-1234.min.js").unwrap(), "no-cache");
+
+        let config = CacheConfig {
+            rules: vec![CacheRule {
+                glob: "fonts/*".to_owned(),
+                cache_control: "public, max-age=604800".to_owned(),
+            }],
+            hashed: None,
+            min_hash_len: 16,
+            default: None,
+        };
+
+        let policy = CachePolicy::new(&config).expect("cache policy");
+
+        assert_eq!(policy.cache_control("fonts/inter.woff2").unwrap(), "public, max-age=604800");
+        assert_eq!(policy.cache_control("main-1a2b3c4d5e6f7a8b.css"), None);
+    }
+}
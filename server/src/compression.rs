@@ -0,0 +1,212 @@
+//! Content negotiation and on-the-fly compression of static assets
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use hyper::body::Bytes;
+use mime_guess::Mime;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompressionConfig {
+    /// Serve `.br`/`.gz` siblings of a file when the client accepts them
+    pub precompressed: bool,
+
+    /// Compress files without a precompressed sibling when they are requested
+    pub on_the_fly: bool,
+
+    /// Files smaller than this many bytes are never compressed on the fly
+    pub min_size: u64,
+
+    /// Upper bound on the total size of cached compressed files, in bytes
+    pub cache_limit: usize,
+
+    pub brotli_quality: u32,
+    pub gzip_level: u32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            precompressed: true,
+            on_the_fly: true,
+            min_size: 1024,
+            cache_limit: 64 * 1024 * 1024,
+            brotli_quality: 9,
+            gzip_level: 6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// Value of the `Content-Encoding` header
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Extension of precompressed siblings
+    pub fn extension(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
+        }
+    }
+
+    /// Path of the precompressed sibling of `path`
+    pub fn sibling(self, path: &Path) -> PathBuf {
+        let mut sibling = path.as_os_str().to_owned();
+        sibling.push(".");
+        sibling.push(self.extension());
+        PathBuf::from(sibling)
+    }
+
+    pub fn compress(self, data: &[u8], config: &CompressionConfig) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut out = Vec::with_capacity(data.len() / 3);
+                let params = brotli::enc::BrotliEncoderParams {
+                    quality: config.brotli_quality.min(11) as i32,
+                    ..Default::default()
+                };
+                brotli::BrotliCompress(&mut &data[..], &mut out, &params)?;
+                Ok(out)
+            }
+            Encoding::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::with_capacity(data.len() / 3), flate2::Compression::new(config.gzip_level.min(9)));
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// Parses an `Accept-Encoding` header into the supported encodings the client accepts, most preferred first
+pub fn negotiate(accept_encoding: Option<&str>) -> Vec<Encoding> {
+    let accept_encoding = match accept_encoding {
+        Some(value) => value,
+        None => return Vec::new(),
+    };
+
+    let mut brotli = None;
+    let mut gzip = None;
+    let mut wildcard = None;
+
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';');
+        let coding = parts.next().unwrap_or("").trim();
+
+        let q = parts
+            .filter_map(|param| {
+                let param = param.trim();
+                if param.starts_with("q=") || param.starts_with("Q=") {
+                    param[2..].trim().parse::<f32>().ok()
+                } else {
+                    None
+                }
+            })
+            .next()
+            .unwrap_or(1.0);
+
+        match coding.to_ascii_lowercase().as_str() {
+            "br" => brotli = Some(q),
+            "gzip" | "x-gzip" => gzip = Some(q),
+            "*" => wildcard = Some(q),
+            _ => {}
+        }
+    }
+
+    let mut accepted: Vec<(Encoding, f32)> = vec![(Encoding::Brotli, brotli.or(wildcard)), (Encoding::Gzip, gzip.or(wildcard))]
+        .into_iter()
+        .filter_map(|(encoding, q)| q.filter(|&q| q > 0.0).map(|q| (encoding, q)))
+        .collect();
+
+    // stable sort keeps brotli ahead of gzip when equally preferred
+    accepted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+    accepted.into_iter().map(|(encoding, _)| encoding).collect()
+}
+
+/// Whether compressing a file of this type is likely worth it
+pub fn is_compressible(mime: &Mime) -> bool {
+    match (mime.type_().as_str(), mime.subtype().as_str()) {
+        ("text", _) => true,
+        ("image", "svg") => true,
+        ("application", "javascript") | ("application", "json") | ("application", "wasm") | ("application", "xml") => true,
+        ("application", subtype) => subtype.ends_with("+xml") || subtype.ends_with("+json"),
+        _ => false,
+    }
+}
+
+struct CacheEntry {
+    modified: SystemTime,
+    data: Bytes,
+}
+
+#[derive(Default)]
+struct CacheState {
+    size: usize,
+    entries: HashMap<(PathBuf, Encoding), CacheEntry>,
+}
+
+/// Compressed copies of files, invalidated when the file's modification time changes
+pub struct CompressionCache {
+    limit: usize,
+    state: Mutex<CacheState>,
+}
+
+impl CompressionCache {
+    pub fn new(limit: usize) -> CompressionCache {
+        CompressionCache {
+            limit,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    pub fn get(&self, path: &Path, encoding: Encoding, modified: SystemTime) -> Option<Bytes> {
+        let state = self.state.lock().unwrap();
+
+        match state.entries.get(&(path.to_owned(), encoding)) {
+            Some(entry) if entry.modified == modified => Some(entry.data.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&self, path: &Path, encoding: Encoding, modified: SystemTime, data: Bytes) {
+        if data.len() > self.limit {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(old) = state.entries.remove(&(path.to_owned(), encoding)) {
+            state.size -= old.data.len();
+        }
+
+        // evict arbitrary entries until the new one fits
+        while state.size + data.len() > self.limit {
+            let key = match state.entries.keys().next() {
+                Some(key) => key.clone(),
+                None => break,
+            };
+
+            if let Some(old) = state.entries.remove(&key) {
+                state.size -= old.data.len();
+            }
+        }
+
+        state.size += data.len();
+        state.entries.insert((path.to_owned(), encoding), CacheEntry { modified, data });
+    }
+}
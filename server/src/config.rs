@@ -0,0 +1,550 @@
+//! Server configuration
+//!
+//! Values are resolved with the following precedence, highest first:
+//!
+//! 1. Command-line flags
+//! 2. `NOVA_*` environment variables
+//! 3. The TOML file given by `--config`/`NOVA_CONFIG`
+//! 4. Built-in defaults
+//!
+//! Relative paths inside the config file are resolved against the directory containing that file.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use structopt::StructOpt;
+
+use crate::analytics::AnalyticsConfig;
+use crate::blog::BlogConfig;
+use crate::cache::{CacheConfig, CachePolicy, CachePolicyError};
+use crate::compression::CompressionConfig;
+use crate::contact::ContactConfig;
+use crate::embed;
+use crate::feed::FeedConfig;
+use crate::fire::{Fire, FireConfig, FireError};
+use crate::logging::{LogFormat, LoggingConfig};
+use crate::metrics::MetricsConfig;
+use crate::projects::ProjectsConfig;
+use crate::ratelimit::{RateLimitConfig, RateLimitError, RateLimiter};
+use crate::security::{SecurityConfig, SecurityError, SecurityHeaders};
+use crate::sitemap::RobotsConfig;
+use crate::tls::TlsConfig;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "nova_website",
+    about = "Serves the Nova website",
+    after_help = "Every option can also be set with a NOVA_* environment variable named after it, like NOVA_TLS_CERT for --tls-cert, \
+                  except --log-level which is NOVA_LOG."
+)]
+pub struct Args {
+    /// Path to a TOML configuration file
+    #[structopt(short, long, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+
+    /// Address to bind the server to
+    #[structopt(short, long)]
+    pub address: Option<IpAddr>,
+
+    /// Port to bind the server to
+    #[structopt(short, long)]
+    pub port: Option<u16>,
+
+    /// Directory containing the built client bundle
+    #[structopt(short, long, parse(from_os_str))]
+    pub dist: Option<PathBuf>,
+
+    /// PEM certificate chain, enables HTTPS together with `--tls-key`
+    #[structopt(long, parse(from_os_str))]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key, enables HTTPS together with `--tls-cert`
+    #[structopt(long, parse(from_os_str))]
+    pub tls_key: Option<PathBuf>,
+
+    /// Port for a plain HTTP listener that redirects to HTTPS
+    #[structopt(long)]
+    pub redirect_port: Option<u16>,
+
+    /// Seconds to wait for open connections to finish after SIGINT/SIGTERM
+    #[structopt(long)]
+    pub drain_timeout: Option<u64>,
+
+    /// Log filter in `env_logger` syntax, e.g. `info` or `warn,access=info`
+    #[structopt(long)]
+    pub log_level: Option<String>,
+
+    /// Log output format: text, json or logfmt
+    #[structopt(long)]
+    pub log_format: Option<LogFormat>,
+
+    /// Serve `/metrics` on this port instead of the main listener
+    #[structopt(long)]
+    pub admin_port: Option<u16>,
+
+    /// Watch the dist directory and reload the browser when it changes
+    #[structopt(long)]
+    pub dev: bool,
+
+    /// Bearer token for `/api/stats` from NOVA_STATS_TOKEN, used when the config file has none
+    #[structopt(skip)]
+    pub stats_token: Option<String>,
+
+    /// SMTP password from NOVA_SMTP_PASSWORD, used when the config file has none
+    #[structopt(skip)]
+    pub smtp_password: Option<String>,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// `var(name)` converted by `parse`, with an error naming the variable when that fails. Empty variables are unset.
+fn env_value<T>(var: &dyn Fn(&str) -> Option<OsString>, name: &'static str, parse: fn(&OsString) -> Option<T>) -> Result<Option<T>, ConfigError> {
+    match var(name).filter(|value| !value.is_empty()) {
+        None => Ok(None),
+        Some(value) => parse(&value).map(Some).ok_or_else(|| ConfigError::Env(name, value.to_string_lossy().into_owned())),
+    }
+}
+
+/// `value`, or the variable `name` if the flag was not given
+fn or_env<T>(value: Option<T>, var: &dyn Fn(&str) -> Option<OsString>, name: &'static str, parse: fn(&OsString) -> Option<T>) -> Result<Option<T>, ConfigError> {
+    match value {
+        Some(value) => Ok(Some(value)),
+        None => env_value(var, name, parse),
+    }
+}
+
+fn parsed<T: FromStr>(value: &OsString) -> Option<T> {
+    value.to_str()?.parse().ok()
+}
+
+fn path(value: &OsString) -> Option<PathBuf> {
+    Some(PathBuf::from(value))
+}
+
+impl Args {
+    /// Takes the options that were not given as flags from the `NOVA_*` variables `var` looks up, which is
+    /// `std::env::var_os` except in tests
+    pub fn with_env(mut self, var: impl Fn(&str) -> Option<OsString>) -> Result<Args, ConfigError> {
+        let var: &dyn Fn(&str) -> Option<OsString> = &var;
+
+        self.config = or_env(self.config, var, "NOVA_CONFIG", path)?;
+        self.address = or_env(self.address, var, "NOVA_ADDRESS", parsed)?;
+        self.port = or_env(self.port, var, "NOVA_PORT", parsed)?;
+        self.dist = or_env(self.dist, var, "NOVA_DIST", path)?;
+        self.tls_cert = or_env(self.tls_cert, var, "NOVA_TLS_CERT", path)?;
+        self.tls_key = or_env(self.tls_key, var, "NOVA_TLS_KEY", path)?;
+        self.redirect_port = or_env(self.redirect_port, var, "NOVA_REDIRECT_PORT", parsed)?;
+        self.drain_timeout = or_env(self.drain_timeout, var, "NOVA_DRAIN_TIMEOUT", parsed)?;
+        self.log_level = or_env(self.log_level, var, "NOVA_LOG", parsed)?;
+        self.log_format = or_env(self.log_format, var, "NOVA_LOG_FORMAT", parsed)?;
+        self.admin_port = or_env(self.admin_port, var, "NOVA_ADMIN_PORT", parsed)?;
+        self.stats_token = env_value(var, "NOVA_STATS_TOKEN", parsed)?;
+        self.smtp_password = env_value(var, "NOVA_SMTP_PASSWORD", parsed)?;
+
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            return Err(ConfigError::TlsPair);
+        }
+
+        Ok(self)
+    }
+}
+
+#[derive(Debug, Clone, StructOpt)]
+pub enum Command {
+    /// Prerender every page into a static site that can be deployed without the server
+    Export {
+        /// Directory to write the site to, must be empty or not exist
+        #[structopt(parse(from_os_str))]
+        outdir: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub address: IpAddr,
+    pub port: u16,
+    pub dist: PathBuf,
+
+    /// Seconds to wait for open connections to finish during shutdown before dropping them
+    pub drain_timeout: u64,
+
+    /// Render the requested page into `index.html` instead of serving the empty shell. A no-JS fallback the client
+    /// replaces on mount, not hydrated.
+    pub ssr: bool,
+
+    /// Watch the dist directory and reload connected browsers on rebuilds, never enable in production
+    pub dev: bool,
+
+    pub tls: Option<TlsConfig>,
+    pub compression: CompressionConfig,
+    pub cache: CacheConfig,
+    pub security: SecurityConfig,
+    pub logging: LoggingConfig,
+    pub metrics: MetricsConfig,
+    pub blog: BlogConfig,
+    pub projects: ProjectsConfig,
+    pub contact: ContactConfig,
+    pub rate_limit: RateLimitConfig,
+    pub feed: FeedConfig,
+    pub robots: RobotsConfig,
+    pub analytics: AnalyticsConfig,
+    pub fire: FireConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 9009,
+            dist: PathBuf::from("../client/dist"),
+            drain_timeout: 30,
+            ssr: true,
+            dev: false,
+            tls: None,
+            compression: CompressionConfig::default(),
+            cache: CacheConfig::default(),
+            security: SecurityConfig::default(),
+            logging: LoggingConfig::default(),
+            metrics: MetricsConfig::default(),
+            blog: BlogConfig::default(),
+            projects: ProjectsConfig::default(),
+            contact: ContactConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            feed: FeedConfig::default(),
+            robots: RobotsConfig::default(),
+            analytics: AnalyticsConfig::default(),
+            fire: FireConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("unable to read config file {0:?}: {1}")]
+    Read(PathBuf, #[source] io::Error),
+
+    #[error("invalid config file {0:?}: {1}")]
+    Parse(PathBuf, #[source] toml::de::Error),
+
+    #[error("dist directory {0:?} does not exist or is not a directory")]
+    MissingDist(PathBuf),
+
+    #[error("dist directory {0:?} does not contain an index.html")]
+    MissingIndex(PathBuf),
+
+    #[error("invalid value {1:?} for {0}")]
+    Env(&'static str, String),
+
+    #[error("--tls-cert and --tls-key must be given together")]
+    TlsPair,
+
+    #[error("--redirect-port requires TLS to be configured")]
+    RedirectWithoutTls,
+
+    #[error("redirect port {0} is the same as the HTTPS port")]
+    RedirectPortConflict(u16),
+
+    #[error("admin port {0} is already used by another listener")]
+    AdminPortConflict(u16),
+
+    #[error("blog per_page {0} must be at least 1 and no more than max_per_page {1}")]
+    PerPage(usize, usize),
+
+    #[error("contact per_ip_per_hour must be at least 1, disable the form instead")]
+    ContactLimit,
+
+    #[error("feed base_url {0:?} must be an absolute http or https URL")]
+    FeedUrl(String),
+
+    #[error("invalid rate limit: {0}")]
+    RateLimit(#[from] RateLimitError),
+
+    #[error("invalid cache policy: {0}")]
+    CachePolicy(#[from] CachePolicyError),
+
+    #[error("invalid security headers: {0}")]
+    Security(#[from] SecurityError),
+
+    #[error("invalid shared fire: {0}")]
+    Fire(#[from] FireError),
+}
+
+impl Config {
+    /// Parses the process arguments and environment, then loads and validates the configuration
+    pub fn load() -> Result<(Config, Option<Command>), ConfigError> {
+        let mut args = Args::from_args().with_env(|name| std::env::var_os(name))?;
+        let command = args.command.take();
+
+        Ok((Config::from_args(args)?, command))
+    }
+
+    pub fn from_args(args: Args) -> Result<Config, ConfigError> {
+        let mut config = match args.config {
+            Some(ref path) => Config::from_file(path)?,
+            None => Config::default(),
+        };
+
+        if let Some(address) = args.address {
+            config.address = address;
+        }
+
+        if let Some(port) = args.port {
+            config.port = port;
+        }
+
+        if let Some(dist) = args.dist {
+            config.dist = dist;
+        }
+
+        if let Some(drain_timeout) = args.drain_timeout {
+            config.drain_timeout = drain_timeout;
+        }
+
+        if let Some(level) = args.log_level {
+            config.logging.level = level;
+        }
+
+        if let Some(format) = args.log_format {
+            config.logging.format = format;
+        }
+
+        if let Some(port) = args.admin_port {
+            config.metrics.admin_port = Some(port);
+        }
+
+        if args.dev {
+            config.dev = true;
+        }
+
+        if let (Some(cert), Some(key)) = (args.tls_cert, args.tls_key) {
+            let tls = config.tls.get_or_insert_with(TlsConfig::default);
+            tls.cert = cert;
+            tls.key = key;
+        }
+
+        if let Some(port) = args.redirect_port {
+            match config.tls {
+                Some(ref mut tls) => tls.redirect_port = Some(port),
+                None => return Err(ConfigError::RedirectWithoutTls),
+            }
+        }
+
+        // secrets only fill in for the config file, so that they need not be written into it
+        if config.analytics.token.is_none() {
+            config.analytics.token = args.stats_token;
+        }
+
+        if let Some(ref mut smtp) = config.contact.smtp {
+            if smtp.password.is_none() {
+                smtp.password = args.smtp_password;
+            }
+        }
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_owned(), e))?;
+        let mut config: Config = toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_owned(), e))?;
+
+        if let Some(base) = path.parent() {
+            config.dist = base.join(&config.dist);
+            config.blog.content = base.join(&config.blog.content);
+            config.projects.manifest = base.join(&config.projects.manifest);
+            config.contact.outbox = base.join(&config.contact.outbox);
+            config.analytics.database = base.join(&config.analytics.database);
+
+            if let Some(ref mut tls) = config.tls {
+                tls.cert = base.join(&tls.cert);
+                tls.key = base.join(&tls.key);
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        // an embedded client was checked when it was compiled in
+        if embed::files().is_none() {
+            if !self.dist.is_dir() {
+                return Err(ConfigError::MissingDist(self.dist.clone()));
+            }
+
+            if !self.index_path().is_file() {
+                return Err(ConfigError::MissingIndex(self.dist.clone()));
+            }
+        }
+
+        if let Some(ref tls) = self.tls {
+            if tls.redirect_port == Some(self.port) {
+                return Err(ConfigError::RedirectPortConflict(self.port));
+            }
+        }
+
+        if let Some(port) = self.metrics.admin_port {
+            let redirect_port = self.tls.as_ref().and_then(|tls| tls.redirect_port);
+
+            if port == self.port || Some(port) == redirect_port {
+                return Err(ConfigError::AdminPortConflict(port));
+            }
+        }
+
+        if !self.blog.is_valid() {
+            return Err(ConfigError::PerPage(self.blog.per_page, self.blog.max_per_page));
+        }
+
+        if self.contact.enabled && self.contact.per_ip_per_hour == 0 {
+            return Err(ConfigError::ContactLimit);
+        }
+
+        if !self.feed.is_valid() {
+            return Err(ConfigError::FeedUrl(self.feed.base_url.clone().unwrap_or_default()));
+        }
+
+        CachePolicy::new(&self.cache)?;
+        RateLimiter::new(&self.rate_limit)?;
+        SecurityHeaders::new(&self.security)?;
+        Fire::new(&self.fire)?;
+
+        Ok(())
+    }
+
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+
+    pub fn index_path(&self) -> PathBuf {
+        self.dist.join("index.html")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Temporary directory holding a config file and the dist directory it points at, removed when dropped
+    struct Fixture {
+        root: PathBuf,
+    }
+
+    impl Fixture {
+        fn new(config: &str) -> Fixture {
+            let root = std::env::temp_dir().join(format!("nova-test-{}", uuid::Uuid::new_v4().to_simple()));
+
+            fs::create_dir_all(root.join("site")).expect("create dist directory");
+            fs::write(root.join("site/index.html"), "<!doctype html>").expect("write index.html");
+            fs::write(root.join("nova.toml"), config).expect("write config file");
+
+            Fixture { root }
+        }
+
+        fn config(&self) -> PathBuf {
+            self.root.join("nova.toml")
+        }
+
+        /// Arguments parsed from `flags` and the variables in `env`, never the process environment
+        fn args(&self, flags: &[&str], env: &[(&str, &str)]) -> Result<Args, ConfigError> {
+            let config = self.config();
+            let mut args = vec!["nova_website", "--config", config.to_str().expect("temporary paths are UTF-8")];
+            args.extend_from_slice(flags);
+
+            Args::from_iter_safe(args).expect("valid arguments").with_env(|name| env.iter().find(|(key, _)| *key == name).map(|(_, value)| value.into()))
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[test]
+    fn flags_override_the_environment_which_overrides_the_file() {
+        let fixture = Fixture::new("port = 8000\ndist = \"site\"\ndrain_timeout = 5\n\n[logging]\nlevel = \"debug\"\n");
+        let env = [("NOVA_PORT", "8100"), ("NOVA_LOG", "warn")];
+
+        let config = Config::from_args(fixture.args(&[], &[]).expect("no environment")).expect("config from the file");
+
+        assert_eq!(config.port, 8000);
+        assert_eq!(config.drain_timeout, 5);
+        assert_eq!(config.logging.level, "debug");
+        assert_eq!(config.address, Config::default().address);
+
+        let config = Config::from_args(fixture.args(&[], &env).expect("valid environment")).expect("config from the environment");
+
+        assert_eq!(config.port, 8100);
+        assert_eq!(config.logging.level, "warn");
+        assert_eq!(config.drain_timeout, 5);
+
+        let config = Config::from_args(fixture.args(&["--port", "8200"], &env).expect("valid environment")).expect("config from the flags");
+
+        assert_eq!(config.port, 8200);
+        assert_eq!(config.logging.level, "warn");
+        assert_eq!(config.drain_timeout, 5);
+    }
+
+    #[test]
+    fn secrets_from_the_environment_fill_in_for_the_file() {
+        let fixture = Fixture::new("dist = \"site\"\n\n[contact.smtp]\nhost = \"smtp.example.com\"\n");
+        let env = [("NOVA_STATS_TOKEN", "from-env"), ("NOVA_SMTP_PASSWORD", "hunter2")];
+
+        let config = Config::from_args(fixture.args(&[], &env).expect("valid environment")).expect("config");
+
+        assert_eq!(config.analytics.token.as_deref(), Some("from-env"));
+        assert_eq!(config.contact.smtp.and_then(|smtp| smtp.password).as_deref(), Some("hunter2"));
+
+        let fixture = Fixture::new("dist = \"site\"\n\n[analytics]\ntoken = \"from-file\"\n");
+        let config = Config::from_args(fixture.args(&[], &env).expect("valid environment")).expect("config");
+
+        assert_eq!(config.analytics.token.as_deref(), Some("from-file"));
+    }
+
+    #[test]
+    fn invalid_environments_are_refused() {
+        let fixture = Fixture::new("dist = \"site\"\n");
+
+        assert!(matches!(fixture.args(&[], &[("NOVA_PORT", "eighty")]), Err(ConfigError::Env("NOVA_PORT", value)) if value == "eighty"));
+        assert!(matches!(fixture.args(&[], &[("NOVA_TLS_CERT", "cert.pem")]), Err(ConfigError::TlsPair)));
+
+        // a flag wins over a broken variable, and empty variables are unset
+        assert!(fixture.args(&["--port", "80"], &[("NOVA_PORT", "eighty")]).is_ok());
+        assert!(fixture.args(&[], &[("NOVA_PORT", "")]).expect("valid environment").port.is_none());
+
+        let args = fixture.args(&["--tls-cert", "cert.pem"], &[("NOVA_TLS_KEY", "key.pem")]).expect("a pair from both sources");
+        assert_eq!(args.tls_key, Some(PathBuf::from("key.pem")));
+    }
+
+    #[test]
+    fn relative_paths_are_resolved_against_the_file() {
+        let fixture = Fixture::new("dist = \"site\"\n\n[blog]\ncontent = \"posts\"\n\n[tls]\ncert = \"/etc/nova/cert.pem\"\n");
+
+        let config = Config::from_file(&fixture.config()).expect("config file");
+
+        assert_eq!(config.dist, fixture.root.join("site"));
+        assert_eq!(config.blog.content, fixture.root.join("posts"));
+        assert_eq!(config.projects.manifest, fixture.root.join("../content/projects.toml"));
+
+        let tls = config.tls.as_ref().expect("tls section");
+
+        assert_eq!(tls.cert, Path::new("/etc/nova/cert.pem"));
+        assert_eq!(tls.key, fixture.root.join("key.pem"));
+
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn unknown_and_missing_files_are_refused() {
+        let fixture = Fixture::new("dist = \"site\"\nprot = 8000\n");
+
+        assert!(matches!(Config::from_file(&fixture.config()), Err(ConfigError::Parse(..))));
+        assert!(matches!(Config::from_file(&fixture.root.join("missing.toml")), Err(ConfigError::Read(..))));
+    }
+}
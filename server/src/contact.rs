@@ -0,0 +1,613 @@
+//! Contact form at `POST /api/contact`
+//!
+//! Submissions are validated, checked for spam and delivered as plain text emails, to a maildir-style outbox
+//! directory by default or through SMTP when `[contact.smtp]` is configured.
+//!
+//! The form first fetches a token from `GET /api/contact` that records when it was issued. Submissions that fill
+//! the hidden `website` field or arrive within a few seconds of the token are dropped while appearing successful,
+//! so bots get no signal to adapt to.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac, NewMac};
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Address, Message, SmtpTransport, Transport};
+use nova_common::api::{ContactForm, Outcome, Token};
+use nova_common::Endpoint;
+use sha2::Sha256;
+use warp::http::{header, HeaderValue, StatusCode};
+use warp::{Filter, Rejection, Reply};
+
+use crate::ratelimit::{self, bucket_key, TrustedProxies};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContactConfig {
+    pub enabled: bool,
+
+    /// Recipient of the messages, and the address they are sent from
+    pub to: String,
+    pub from: String,
+
+    /// Maildir the messages are written to when SMTP is not configured
+    pub outbox: PathBuf,
+
+    pub smtp: Option<SmtpConfig>,
+
+    /// Submissions sooner than this after the form was loaded are treated as spam, in seconds
+    pub min_seconds: u64,
+
+    /// How long a form token stays valid, in seconds
+    pub max_age: u64,
+
+    /// Messages accepted from one IP address per hour
+    pub per_ip_per_hour: usize,
+}
+
+impl Default for ContactConfig {
+    fn default() -> Self {
+        ContactConfig {
+            enabled: true,
+            to: "contact@localhost".to_owned(),
+            from: "website@localhost".to_owned(),
+            outbox: PathBuf::from("../outbox"),
+            smtp: None,
+            min_seconds: 3,
+            max_age: 24 * 60 * 60,
+            per_ip_per_hour: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SmtpConfig {
+    pub host: String,
+
+    /// Defaults to 587 with STARTTLS, or 465 with implicit TLS
+    pub port: Option<u16>,
+
+    #[serde(default = "default_starttls")]
+    pub starttls: bool,
+
+    pub username: Option<String>,
+
+    /// Falls back to the NOVA_SMTP_PASSWORD environment variable
+    pub password: Option<String>,
+}
+
+fn default_starttls() -> bool {
+    true
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ContactError {
+    #[error("invalid contact address {0:?}: {1}")]
+    Address(String, #[source] lettre::address::AddressError),
+
+    #[error("unable to create the outbox {0:?}: {1}")]
+    Outbox(PathBuf, #[source] io::Error),
+
+    #[error("invalid SMTP relay {0:?}: {1}")]
+    Smtp(String, #[source] lettre::transport::smtp::Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DeliveryError {
+    #[error("unable to write {0:?}: {1}")]
+    Write(PathBuf, #[source] io::Error),
+
+    #[error("unable to send over SMTP: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+}
+
+/// Where accepted messages go. Called from a blocking thread.
+pub trait Backend: Send + Sync {
+    fn deliver(&self, message: &Message) -> Result<(), DeliveryError>;
+}
+
+/// Writes each message to `new/` of a maildir, through `tmp/` so readers never see partial files
+pub struct Outbox {
+    dir: PathBuf,
+}
+
+impl Outbox {
+    pub fn new(dir: &Path) -> Result<Outbox, ContactError> {
+        for sub in &["tmp", "new", "cur"] {
+            fs::create_dir_all(dir.join(sub)).map_err(|e| ContactError::Outbox(dir.to_owned(), e))?;
+        }
+
+        Ok(Outbox { dir: dir.to_owned() })
+    }
+}
+
+impl Backend for Outbox {
+    fn deliver(&self, message: &Message) -> Result<(), DeliveryError> {
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let name = format!("{}.{}.nova.eml", secs, uuid::Uuid::new_v4().to_simple());
+
+        let tmp = self.dir.join("tmp").join(&name);
+        let new = self.dir.join("new").join(&name);
+
+        fs::write(&tmp, message.formatted()).map_err(|e| DeliveryError::Write(tmp.clone(), e))?;
+        fs::rename(&tmp, &new).map_err(|e| DeliveryError::Write(new, e))
+    }
+}
+
+pub struct Smtp {
+    transport: SmtpTransport,
+}
+
+impl Smtp {
+    pub fn new(config: &SmtpConfig) -> Result<Smtp, ContactError> {
+        let relay = match config.starttls {
+            true => SmtpTransport::starttls_relay(&config.host),
+            false => SmtpTransport::relay(&config.host),
+        };
+
+        let mut builder = relay.map_err(|e| ContactError::Smtp(config.host.clone(), e))?;
+
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+
+        if let (Some(username), Some(password)) = (config.username.clone(), config.password.clone()) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Smtp { transport: builder.build() })
+    }
+}
+
+impl Backend for Smtp {
+    fn deliver(&self, message: &Message) -> Result<(), DeliveryError> {
+        self.transport.send(message)?;
+        Ok(())
+    }
+}
+
+/// Problems with a submission by field name, `form` for those not tied to a field
+pub type FieldErrors = BTreeMap<&'static str, &'static str>;
+
+fn has_control(text: &str) -> bool {
+    text.chars().any(char::is_control)
+}
+
+/// The sender's address, or what is wrong with the form
+pub fn validate(form: &ContactForm) -> Result<Address, FieldErrors> {
+    let mut errors = FieldErrors::new();
+
+    let name = form.name.trim();
+    if name.is_empty() {
+        errors.insert("name", "Please enter your name.");
+    } else if name.chars().count() > 100 || has_control(name) {
+        errors.insert("name", "Please enter a shorter name, on one line.");
+    }
+
+    let email = form.email.trim();
+    let address = match email.parse::<Address>() {
+        _ if email.is_empty() => Err("Please enter your email address."),
+        Ok(_) if email.len() > 254 => Err("This email address is too long."),
+        Ok(address) if address.domain().contains('.') => Ok(address),
+        _ => Err("Please enter a valid email address."),
+    };
+    if let Err(error) = address {
+        errors.insert("email", error);
+    }
+
+    let subject = form.subject.trim();
+    if subject.chars().count() > 150 || has_control(subject) {
+        errors.insert("subject", "Please enter a shorter subject, on one line.");
+    }
+
+    let length = form.message.trim().chars().count();
+    if length < 10 {
+        errors.insert("message", "Please write a little more.");
+    } else if length > 5000 {
+        errors.insert("message", "Please keep your message under 5000 characters.");
+    }
+
+    match address {
+        Ok(address) if errors.is_empty() => Ok(address),
+        _ => Err(errors),
+    }
+}
+
+/// Issues and checks form tokens, `{issued}.{mac}` where `issued` is in seconds since the epoch
+struct Tokens {
+    key: [u8; 32],
+}
+
+impl Tokens {
+    fn new() -> Tokens {
+        let mut key = [0; 32];
+        key[..16].copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+        key[16..].copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+
+        Tokens { key }
+    }
+
+    fn mac(&self, issued: u64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_varkey(&self.key).expect("HMAC accepts any key length");
+        mac.update(issued.to_string().as_bytes());
+        mac
+    }
+
+    fn issue(&self, now: u64) -> String {
+        let tag = self.mac(now).finalize().into_bytes();
+
+        format!("{}.{}", now, tag.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+    }
+
+    /// When a genuine token was issued
+    fn issued(&self, token: &str) -> Option<u64> {
+        let (issued, tag) = token.split_once('.')?;
+        let issued = issued.parse().ok()?;
+
+        if tag.len() != 64 || !tag.is_ascii() {
+            return None;
+        }
+
+        let tag = (0..64).step_by(2).map(|i| u8::from_str_radix(&tag[i..i + 2], 16)).collect::<Result<Vec<u8>, _>>().ok()?;
+
+        self.mac(issued).verify(&tag).ok().map(|_| issued)
+    }
+}
+
+/// Sliding one-hour window of accepted messages per IPv4 address or IPv6 /64, see [`bucket_key`]
+struct Limiter {
+    max: usize,
+    window: Duration,
+    hits: Mutex<HashMap<IpAddr, VecDeque<Instant>>>,
+}
+
+impl Limiter {
+    /// Records a message from `ip`, or returns how long until it would be accepted
+    fn check(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut hits = self.hits.lock().unwrap();
+
+        // forget addresses that have been quiet for a whole window
+        if hits.len() >= 1024 {
+            hits.retain(|_, times| times.back().is_some_and(|&last| now.duration_since(last) < self.window));
+        }
+
+        let times = hits.entry(bucket_key(ip)).or_default();
+
+        while times.front().is_some_and(|&first| now.duration_since(first) >= self.window) {
+            times.pop_front();
+        }
+
+        if times.len() >= self.max {
+            return Err(times.front().map_or(self.window, |&first| self.window - now.duration_since(first)));
+        }
+
+        times.push_back(now);
+        Ok(())
+    }
+}
+
+pub struct Contact {
+    config: ContactConfig,
+    to: Mailbox,
+    from: Mailbox,
+    backend: Box<dyn Backend>,
+    tokens: Tokens,
+    limiter: Limiter,
+}
+
+impl Contact {
+    pub fn new(config: ContactConfig) -> Result<Contact, ContactError> {
+        let mailbox = |address: &str| address.parse::<Address>().map_err(|e| ContactError::Address(address.to_owned(), e));
+
+        let to = Mailbox::new(None, mailbox(&config.to)?);
+        let from = Mailbox::new(Some(crate::render::SITE_NAME.to_owned()), mailbox(&config.from)?);
+
+        let backend: Box<dyn Backend> = match config.smtp {
+            Some(ref smtp) => Box::new(Smtp::new(smtp)?),
+            None => Box::new(Outbox::new(&config.outbox)?),
+        };
+
+        let limiter = Limiter {
+            max: config.per_ip_per_hour,
+            window: Duration::from_secs(60 * 60),
+            hits: Mutex::default(),
+        };
+
+        Ok(Contact {
+            config,
+            to,
+            from,
+            backend,
+            tokens: Tokens::new(),
+            limiter,
+        })
+    }
+
+    pub fn config(&self) -> &ContactConfig {
+        &self.config
+    }
+
+    fn message(&self, form: &ContactForm, reply_to: Address) -> Result<Message, lettre::error::Error> {
+        let subject = match form.subject.trim() {
+            "" => format!("Message from {}", form.name.trim()),
+            subject => subject.to_owned(),
+        };
+
+        Message::builder()
+            .from(self.from.clone())
+            .reply_to(Mailbox::new(Some(form.name.trim().to_owned()), reply_to))
+            .to(self.to.clone())
+            .subject(format!("[{}] {}", crate::render::SITE_NAME, subject))
+            .date_now()
+            .message_id(None)
+            .header(ContentType::TEXT_PLAIN)
+            .body(form.message.trim().to_owned())
+    }
+}
+
+fn respond<T: serde::Serialize>(value: &T, status: StatusCode) -> warp::reply::Response {
+    let mut res = warp::reply::with_status(warp::reply::json(value), status).into_response();
+    res.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    res
+}
+
+fn sent() -> warp::reply::Response {
+    let outcome = Outcome {
+        status: Some("sent".to_owned()),
+        errors: BTreeMap::new(),
+    };
+
+    respond(&outcome, StatusCode::ACCEPTED)
+}
+
+fn failed(status: StatusCode, errors: FieldErrors) -> warp::reply::Response {
+    let errors = errors.into_iter().map(|(field, error)| (field.to_owned(), error.to_owned())).collect();
+
+    respond(&Outcome { status: None, errors }, status)
+}
+
+fn form_error(status: StatusCode, error: &'static str) -> warp::reply::Response {
+    failed(status, std::iter::once(("form", error)).collect())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+async fn submit(contact: Arc<Contact>, form: ContactForm, client: Option<IpAddr>) -> warp::reply::Response {
+    let config = contact.config();
+
+    let address = match validate(&form) {
+        Ok(address) => address,
+        Err(errors) => return failed(StatusCode::UNPROCESSABLE_ENTITY, errors),
+    };
+
+    let issued = match contact.tokens.issued(&form.token) {
+        Some(issued) if unix_now().saturating_sub(issued) <= config.max_age => issued,
+        _ => return form_error(StatusCode::UNPROCESSABLE_ENTITY, "This form has expired, please send it again."),
+    };
+
+    if !form.website.is_empty() || unix_now().saturating_sub(issued) < config.min_seconds {
+        log::info!("dropped a contact message that looked automated");
+        return sent();
+    }
+
+    // every listener records the peer, so a request without one did not come through them and cannot be limited
+    let ip = match client {
+        Some(ip) => ip,
+        None => {
+            log::error!("refused a contact message without a client address");
+            return form_error(StatusCode::INTERNAL_SERVER_ERROR, "The message could not be sent, please try again later.");
+        }
+    };
+
+    if let Err(wait) = contact.limiter.check(ip, Instant::now()) {
+        let mut res = form_error(StatusCode::TOO_MANY_REQUESTS, "You have sent several messages already, please try again later.");
+        res.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(wait.as_secs().max(1)));
+        return res;
+    }
+
+    let message = match contact.message(&form, address) {
+        Ok(message) => message,
+        Err(e) => {
+            log::error!("unable to build a contact message: {}", e);
+            return form_error(StatusCode::INTERNAL_SERVER_ERROR, "The message could not be sent, please try again later.");
+        }
+    };
+
+    let delivered = tokio::task::spawn_blocking(move || contact.backend.deliver(&message)).await;
+
+    match delivered {
+        Ok(Ok(())) => {
+            log::info!("delivered a contact message");
+            sent()
+        }
+        Ok(Err(e)) => {
+            log::error!("unable to deliver a contact message: {}", e);
+            form_error(StatusCode::SERVICE_UNAVAILABLE, "The message could not be sent, please try again later.")
+        }
+        Err(e) => {
+            log::error!("contact delivery panicked: {}", e);
+            form_error(StatusCode::INTERNAL_SERVER_ERROR, "The message could not be sent, please try again later.")
+        }
+    }
+}
+
+/// `GET /api/contact` for a form token and `POST /api/contact` to send a message, or nothing when disabled
+pub fn routes(contact: Option<Arc<Contact>>, proxies: TrustedProxies) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    let contact = warp::any().and_then(move || {
+        let contact = contact.clone();
+
+        async move { contact.ok_or_else(warp::reject::not_found) }
+    });
+
+    let token = warp::get().and(contact.clone()).map(|contact: Arc<Contact>| respond(&Token { token: contact.tokens.issue(unix_now()) }, StatusCode::OK));
+
+    let post = warp::post()
+        .and(contact)
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::json())
+        .and(ratelimit::client_ip(proxies))
+        .and_then(|contact: Arc<Contact>, form: ContactForm, client: Option<IpAddr>| async move {
+            Ok::<_, Rejection>(submit(contact, form, client).await)
+        });
+
+    warp::path(Endpoint::PREFIX).and(warp::path(Endpoint::Contact.segment())).and(warp::path::end()).and(token.or(post).unify())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    /// Counts what would have been delivered
+    struct Recorder(Arc<Mutex<usize>>);
+
+    impl Backend for Recorder {
+        fn deliver(&self, _: &Message) -> Result<(), DeliveryError> {
+            *self.0.lock().unwrap() += 1;
+            Ok(())
+        }
+    }
+
+    fn contact() -> (Arc<Contact>, Arc<Mutex<usize>>) {
+        let config = ContactConfig::default();
+        let delivered = Arc::new(Mutex::new(0));
+
+        let contact = Contact {
+            to: config.to.parse().expect("valid recipient"),
+            from: config.from.parse().expect("valid sender"),
+            backend: Box::new(Recorder(delivered.clone())),
+            tokens: Tokens::new(),
+            limiter: Limiter {
+                max: config.per_ip_per_hour,
+                window: Duration::from_secs(60 * 60),
+                hits: Mutex::default(),
+            },
+            config,
+        };
+
+        (Arc::new(contact), delivered)
+    }
+
+    /// A valid form with a token issued `age` seconds ago
+    fn form(contact: &Contact, age: u64) -> ContactForm {
+        ContactForm {
+            name: "Ada".to_owned(),
+            email: "ada@example.com".to_owned(),
+            message: "Hello there, nice website.".to_owned(),
+            token: contact.tokens.issue(unix_now() - age),
+            ..ContactForm::default()
+        }
+    }
+
+    async fn send(contact: &Arc<Contact>, form: ContactForm) -> StatusCode {
+        submit(contact.clone(), form, Some(IpAddr::V4(Ipv4Addr::LOCALHOST))).await.status()
+    }
+
+    #[tokio::test]
+    async fn genuine_messages_are_delivered() {
+        let (contact, delivered) = contact();
+
+        assert_eq!(send(&contact, form(&contact, 10)).await, StatusCode::ACCEPTED);
+        assert_eq!(*delivered.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn spam_appears_sent_but_is_dropped() {
+        let (contact, delivered) = contact();
+
+        let honeypot = ContactForm {
+            website: "https://spam.example".to_owned(),
+            ..form(&contact, 10)
+        };
+
+        assert_eq!(send(&contact, honeypot).await, StatusCode::ACCEPTED);
+
+        // sooner than min_seconds after the form was loaded
+        assert_eq!(send(&contact, form(&contact, 0)).await, StatusCode::ACCEPTED);
+
+        assert_eq!(*delivered.lock().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn expired_and_forged_tokens_are_refused() {
+        let (contact, delivered) = contact();
+        let max_age = contact.config().max_age;
+
+        assert_eq!(send(&contact, form(&contact, max_age + 1)).await, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let forged = ContactForm {
+            token: format!("{}.{}", unix_now() - 10, "0".repeat(64)),
+            ..form(&contact, 10)
+        };
+
+        assert_eq!(send(&contact, forged).await, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(*delivered.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn limiter_slides_over_the_window() {
+        let limiter = Limiter {
+            max: 2,
+            window: Duration::from_secs(60),
+            hits: Mutex::default(),
+        };
+
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let other = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let start = Instant::now();
+
+        assert!(limiter.check(ip, start).is_ok());
+        assert!(limiter.check(ip, start + Duration::from_secs(20)).is_ok());
+        assert_eq!(limiter.check(ip, start + Duration::from_secs(30)), Err(Duration::from_secs(30)));
+        assert!(limiter.check(other, start + Duration::from_secs(30)).is_ok());
+
+        // the first message has left the window
+        assert!(limiter.check(ip, start + Duration::from_secs(60)).is_ok());
+        assert!(limiter.check(ip, start + Duration::from_secs(61)).is_err());
+    }
+
+    #[test]
+    fn limiter_of_zero_refuses_without_panicking() {
+        let limiter = Limiter {
+            max: 0,
+            window: Duration::from_secs(60),
+            hits: Mutex::default(),
+        };
+
+        assert_eq!(limiter.check(IpAddr::V4(Ipv4Addr::LOCALHOST), Instant::now()), Err(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn limiter_counts_an_ipv6_64_as_one_client() {
+        let limiter = Limiter {
+            max: 1,
+            window: Duration::from_secs(60),
+            hits: Mutex::default(),
+        };
+
+        let ip = |ip: &str| ip.parse::<IpAddr>().expect("valid address");
+        let now = Instant::now();
+
+        assert!(limiter.check(ip("2001:db8::1"), now).is_ok());
+        assert!(limiter.check(ip("2001:db8::ffff:2"), now).is_err());
+        assert!(limiter.check(ip("2001:db8:0:1::1"), now).is_ok());
+    }
+
+    #[tokio::test]
+    async fn messages_without_a_client_address_are_refused() {
+        let (contact, delivered) = contact();
+
+        assert_eq!(submit(contact.clone(), form(&contact, 10), None).await.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(*delivered.lock().unwrap(), 0);
+    }
+}
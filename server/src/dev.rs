@@ -0,0 +1,196 @@
+//! Development mode, enabled with `--dev`
+//!
+//! The dist directory is polled for changes and every page gets a small script that listens on the
+//! `/__livereload` WebSocket. Once a rebuild settles, the browser swaps its stylesheets if only CSS changed and
+//! reloads the page otherwise.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use futures::{SinkExt, StreamExt};
+use tokio::sync::broadcast;
+use warp::http::{header, HeaderValue};
+use warp::ws::{Message, WebSocket, Ws};
+use warp::{Filter, Rejection, Reply};
+
+use crate::shutdown::Shutdown;
+
+/// Served at `/__livereload.js`, an inline script would be blocked by the content security policy
+pub const SCRIPT: &str = include_str!("livereload.js");
+
+/// Tag inserted at the end of the body of every rendered page
+pub const SCRIPT_TAG: &str = r#"<script src="/__livereload.js"></script>"#;
+
+/// How often the dist directory is checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Length and modification time of every file below the dist directory, by relative path
+type Signature = HashMap<String, (u64, Option<SystemTime>)>;
+
+fn scan(root: &Path, dir: &Path, signature: &mut Signature) {
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+
+        if metadata.is_dir() {
+            scan(root, &path, signature);
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let name = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+            signature.insert(name, (metadata.len(), metadata.modified().ok()));
+        }
+    }
+}
+
+/// Paths that were added, removed or modified between two scans
+fn changes(old: &Signature, new: &Signature) -> Vec<String> {
+    let mut changed: Vec<String> = new.iter().filter(|(path, meta)| old.get(*path) != Some(meta)).map(|(path, _)| path.clone()).collect();
+    changed.extend(old.keys().filter(|path| !new.contains_key(*path)).cloned());
+    changed.sort();
+    changed
+}
+
+/// What the browser should do about a set of changed files, as sent over the socket
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Change {
+    Reload,
+    Css { paths: Vec<String> },
+}
+
+impl Change {
+    fn new(changed: Vec<String>) -> Change {
+        // precompressed siblings change along with the stylesheet itself
+        let stylesheets = changed.iter().map(|path| path.trim_end_matches(".gz").trim_end_matches(".br")).all(|path| path.ends_with(".css"));
+
+        if !stylesheets {
+            return Change::Reload;
+        }
+
+        let mut paths: Vec<String> = changed.iter().filter(|path| path.ends_with(".css")).cloned().collect();
+        paths.dedup();
+
+        Change::Css { paths }
+    }
+}
+
+pub struct LiveReload {
+    dist: PathBuf,
+    changes: broadcast::Sender<Arc<str>>,
+}
+
+impl LiveReload {
+    pub fn new(dist: &Path) -> LiveReload {
+        let (changes, _) = broadcast::channel(16);
+
+        LiveReload { dist: dist.to_owned(), changes }
+    }
+
+    fn signature(&self) -> Signature {
+        let mut signature = Signature::new();
+        scan(&self.dist, &self.dist, &mut signature);
+        signature
+    }
+
+    /// Spawns a task that polls the dist directory and notifies the browsers once a change has settled
+    pub fn watch(self: Arc<Self>) {
+        log::info!("dev mode: watching {:?} for changes", self.dist);
+
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+        tokio::spawn(async move {
+            let scan = |reload: Arc<LiveReload>| async move { tokio::task::spawn_blocking(move || reload.signature()).await.unwrap_or_default() };
+
+            let mut last = scan(self.clone()).await;
+            let mut pending: Vec<String> = Vec::new();
+
+            loop {
+                interval.tick().await;
+
+                let current = scan(self.clone()).await;
+                let changed = changes(&last, &current);
+                last = current;
+
+                // a build writes files over a while, so wait for a quiet poll before telling anyone
+                if !changed.is_empty() {
+                    pending.extend(changed);
+                    continue;
+                }
+
+                if pending.is_empty() {
+                    continue;
+                }
+
+                pending.sort();
+                pending.dedup();
+
+                let change = Change::new(std::mem::take(&mut pending));
+                log::info!("dev mode: dist changed, sending {:?}", change);
+
+                let _ = self.changes.send(serde_json::to_string(&change).expect("serializable change").into());
+            }
+        });
+    }
+}
+
+/// Forwards changes to one browser until either side goes away
+async fn client(socket: WebSocket, mut changes: broadcast::Receiver<Arc<str>>, shutdown: Shutdown) {
+    let (mut tx, mut rx) = socket.split();
+    let shutdown = shutdown.wait();
+    futures::pin_mut!(shutdown);
+
+    loop {
+        tokio::select! {
+            change = changes.recv() => {
+                let text = match change {
+                    Ok(text) => text.to_string(),
+                    // missed some changes, so reload to be safe
+                    Err(broadcast::RecvError::Lagged(_)) => r#"{"type":"reload"}"#.to_owned(),
+                    Err(broadcast::RecvError::Closed) => break,
+                };
+
+                if tx.send(Message::text(text)).await.is_err() {
+                    break;
+                }
+            }
+            message = rx.next() => match message {
+                Some(Ok(message)) if !message.is_close() => {}
+                _ => break,
+            },
+            _ = &mut shutdown => break,
+        }
+    }
+
+    let _ = tx.close().await;
+}
+
+/// `/__livereload` and `/__livereload.js`, or nothing outside of dev mode
+pub fn routes(reload: Option<Arc<LiveReload>>, shutdown: Shutdown) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    let reload = warp::any().and_then(move || {
+        let reload = reload.clone();
+
+        async move { reload.ok_or_else(warp::reject::not_found) }
+    });
+
+    let socket = warp::path!("__livereload").and(reload.clone()).and(warp::ws()).map(move |reload: Arc<LiveReload>, ws: Ws| {
+        let changes = reload.changes.subscribe();
+        let shutdown = shutdown.clone();
+
+        ws.on_upgrade(move |socket| client(socket, changes, shutdown)).into_response()
+    });
+
+    let script = warp::path!("__livereload.js").and(warp::get()).and(reload).map(|_| {
+        let mut res = SCRIPT.into_response();
+        res.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("application/javascript; charset=utf-8"));
+        res.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        res
+    });
+
+    socket.or(script).unify()
+}
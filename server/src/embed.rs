@@ -0,0 +1,41 @@
+//! The client bundle compiled into the binary, with the `embed` feature
+//!
+//! `build.rs` reads the dist directory (`NOVA_EMBED_DIST`, or `../client/dist`) when the server is compiled and
+//! generates a table of its files with their content types, ETags and compressed variants, so a release binary
+//! can be deployed on its own. Without the feature [`files`] is `None` and everything is read from disk.
+
+use std::time::SystemTime;
+
+/// A file of the dist directory, path relative to it with `/` separators
+#[derive(Debug)]
+pub struct EmbeddedFile {
+    pub path: &'static str,
+    pub mime: &'static str,
+
+    /// Hash of `data`, as [`content_hash`](crate::assets::content_hash) computes it
+    pub hash: &'static str,
+
+    pub data: &'static [u8],
+
+    /// Compressed when the file was precompressed in dist or compression made it smaller
+    pub gzip: Option<&'static [u8]>,
+    pub brotli: Option<&'static [u8]>,
+}
+
+#[cfg(feature = "embed")]
+mod generated {
+    use super::EmbeddedFile;
+
+    include!(concat!(env!("OUT_DIR"), "/embedded.rs"));
+}
+
+/// The embedded files and when they were embedded, if the binary has any
+#[cfg(feature = "embed")]
+pub fn files() -> Option<(&'static [EmbeddedFile], SystemTime)> {
+    Some((generated::FILES, std::time::UNIX_EPOCH + std::time::Duration::from_secs(generated::EMBEDDED_AT)))
+}
+
+#[cfg(not(feature = "embed"))]
+pub fn files() -> Option<(&'static [EmbeddedFile], SystemTime)> {
+    None
+}
@@ -0,0 +1,188 @@
+//! Error responses for requests the routes turned down
+//!
+//! Rejections are mapped to a status and answered with a small page in the site's style, or with an RFC 7807
+//! problem document (`application/problem+json`) for clients that ask for JSON. Without a preference either way,
+//! paths below `/api/` get JSON and everything else HTML.
+
+use std::time::Duration;
+
+use warp::http::{header, HeaderMap, HeaderValue, StatusCode};
+use warp::path::FullPath;
+use warp::reject::Reject;
+use warp::{Filter, Rejection, Reply};
+
+use crate::metrics::RouteClass;
+use crate::render::{escape, SITE_NAME};
+
+/// The client made too many requests and may try again after the given time
+#[derive(Debug)]
+pub struct RateLimited(pub Duration);
+
+impl Reject for RateLimited {}
+
+/// Something the request needs is broken on our side, like an unreadable `index.html`
+#[derive(Debug)]
+pub struct Internal;
+
+impl Reject for Internal {}
+
+/// What went wrong, ready to be written as HTML or JSON
+#[derive(Debug)]
+struct Problem {
+    status: StatusCode,
+    detail: &'static str,
+    retry_after: Option<u64>,
+}
+
+impl Problem {
+    fn new(status: StatusCode, detail: &'static str) -> Problem {
+        Problem { status, detail, retry_after: None }
+    }
+
+    fn from_rejection(rejection: &Rejection) -> Problem {
+        if let Some(RateLimited(wait)) = rejection.find() {
+            // whole seconds, rounded up so that retrying right on time succeeds
+            let seconds = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+
+            return Problem {
+                retry_after: Some(seconds.max(1)),
+                ..Problem::new(StatusCode::TOO_MANY_REQUESTS, "Too many requests, please slow down.")
+            };
+        }
+
+        if rejection.find::<Internal>().is_some() {
+            return Problem::new(StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong on our side, please try again later.");
+        }
+
+        if rejection.find::<warp::reject::PayloadTooLarge>().is_some() {
+            return Problem::new(StatusCode::PAYLOAD_TOO_LARGE, "The request body is too large.");
+        }
+
+        if rejection.find::<warp::reject::LengthRequired>().is_some() {
+            return Problem::new(StatusCode::LENGTH_REQUIRED, "The request body needs a Content-Length.");
+        }
+
+        if rejection.find::<warp::reject::UnsupportedMediaType>().is_some() {
+            return Problem::new(StatusCode::UNSUPPORTED_MEDIA_TYPE, "The request body has an unsupported content type.");
+        }
+
+        if rejection.find::<warp::body::BodyDeserializeError>().is_some() || rejection.find::<warp::reject::InvalidQuery>().is_some() {
+            return Problem::new(StatusCode::BAD_REQUEST, "The request could not be understood.");
+        }
+
+        if rejection.find::<warp::reject::InvalidHeader>().is_some() || rejection.find::<warp::reject::MissingHeader>().is_some() {
+            return Problem::new(StatusCode::BAD_REQUEST, "The request is missing a header or has an invalid one.");
+        }
+
+        if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+            return Problem::new(StatusCode::METHOD_NOT_ALLOWED, "This method is not allowed here.");
+        }
+
+        if rejection.is_not_found() {
+            return Problem::new(StatusCode::NOT_FOUND, "There is nothing here.");
+        }
+
+        log::error!("unhandled rejection: {:?}", rejection);
+
+        Problem::new(StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong on our side, please try again later.")
+    }
+
+    fn title(&self) -> &'static str {
+        self.status.canonical_reason().unwrap_or("Error")
+    }
+
+    fn json(&self, instance: &str) -> Vec<u8> {
+        let mut problem = serde_json::json!({
+            "type": "about:blank",
+            "title": self.title(),
+            "status": self.status.as_u16(),
+            "detail": self.detail,
+            "instance": instance,
+        });
+
+        if let Some(seconds) = self.retry_after {
+            problem["retry_after"] = seconds.into();
+        }
+
+        serde_json::to_vec(&problem).expect("serializable problem")
+    }
+
+    fn html(&self) -> String {
+        format!(
+            "<!doctype html>\n\
+             <html lang=\"en\">\n\
+             <head>\n\
+             \x20   <meta charset=\"utf-8\">\n\
+             \x20   <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">\n\
+             \x20   <title>{title} · {site}</title>\n\
+             \x20   <link rel=\"stylesheet\" href=\"/main.css\">\n\
+             </head>\n\
+             <body>\n\
+             \x20   <div class=\"container error-page\">\n\
+             \x20       <h1>{status} {title}</h1>\n\
+             \x20       <p>{detail}</p>\n\
+             \x20       <p><a href=\"/\">Back to {site}</a></p>\n\
+             \x20   </div>\n\
+             </body>\n\
+             </html>\n",
+            status = self.status.as_u16(),
+            title = escape(self.title()),
+            detail = escape(self.detail),
+            site = escape(SITE_NAME),
+        )
+    }
+}
+
+/// Whether the client gets a problem document rather than a page
+fn wants_json(path: &str, headers: &HeaderMap) -> bool {
+    let accept = headers.get(header::ACCEPT).and_then(|value| value.to_str().ok()).unwrap_or_default();
+
+    match (accept.contains("json"), accept.contains("text/html")) {
+        (true, false) => true,
+        (false, true) => false,
+        _ => path.starts_with("/api/"),
+    }
+}
+
+/// The error response for `rejection` of a request to `path`
+pub fn respond(rejection: &Rejection, path: &str, headers: &HeaderMap) -> warp::reply::Response {
+    let problem = Problem::from_rejection(rejection);
+
+    let (body, content_type) = match wants_json(path, headers) {
+        true => (problem.json(path), "application/problem+json"),
+        false => (problem.html().into_bytes(), "text/html; charset=utf-8"),
+    };
+
+    let mut res = warp::reply::with_status(body, problem.status).into_response();
+
+    res.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    res.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+
+    if let Some(seconds) = problem.retry_after {
+        res.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+    }
+
+    res.extensions_mut().insert(match problem.status {
+        StatusCode::TOO_MANY_REQUESTS => RouteClass::Limited,
+        _ => RouteClass::Unmatched,
+    });
+
+    res
+}
+
+/// Answers every rejection of `filter` with an error response, so nothing falls through to warp's plain text ones
+pub fn recover<F, R>(filter: F) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    let outcome = filter.map(|reply: R| Ok(reply.into_response())).or_else(|rejection| async { Ok::<_, Rejection>((Err(rejection),)) });
+
+    warp::path::full()
+        .and(warp::header::headers_cloned())
+        .and(outcome)
+        .map(|path: FullPath, headers: HeaderMap, outcome: Result<warp::reply::Response, Rejection>| match outcome {
+            Ok(res) => res,
+            Err(rejection) => respond(&rejection, path.as_str(), &headers),
+        })
+}
@@ -0,0 +1,391 @@
+//! Static site export
+//!
+//! Renders every page into its own `index.html` below the output directory, copies the rest of the dist directory
+//! alongside and writes `manifest.json` describing the result, so the site can be served by any static host.
+//! `robots.txt` is generated too, and so are `sitemap.xml` and the Atom and RSS feeds when `feed.base_url` says where
+//! the site will live. The per-tag feeds are left out, as nothing links to them.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use nova_common::Page;
+
+use crate::assets::{self, Assets};
+use crate::blog::Blog;
+use crate::cache::CachePolicy;
+use crate::config::Config;
+use crate::feed;
+use crate::projects::Projects;
+use crate::render::{self, Renderer};
+use crate::sitemap;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error("unable to write {0:?}: {1}")]
+    Write(PathBuf, #[source] io::Error),
+
+    #[error("unable to read {0:?}: {1}")]
+    Read(PathBuf, #[source] io::Error),
+
+    #[error("unable to render {0:?} from the index.html template")]
+    Render(String),
+
+    #[error("output directory {0:?} must not be inside the dist directory")]
+    InsideDist(PathBuf),
+
+    #[error("output directory {0:?} is not empty")]
+    NotEmpty(PathBuf),
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestPage {
+    pub route: String,
+    pub file: String,
+    pub title: String,
+    pub status: u16,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestFile {
+    pub file: String,
+    pub size: u64,
+    pub hash: String,
+    pub content_type: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    pub version: &'static str,
+    pub pages: Vec<ManifestPage>,
+    pub files: Vec<ManifestFile>,
+}
+
+fn relative(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+
+    relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+fn write(path: &Path, data: &[u8]) -> Result<(), ExportError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| ExportError::Write(parent.to_owned(), e))?;
+    }
+
+    fs::write(path, data).map_err(|e| ExportError::Write(path.to_owned(), e))
+}
+
+/// Every file below `dir`, depth first
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), ExportError> {
+    let entries = fs::read_dir(dir).map_err(|e| ExportError::Read(dir.to_owned(), e))?;
+
+    for entry in entries {
+        let path = entry.map_err(|e| ExportError::Read(dir.to_owned(), e))?.path();
+
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Canonical form of a path that may not exist yet, by resolving its nearest existing ancestor
+fn canonical_target(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut rest = Vec::new();
+
+    loop {
+        if let Ok(canonical) = existing.canonicalize() {
+            return rest.iter().rev().fold(canonical, |path, name| path.join(name));
+        }
+
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name.to_owned());
+                existing = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+            }
+            _ => return path.to_owned(),
+        }
+    }
+}
+
+/// File a page is written to, relative to the output directory
+fn page_file(page: &Page) -> String {
+    match page {
+        Page::NotFound(_) => "404.html".to_owned(),
+        page => match page.path().trim_matches('/') {
+            "" => "index.html".to_owned(),
+            path => format!("{}/index.html", path),
+        },
+    }
+}
+
+/// Exports the site to `outdir`, which must be empty or not exist yet
+pub async fn export(config: &Config, outdir: &Path) -> Result<Manifest, ExportError> {
+    let dist = config.dist.canonicalize().map_err(|e| ExportError::Read(config.dist.clone(), e))?;
+
+    if outdir.exists() {
+        let mut entries = fs::read_dir(outdir).map_err(|e| ExportError::Read(outdir.to_owned(), e))?;
+
+        if entries.next().is_some() {
+            return Err(ExportError::NotEmpty(outdir.to_owned()));
+        }
+    }
+
+    if canonical_target(outdir).starts_with(&dist) {
+        return Err(ExportError::InsideDist(outdir.to_owned()));
+    }
+
+    fs::create_dir_all(outdir).map_err(|e| ExportError::Write(outdir.to_owned(), e))?;
+
+    let policy = CachePolicy::new(&config.cache).expect("validated cache policy");
+    let assets = Arc::new(Assets::new(&dist, config.compression.clone(), policy));
+    let blog = Arc::new(Blog::new(config.blog.clone()));
+    let projects = Arc::new(Projects::new(config.projects.clone()));
+    let base = feed::base_url(&config.feed);
+    let feeds = base.map(|_| config.feed.title.clone());
+    let renderer = Renderer::new(assets.clone(), blog.clone(), projects.clone(), feeds, true, false);
+
+    let mut manifest = Manifest {
+        version: env!("CARGO_PKG_VERSION"),
+        pages: Vec::new(),
+        files: Vec::new(),
+    };
+
+    let mut pages = render::pages(&blog.index(), &projects.index());
+    pages.push(Page::NotFound(String::new()));
+
+    for page in &pages {
+        let (document, html) = renderer.render(page).await.ok_or_else(|| ExportError::Render(page.path()))?;
+        let file = page_file(page);
+
+        write(&outdir.join(&file), html.as_bytes())?;
+
+        manifest.pages.push(ManifestPage {
+            route: page.path(),
+            title: document.title,
+            status: document.status.as_u16(),
+            file,
+        });
+    }
+
+    let mut generated = vec![("robots.txt", sitemap::robots(&config.robots, base))];
+
+    match base {
+        Some(base) => {
+            generated.push(("sitemap.xml", sitemap::sitemap(&blog.index(), &projects.index(), base)));
+            generated.push(("feed.atom", feed::atom(&blog.index(), None, &config.feed, base)));
+            generated.push(("feed.xml", feed::rss(&blog.index(), &config.feed, base)));
+        }
+        None => log::warn!("not exporting the feeds and sitemap.xml, they need feed.base_url for absolute URLs"),
+    }
+
+    let mut files = Vec::new();
+    walk(&dist, &mut files)?;
+    files.sort();
+
+    for path in files {
+        let name = relative(&dist, &path);
+
+        // the template and its compressed siblings are replaced by the rendered pages, and generated files win
+        if name == "index.html" || name.starts_with("index.html.") || generated.iter().any(|(file, _)| *file == name) {
+            continue;
+        }
+
+        let data = fs::read(&path).map_err(|e| ExportError::Read(path.clone(), e))?;
+
+        write(&outdir.join(&name), &data)?;
+
+        manifest.files.push(ManifestFile {
+            size: data.len() as u64,
+            hash: assets::content_hash(&data),
+            content_type: mime_guess::from_path(&path).first_or_octet_stream().to_string(),
+            cache_control: assets.cache_control(&name).and_then(|value| value.to_str().ok()).map(str::to_owned),
+            file: name,
+        });
+    }
+
+    for (name, body) in generated {
+        write(&outdir.join(name), body.as_bytes())?;
+
+        manifest.files.push(ManifestFile {
+            size: body.len() as u64,
+            hash: assets::content_hash(body.as_bytes()),
+            content_type: mime_guess::from_path(name).first_or_octet_stream().to_string(),
+            cache_control: assets.cache_control(name).and_then(|value| value.to_str().ok()).map(str::to_owned),
+            file: name.to_owned(),
+        });
+    }
+
+    let json = serde_json::to_vec_pretty(&manifest).expect("serializable manifest");
+    write(&outdir.join("manifest.json"), &json)?;
+
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::feed::FeedConfig;
+
+    const TEMPLATE: &str = r#"<html><head><title>Nova Dev</title></head><body><div id="app"></div></body></html>"#;
+
+    const PROJECTS: &str = r#"
+[[project]]
+slug = "doom-fire"
+title = "Doom Fire"
+description = "The PSX Doom fire effect."
+date = 2020-06-14
+"#;
+
+    /// Temporary directory holding a dist directory and a project manifest, removed when dropped
+    struct Fixture {
+        root: PathBuf,
+    }
+
+    impl Fixture {
+        fn new() -> Fixture {
+            let root = std::env::temp_dir().join(format!("nova-test-{}", uuid::Uuid::new_v4().to_simple()));
+            let fixture = Fixture { root };
+
+            fixture.write("dist/index.html", TEMPLATE);
+            fixture.write("dist/index.html.gz", "stale");
+            fixture.write("dist/main-1a2b3c4d5e6f7a8b.css", "body { color: #333; }");
+            fixture.write("dist/images/logo.svg", "<svg/>");
+            fixture.write("dist/robots.txt", "stale");
+            fixture.write("projects.toml", PROJECTS);
+
+            fixture
+        }
+
+        fn write(&self, path: &str, data: &str) {
+            let path = self.root.join(path);
+
+            fs::create_dir_all(path.parent().expect("fixture files are in a directory")).expect("create fixture directory");
+            fs::write(path, data).expect("write fixture file");
+        }
+
+        fn config(&self, base_url: Option<&str>) -> Config {
+            let mut config = Config {
+                dist: self.root.join("dist"),
+                feed: FeedConfig {
+                    base_url: base_url.map(str::to_owned),
+                    ..FeedConfig::default()
+                },
+                ..Config::default()
+            };
+
+            config.blog.content = self.root.join("content");
+            config.projects.manifest = self.root.join("projects.toml");
+            config
+        }
+
+        fn read(&self, path: &str) -> String {
+            fs::read_to_string(self.root.join(path)).unwrap_or_else(|e| panic!("{}: {}", path, e))
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[test]
+    fn pages_get_their_own_files() {
+        assert_eq!(page_file(&Page::Index), "index.html");
+        assert_eq!(page_file(&Page::Blog), "blog/index.html");
+        assert_eq!(page_file(&Page::Project("doom-fire".to_owned())), "portfolio/doom-fire/index.html");
+        assert_eq!(page_file(&Page::NotFound(String::new())), "404.html");
+    }
+
+    #[tokio::test]
+    async fn the_site_is_exported() {
+        let fixture = Fixture::new();
+        let manifest = export(&fixture.config(Some("https://nova.example/")), &fixture.root.join("out")).await.expect("export");
+
+        let pages: Vec<(&str, &str, u16)> = manifest.pages.iter().map(|page| (page.route.as_str(), page.file.as_str(), page.status)).collect();
+
+        assert_eq!(
+            pages,
+            vec![
+                ("/", "index.html", 200),
+                ("/portfolio", "portfolio/index.html", 200),
+                ("/about", "about/index.html", 200),
+                ("/blog", "blog/index.html", 200),
+                ("/contact", "contact/index.html", 200),
+                ("/portfolio/doom-fire", "portfolio/doom-fire/index.html", 200),
+                ("", "404.html", 404),
+            ]
+        );
+
+        assert!(fixture.read("out/portfolio/doom-fire/index.html").contains("<title>Doom Fire · Nova Dev</title>"));
+        assert!(fixture.read("out/404.html").contains("<title>Page not found · Nova Dev</title>"));
+        assert!(fixture.read("out/index.html").contains(r#"href="/feed.atom""#));
+
+        let files: Vec<(&str, Option<&str>)> = manifest.files.iter().map(|file| (file.file.as_str(), file.cache_control.as_deref())).collect();
+
+        assert_eq!(
+            files,
+            vec![
+                ("images/logo.svg", Some("no-cache")),
+                ("main-1a2b3c4d5e6f7a8b.css", Some("public, max-age=31536000, immutable")),
+                ("robots.txt", Some("no-cache")),
+                ("sitemap.xml", Some("no-cache")),
+                ("feed.atom", Some("no-cache")),
+                ("feed.xml", Some("no-cache")),
+            ]
+        );
+
+        let css = &manifest.files[1];
+
+        assert_eq!(css.size, 21);
+        assert_eq!(css.hash, assets::content_hash(b"body { color: #333; }"));
+        assert_eq!(css.content_type, "text/css");
+        assert_eq!(fixture.read("out/main-1a2b3c4d5e6f7a8b.css"), "body { color: #333; }");
+
+        // the template's compressed sibling would be served in place of the rendered index
+        assert!(!fixture.root.join("out/index.html.gz").exists());
+
+        assert!(fixture.read("out/robots.txt").contains("Sitemap: https://nova.example/sitemap.xml"));
+        assert!(fixture.read("out/feed.atom").contains("<feed"));
+
+        let json: serde_json::Value = serde_json::from_str(&fixture.read("out/manifest.json")).expect("manifest.json");
+
+        assert_eq!(json["pages"].as_array().map(Vec::len), Some(7));
+        assert_eq!(json["files"][1]["file"], "main-1a2b3c4d5e6f7a8b.css");
+    }
+
+    #[tokio::test]
+    async fn feeds_and_sitemap_need_a_base_url() {
+        let fixture = Fixture::new();
+        let manifest = export(&fixture.config(None), &fixture.root.join("out")).await.expect("export");
+
+        assert!(manifest.files.iter().all(|file| !matches!(file.file.as_str(), "sitemap.xml" | "feed.atom" | "feed.xml")));
+        assert!(!fixture.read("out/index.html").contains("feed.atom"));
+        assert!(!fixture.read("out/robots.txt").contains("Sitemap"));
+    }
+
+    #[tokio::test]
+    async fn occupied_and_nested_outputs_are_refused() {
+        let fixture = Fixture::new();
+        let config = fixture.config(None);
+
+        fixture.write("out/keep.txt", "mine");
+
+        assert!(matches!(export(&config, &fixture.root.join("out")).await, Err(ExportError::NotEmpty(_))));
+        assert_eq!(fixture.read("out/keep.txt"), "mine");
+
+        assert!(matches!(export(&config, &fixture.root.join("dist/out")).await, Err(ExportError::InsideDist(_))));
+        assert!(matches!(export(&config, &fixture.root.join("dist/../dist/new/deeper")).await, Err(ExportError::InsideDist(_))));
+        assert!(!fixture.root.join("dist/out").exists());
+    }
+}
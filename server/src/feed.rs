@@ -0,0 +1,498 @@
+//! Atom and RSS feeds of the blog
+//!
+//! `/feed.atom` and `/feed.xml` carry the newest posts with their full content, `/tags/{tag}/feed.atom` only the posts
+//! with that tag. Links need an absolute URL, so the feeds are only served when `base_url` is configured. Taking it
+//! from the request's `Host` header instead would let anyone put their own host into responses that caches keep.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use hyper::body::Bytes;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use warp::http::{HeaderMap, Uri};
+use warp::{Filter, Rejection};
+
+use crate::assets::Assets;
+use crate::blog::{Blog, Index, Post};
+use crate::render::{escape, SITE_NAME};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeedConfig {
+    /// Public URL of the site like `https://nova.dev`, used for the links and ids in the feeds
+    pub base_url: Option<String>,
+
+    pub title: String,
+    pub author: String,
+
+    /// Number of posts in each feed
+    pub limit: usize,
+}
+
+impl Default for FeedConfig {
+    fn default() -> Self {
+        FeedConfig {
+            base_url: None,
+            title: SITE_NAME.to_owned(),
+            author: SITE_NAME.to_owned(),
+            limit: 20,
+        }
+    }
+}
+
+impl FeedConfig {
+    /// Whether `base_url` is unset or an absolute http(s) URL
+    pub fn is_valid(&self) -> bool {
+        self.base_url.as_deref().is_none_or(|url| {
+            url.parse::<Uri>()
+                .is_ok_and(|uri| matches!(uri.scheme_str(), Some("http") | Some("https")) && uri.authority().is_some() && uri.query().is_none())
+        })
+    }
+}
+
+/// Characters left alone when putting a tag into a path segment
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.').remove(b'~');
+
+/// Atom timestamps, RFC 3339 in UTC
+fn rfc3339(date: &DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Absolute URLs of the site, without a trailing slash on `base`
+struct Site<'a> {
+    config: &'a FeedConfig,
+    base: &'a str,
+}
+
+impl Site<'_> {
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base, path)
+    }
+
+    fn post_url(&self, post: &Post) -> String {
+        self.url(&format!("/blog/{}", utf8_percent_encode(&post.summary.slug, SEGMENT)))
+    }
+
+    fn title(&self, tag: Option<&str>) -> String {
+        match tag {
+            Some(tag) => format!("{} · {}", self.config.title, tag),
+            None => self.config.title.clone(),
+        }
+    }
+}
+
+/// Posts going into a feed, newest first
+fn entries<'a>(index: &'a Index, tag: Option<&'a str>, limit: usize) -> Vec<&'a Arc<Post>> {
+    index.tagged(tag).take(limit).collect()
+}
+
+/// Latest revision of any entry, or the epoch for an empty feed so the document stays stable
+fn last_updated(posts: &[&Arc<Post>]) -> DateTime<Utc> {
+    posts.iter().map(|post| post.updated).max().unwrap_or_else(|| Utc.timestamp_opt(0, 0).unwrap())
+}
+
+/// Atom 1.0 document, see RFC 4287
+pub fn atom(index: &Index, tag: Option<&str>, config: &FeedConfig, base: &str) -> String {
+    let site = Site { config, base };
+    let posts = entries(index, tag, config.limit);
+
+    let self_url = match tag {
+        Some(tag) => site.url(&format!("/tags/{}/feed.atom", utf8_percent_encode(tag, SEGMENT))),
+        None => site.url("/feed.atom"),
+    };
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+
+    out.push_str(&format!("<feed xmlns=\"http://www.w3.org/2005/Atom\" xml:base=\"{}/\">\n", escape(base)));
+    out.push_str(&format!("  <id>{}</id>\n", escape(&self_url)));
+    out.push_str(&format!("  <title>{}</title>\n", escape(&site.title(tag))));
+    out.push_str(&format!("  <updated>{}</updated>\n", rfc3339(&last_updated(&posts))));
+    out.push_str(&format!("  <link rel=\"self\" type=\"application/atom+xml\" href=\"{}\"/>\n", escape(&self_url)));
+    out.push_str(&format!("  <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n", escape(&site.url("/blog"))));
+    out.push_str(&format!("  <author><name>{}</name></author>\n", escape(&config.author)));
+    out.push_str(&format!("  <generator version=\"{}\">nova_website</generator>\n", env!("CARGO_PKG_VERSION")));
+
+    for post in posts {
+        let url = site.post_url(post);
+
+        out.push_str("  <entry>\n");
+        out.push_str(&format!("    <id>{}</id>\n", escape(&url)));
+        out.push_str(&format!("    <title>{}</title>\n", escape(&post.summary.title)));
+        out.push_str(&format!("    <published>{}</published>\n", rfc3339(&post.summary.date)));
+        out.push_str(&format!("    <updated>{}</updated>\n", rfc3339(&post.updated)));
+        out.push_str(&format!("    <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n", escape(&url)));
+
+        for tag in &post.summary.tags {
+            out.push_str(&format!("    <category term=\"{}\"/>\n", escape(tag)));
+        }
+
+        out.push_str(&format!("    <summary>{}</summary>\n", escape(&post.summary.summary)));
+        out.push_str(&format!("    <content type=\"html\" xml:base=\"{}\">{}</content>\n", escape(&url), escape(&post.html)));
+        out.push_str("  </entry>\n");
+    }
+
+    out.push_str("</feed>\n");
+    out
+}
+
+/// RSS 2.0 document of all posts
+pub fn rss(index: &Index, config: &FeedConfig, base: &str) -> String {
+    let site = Site { config, base };
+    let posts = entries(index, None, config.limit);
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+
+    out.push_str("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\n<channel>\n");
+    out.push_str(&format!("  <title>{}</title>\n", escape(&site.title(None))));
+    out.push_str(&format!("  <link>{}</link>\n", escape(&site.url("/blog"))));
+    out.push_str(&format!("  <description>Posts from {}</description>\n", escape(&config.title)));
+    out.push_str(&format!("  <atom:link rel=\"self\" type=\"application/rss+xml\" href=\"{}\"/>\n", escape(&site.url("/feed.xml"))));
+    out.push_str(&format!("  <lastBuildDate>{}</lastBuildDate>\n", last_updated(&posts).to_rfc2822()));
+    out.push_str(&format!("  <generator>nova_website {}</generator>\n", env!("CARGO_PKG_VERSION")));
+
+    for post in posts {
+        let url = site.post_url(post);
+
+        out.push_str("  <item>\n");
+        out.push_str(&format!("    <title>{}</title>\n", escape(&post.summary.title)));
+        out.push_str(&format!("    <link>{}</link>\n", escape(&url)));
+        out.push_str(&format!("    <guid isPermaLink=\"true\">{}</guid>\n", escape(&url)));
+        out.push_str(&format!("    <pubDate>{}</pubDate>\n", post.summary.date.to_rfc2822()));
+
+        for tag in &post.summary.tags {
+            out.push_str(&format!("    <category>{}</category>\n", escape(tag)));
+        }
+
+        out.push_str(&format!("    <description>{}</description>\n", escape(&post.html)));
+        out.push_str("  </item>\n");
+    }
+
+    out.push_str("</channel>\n</rss>\n");
+    out
+}
+
+/// `base_url` without a trailing slash, also used by the sitemap
+pub fn base_url(config: &FeedConfig) -> Option<&str> {
+    config.base_url.as_deref().map(|url| url.trim_end_matches('/'))
+}
+
+/// Which document a feed route serves
+#[derive(Debug)]
+enum Kind {
+    Atom(Option<String>),
+    Rss,
+}
+
+/// Serves the feeds, or nothing without a `base_url`
+pub fn routes(blog: Arc<Blog>, assets: Arc<Assets>, config: FeedConfig) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    let config = Arc::new(config);
+
+    let main = warp::path!("feed.atom").map(|| Kind::Atom(None));
+    let legacy = warp::path!("feed.xml").map(|| Kind::Rss);
+    let tagged = warp::path!("tags" / String / "feed.atom").map(|tag: String| Kind::Atom(Some(percent_decode_str(&tag).decode_utf8_lossy().into_owned())));
+
+    warp::get()
+        .or(warp::head())
+        .unify()
+        .and(main.or(legacy).unify().or(tagged).unify())
+        .and(warp::header::headers_cloned())
+        .and_then(move |kind: Kind, headers: HeaderMap| {
+            let blog = blog.clone();
+            let assets = assets.clone();
+            let config = config.clone();
+
+            async move {
+                let base = base_url(&config).ok_or_else(warp::reject::not_found)?;
+                let index = blog.index();
+
+                let (path, mime, body) = match kind {
+                    Kind::Atom(Some(ref tag)) if index.tagged(Some(tag)).next().is_none() => return Err(warp::reject::not_found()),
+                    Kind::Atom(tag) => ("feed.atom", "application/atom+xml; charset=utf-8", atom(&index, tag.as_deref(), &config, base)),
+                    Kind::Rss => ("feed.xml", "application/rss+xml; charset=utf-8", rss(&index, &config, base)),
+                };
+
+                let mime = mime.parse().expect("valid feed mime type");
+
+                Ok::<_, Rejection>(assets.serve_generated(path, mime, Bytes::from(body), &headers).await)
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    //! Checks the generated Atom against the rules of RFC 4287 section 4
+
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    use chrono::DateTime;
+    use roxmltree::{Document, Node};
+
+    use super::*;
+
+    const ATOM: &str = "http://www.w3.org/2005/Atom";
+    const BASE: &str = "https://nova.example";
+
+    fn post(slug: &str, front: &str, body: &str) -> Arc<Post> {
+        let source = format!("+++\n{}\n+++\n\n{}\n", front, body);
+
+        Arc::new(Post::parse(Path::new(&format!("posts/{}.md", slug)), &source).expect("valid test post"))
+    }
+
+    fn index() -> Index {
+        Index::new(vec![
+            post("hello-world", "title = \"Hello, World\"\ndate = 2020-07-01\ntags = [\"meta\", \"rust\"]", "First *post*."),
+            post(
+                "revised",
+                "title = \"Fish & <Chips>\"\ndate = 2020-07-03T10:00:00Z\nupdated = 2020-08-01T12:30:00+02:00\ntags = [\"rust\", \"c++ & co\"]",
+                "Has [a link](/blog/hello-world) and `code <b>`.\n\n| a | b |\n|---|---|\n| 1 | 2 |",
+            ),
+            post("older", "title = \"Older\"\ndate = 2019-12-24", "Unicode ✓ and \"quotes\"."),
+        ])
+    }
+
+    fn children<'a, 'input>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+        node.children().filter(move |child| child.is_element() && child.tag_name().namespace() == Some(ATOM) && child.tag_name().name() == name)
+    }
+
+    fn one<'a, 'input>(node: Node<'a, 'input>, name: &'a str) -> Node<'a, 'input> {
+        let mut found = children(node, name);
+        let first = found.next().unwrap_or_else(|| panic!("<{}> is missing from <{}>", name, node.tag_name().name()));
+
+        assert!(found.next().is_none(), "<{}> appears more than once in <{}>", name, node.tag_name().name());
+        first
+    }
+
+    fn at_most_one(node: Node, name: &str) {
+        assert!(children(node, name).count() <= 1, "<{}> appears more than once in <{}>", name, node.tag_name().name());
+    }
+
+    fn text<'a>(node: Node<'a, '_>) -> &'a str {
+        node.text().unwrap_or("")
+    }
+
+    /// atomDateConstruct: an RFC 3339 timestamp with an uppercase `T` and `Z`
+    fn date(node: Node) -> DateTime<chrono::FixedOffset> {
+        let value = text(node);
+
+        assert!(!value.contains('t') && !value.contains('z'), "{:?} must use uppercase T and Z", value);
+        DateTime::parse_from_rfc3339(value).unwrap_or_else(|e| panic!("{:?} is not RFC 3339: {}", value, e))
+    }
+
+    /// atomUri: an absolute IRI
+    fn absolute_iri(value: &str) {
+        let uri = value.parse::<Uri>().unwrap_or_else(|e| panic!("{:?} is not a URI: {}", value, e));
+
+        assert!(uri.scheme().is_some(), "{:?} is not absolute", value);
+    }
+
+    /// atomLink: `href` is required, and there is at most one alternate link per type and language
+    fn links(node: Node) {
+        let mut alternates = HashSet::new();
+
+        for link in children(node, "link") {
+            let href = link.attribute("href").expect("<link> without href");
+            absolute_iri(href);
+
+            if link.attribute("rel").unwrap_or("alternate") == "alternate" {
+                let key = (link.attribute("type"), link.attribute("hreflang"));
+                assert!(alternates.insert(key), "duplicate alternate link {:?} in <{}>", key, node.tag_name().name());
+            }
+        }
+    }
+
+    /// atomTextConstruct: `type` is text, html or xhtml, and html is escaped markup rather than child elements
+    fn text_construct(node: Node) {
+        let kind = node.attribute("type").unwrap_or("text");
+
+        assert!(matches!(kind, "text" | "html" | "xhtml"), "invalid text type {:?}", kind);
+
+        if kind != "xhtml" {
+            assert!(node.children().all(|child| !child.is_element()), "<{}> of type {} has child elements", node.tag_name().name(), kind);
+        }
+    }
+
+    /// Validates an Atom feed document, returning its entry ids
+    fn validate(xml: &str) -> Vec<String> {
+        let doc = Document::parse(xml).unwrap_or_else(|e| panic!("feed is not well-formed XML: {}\n{}", e, xml));
+        let feed = doc.root_element();
+
+        assert_eq!(feed.tag_name().namespace(), Some(ATOM));
+        assert_eq!(feed.tag_name().name(), "feed");
+
+        absolute_iri(text(one(feed, "id")));
+        text_construct(one(feed, "title"));
+        let feed_updated = date(one(feed, "updated"));
+
+        for name in &["generator", "icon", "logo", "rights", "subtitle"] {
+            at_most_one(feed, name);
+        }
+
+        links(feed);
+
+        let self_link = children(feed, "link").find(|link| link.attribute("rel") == Some("self"));
+        assert!(self_link.is_some(), "feeds should contain a self link");
+
+        let feed_author = children(feed, "author").next().is_some();
+        let mut ids = Vec::new();
+
+        for entry in children(feed, "entry") {
+            let id = text(one(entry, "id"));
+            absolute_iri(id);
+            ids.push(id.to_owned());
+
+            text_construct(one(entry, "title"));
+
+            let updated = date(one(entry, "updated"));
+            assert!(updated <= feed_updated, "entry {} updated after the feed", id);
+
+            if let Some(published) = children(entry, "published").next() {
+                at_most_one(entry, "published");
+                assert!(date(published) <= updated, "entry {} published after its last update", id);
+            }
+
+            assert!(feed_author || children(entry, "author").next().is_some(), "entry {} has no author", id);
+
+            for author in children(entry, "author").chain(children(feed, "author")) {
+                assert!(!text(one(author, "name")).is_empty());
+            }
+
+            for category in children(entry, "category") {
+                assert!(category.attribute("term").is_some_and(|term| !term.is_empty()), "category without term in {}", id);
+            }
+
+            links(entry);
+
+            let content = children(entry, "content").next();
+            at_most_one(entry, "content");
+            at_most_one(entry, "summary");
+
+            match content {
+                Some(content) => {
+                    assert!(content.attribute("src").is_none(), "content of {} should be inline", id);
+                    text_construct(content);
+                }
+                None => assert!(children(entry, "link").any(|link| link.attribute("rel").unwrap_or("alternate") == "alternate")),
+            }
+
+            if let Some(summary) = children(entry, "summary").next() {
+                text_construct(summary);
+            }
+        }
+
+        let unique: HashSet<_> = ids.iter().collect();
+        assert_eq!(unique.len(), ids.len(), "entry ids must be unique");
+
+        ids
+    }
+
+    #[test]
+    fn atom_feed_is_valid() {
+        let xml = atom(&index(), None, &FeedConfig::default(), BASE);
+        let ids = validate(&xml);
+
+        assert_eq!(
+            ids,
+            ["https://nova.example/blog/revised", "https://nova.example/blog/hello-world", "https://nova.example/blog/older"]
+        );
+    }
+
+    #[test]
+    fn feed_updated_is_the_latest_revision() {
+        let xml = atom(&index(), None, &FeedConfig::default(), BASE);
+        let doc = Document::parse(&xml).unwrap();
+        let feed = doc.root_element();
+
+        assert_eq!(text(one(feed, "updated")), "2020-08-01T10:30:00Z");
+
+        let revised = children(feed, "entry").next().unwrap();
+        assert_eq!(text(one(revised, "published")), "2020-07-03T10:00:00Z");
+        assert_eq!(text(one(revised, "updated")), "2020-08-01T10:30:00Z");
+
+        let unrevised = children(feed, "entry").nth(1).unwrap();
+        assert_eq!(text(one(unrevised, "published")), text(one(unrevised, "updated")));
+    }
+
+    #[test]
+    fn content_is_the_full_escaped_html() {
+        let index = index();
+        let xml = atom(&index, None, &FeedConfig::default(), BASE);
+        let doc = Document::parse(&xml).unwrap();
+        let entry = children(doc.root_element(), "entry").next().unwrap();
+
+        assert_eq!(text(one(entry, "title")), "Fish & <Chips>");
+        assert_eq!(one(entry, "content").attribute("type"), Some("html"));
+        assert_eq!(text(one(entry, "content")), index.get("revised").unwrap().html);
+        assert!(text(one(entry, "content")).contains("<table>"));
+    }
+
+    #[test]
+    fn tag_feed_only_has_tagged_posts() {
+        let xml = atom(&index(), Some("c++ & co"), &FeedConfig::default(), BASE);
+        let ids = validate(&xml);
+        let doc = Document::parse(&xml).unwrap();
+        let feed = doc.root_element();
+
+        assert_eq!(ids, ["https://nova.example/blog/revised"]);
+        assert_eq!(text(one(feed, "id")), "https://nova.example/tags/c%2B%2B%20%26%20co/feed.atom");
+        assert_eq!(text(one(feed, "title")), "Nova Dev · c++ & co");
+    }
+
+    #[test]
+    fn empty_feed_is_valid() {
+        let xml = atom(&Index::default(), None, &FeedConfig::default(), BASE);
+
+        assert!(validate(&xml).is_empty());
+    }
+
+    #[test]
+    fn limit_keeps_the_newest_posts() {
+        let config = FeedConfig {
+            limit: 1,
+            ..FeedConfig::default()
+        };
+
+        assert_eq!(validate(&atom(&index(), None, &config, BASE)), ["https://nova.example/blog/revised"]);
+    }
+
+    #[test]
+    fn rss_feed_is_well_formed() {
+        let xml = rss(&index(), &FeedConfig::default(), BASE);
+        let doc = Document::parse(&xml).unwrap_or_else(|e| panic!("feed is not well-formed XML: {}\n{}", e, xml));
+        let channel = doc.root_element().first_element_child().unwrap();
+        let items: Vec<_> = channel.children().filter(|node| node.has_tag_name("item")).collect();
+
+        assert_eq!(doc.root_element().attribute("version"), Some("2.0"));
+        assert_eq!(items.len(), 3);
+
+        for item in items {
+            let pub_date = item.children().find(|node| node.has_tag_name("pubDate")).and_then(|node| node.text()).unwrap();
+            assert!(DateTime::parse_from_rfc2822(pub_date).is_ok(), "{:?} is not RFC 2822", pub_date);
+        }
+    }
+
+    #[test]
+    fn base_url_only_comes_from_the_config() {
+        let config = FeedConfig {
+            base_url: Some("https://nova.example/".to_owned()),
+            ..FeedConfig::default()
+        };
+
+        assert_eq!(base_url(&config), Some("https://nova.example"));
+        assert_eq!(base_url(&FeedConfig::default()), None);
+    }
+
+    #[test]
+    fn base_url_must_be_absolute() {
+        let with = |url: &str| FeedConfig {
+            base_url: Some(url.to_owned()),
+            ..FeedConfig::default()
+        };
+
+        assert!(FeedConfig::default().is_valid());
+        assert!(with("https://nova.example").is_valid());
+        assert!(!with("nova.example").is_valid());
+        assert!(!with("/blog").is_valid());
+        assert!(!with("ftp://nova.example").is_valid());
+    }
+}
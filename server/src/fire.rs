@@ -0,0 +1,409 @@
+//! The shared DoomFire at `/ws/fire`
+//!
+//! While someone draws on the fire demo, their client sends the stroke of every tick over a WebSocket and the
+//! server relays it to everyone else in the same room, whose clients splat it onto their own fire. Rooms are made
+//! when the first visitor joins and dropped when the last one leaves. Both their size and their number are capped,
+//! and each connection may only send so many strokes per second; the rest are dropped.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use futures::{SinkExt, StreamExt};
+use nova_common::api::{FireEvent, FireQuery, Stroke};
+use nova_common::Socket;
+use tokio::sync::broadcast;
+use warp::ws::{Message, WebSocket, Ws};
+use warp::{Filter, Rejection, Reply};
+
+use crate::ratelimit::Bucket;
+use crate::shutdown::Shutdown;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FireConfig {
+    pub enabled: bool,
+
+    /// Visitors per room, anyone joining a full room is turned away
+    pub max_room_size: usize,
+
+    /// Rooms open at once, nobody can open another one beyond this
+    pub max_rooms: usize,
+
+    /// Strokes per second a connection may sustain, the client sends 30 while drawing
+    pub strokes_per_second: f64,
+
+    /// Strokes a connection may send at once
+    pub burst: u32,
+}
+
+impl Default for FireConfig {
+    fn default() -> Self {
+        FireConfig {
+            enabled: true,
+            max_room_size: 16,
+            max_rooms: 64,
+            strokes_per_second: 40.0,
+            burst: 80,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FireError {
+    #[error("the shared fire needs at least one room of at least one visitor")]
+    Size,
+
+    #[error("the shared fire needs a positive stroke rate and burst")]
+    Rate,
+}
+
+/// Room of visitors who do not ask for one
+const DEFAULT_ROOM: &str = "lobby";
+
+/// Longest message accepted, a stroke takes about 60 bytes
+const MAX_MESSAGE_SIZE: usize = 256;
+
+/// Messages a slow connection may fall behind by before it misses some
+const QUEUE: usize = 64;
+
+/// Close code for a full room, "try again later"
+const TRY_AGAIN_LATER: u16 = 1013;
+
+/// Close code for messages that are not strokes
+const UNSUPPORTED_DATA: u16 = 1003;
+
+/// Lowercase letters, digits and dashes, like content slugs
+fn valid_room(room: &str) -> bool {
+    (1..=32).contains(&room.len()) && room.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+}
+
+/// A serialized [`FireEvent`] for a room, not echoed back to the connection it is `from`
+#[derive(Debug, Clone)]
+struct Relay {
+    from: Option<u64>,
+    text: Arc<str>,
+}
+
+impl Relay {
+    fn new(from: Option<u64>, event: &FireEvent) -> Relay {
+        Relay {
+            from,
+            text: serde_json::to_string(event).expect("serializable event").into(),
+        }
+    }
+}
+
+struct Room {
+    members: usize,
+    relay: broadcast::Sender<Relay>,
+}
+
+impl Room {
+    fn announce(&self) {
+        let _ = self.relay.send(Relay::new(None, &FireEvent::Peers { count: self.members }));
+    }
+}
+
+pub struct Fire {
+    config: FireConfig,
+    rooms: Mutex<HashMap<String, Room>>,
+    next_id: AtomicU64,
+}
+
+/// A connection's place in a room, which it leaves when this is dropped
+struct Membership {
+    fire: Arc<Fire>,
+    room: String,
+    id: u64,
+    relay: broadcast::Sender<Relay>,
+}
+
+impl Membership {
+    fn send(&self, stroke: Stroke) {
+        let _ = self.relay.send(Relay::new(Some(self.id), &FireEvent::Stroke(stroke)));
+    }
+}
+
+impl Drop for Membership {
+    fn drop(&mut self) {
+        let mut rooms = self.fire.rooms.lock().unwrap();
+
+        if let Some(room) = rooms.get_mut(&self.room) {
+            room.members -= 1;
+
+            match room.members {
+                0 => drop(rooms.remove(&self.room)),
+                _ => room.announce(),
+            }
+        }
+    }
+}
+
+impl Fire {
+    pub fn new(config: &FireConfig) -> Result<Fire, FireError> {
+        if config.max_room_size == 0 || config.max_rooms == 0 {
+            return Err(FireError::Size);
+        }
+
+        if !(config.strokes_per_second.is_finite() && config.strokes_per_second > 0.0) || config.burst == 0 {
+            return Err(FireError::Rate);
+        }
+
+        Ok(Fire {
+            config: config.clone(),
+            rooms: Mutex::default(),
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    /// Joins `room` and tells everyone in it the new count, or `None` if it is full or no more rooms may be opened
+    fn join(self: &Arc<Self>, room: &str) -> Option<(Membership, broadcast::Receiver<Relay>)> {
+        let mut rooms = self.rooms.lock().unwrap();
+
+        if !rooms.contains_key(room) && rooms.len() >= self.config.max_rooms {
+            return None;
+        }
+
+        let entry = rooms.entry(room.to_owned()).or_insert_with(|| Room {
+            members: 0,
+            relay: broadcast::channel(QUEUE).0,
+        });
+
+        if entry.members >= self.config.max_room_size {
+            return None;
+        }
+
+        entry.members += 1;
+
+        // subscribed first, so the count reaches the new member too
+        let receiver = entry.relay.subscribe();
+        entry.announce();
+
+        let membership = Membership {
+            fire: self.clone(),
+            room: room.to_owned(),
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            relay: entry.relay.clone(),
+        };
+
+        Some((membership, receiver))
+    }
+}
+
+/// Relays strokes between one visitor and their room until either side goes away
+async fn visitor(socket: WebSocket, fire: Arc<Fire>, room: String, shutdown: Shutdown) {
+    let (mut tx, mut rx) = socket.split();
+
+    let (membership, mut relay) = match fire.join(&room) {
+        Some(joined) => joined,
+        None => {
+            let _ = tx.send(Message::close_with(TRY_AGAIN_LATER, "room is full")).await;
+            return;
+        }
+    };
+
+    let shutdown = shutdown.wait();
+    futures::pin_mut!(shutdown);
+
+    let (rate, burst) = (fire.config.strokes_per_second, fire.config.burst);
+    let mut allowance = Bucket::full(burst, Instant::now());
+
+    loop {
+        tokio::select! {
+            relayed = relay.recv() => match relayed {
+                Ok(relayed) if relayed.from == Some(membership.id) => {}
+                Ok(relayed) => {
+                    if tx.send(Message::text(relayed.text.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+                // a slow connection misses some strokes rather than holding up the room
+                Err(broadcast::RecvError::Lagged(missed)) => log::debug!("fire visitor in {:?} missed {} messages", room, missed),
+                Err(broadcast::RecvError::Closed) => break,
+            },
+            message = rx.next() => match message {
+                Some(Ok(message)) if message.is_text() => {
+                    match message.to_str().ok().and_then(|text| serde_json::from_str::<Stroke>(text).ok()).filter(Stroke::is_valid) {
+                        Some(stroke) if allowance.take(rate, burst, Instant::now()).is_ok() => membership.send(stroke),
+                        Some(_) => {}
+                        None => {
+                            let _ = tx.send(Message::close_with(UNSUPPORTED_DATA, "expected a stroke")).await;
+                            break;
+                        }
+                    }
+                }
+                Some(Ok(message)) if message.is_binary() => {
+                    let _ = tx.send(Message::close_with(UNSUPPORTED_DATA, "expected a stroke")).await;
+                    break;
+                }
+                Some(Ok(message)) if !message.is_close() => {}
+                _ => break,
+            },
+            _ = &mut shutdown => break,
+        }
+    }
+
+    let _ = tx.close().await;
+}
+
+/// `/ws/fire?room=`, or nothing when the shared fire is disabled
+pub fn routes(fire: Option<Arc<Fire>>, shutdown: Shutdown) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    let fire = warp::any().and_then(move || {
+        let fire = fire.clone();
+
+        async move { fire.ok_or_else(warp::reject::not_found) }
+    });
+
+    let room = warp::query::<FireQuery>().and_then(|query: FireQuery| async move {
+        match query.room {
+            None => Ok(DEFAULT_ROOM.to_owned()),
+            Some(room) if valid_room(&room) => Ok(room),
+            Some(_) => Err(warp::reject::not_found()),
+        }
+    });
+
+    warp::path(Socket::PREFIX)
+        .and(warp::path(Socket::Fire.segment()))
+        .and(warp::path::end())
+        .and(fire)
+        .and(room)
+        .and(warp::ws())
+        .map(move |fire: Arc<Fire>, room: String, ws: Ws| {
+            let shutdown = shutdown.clone();
+
+            ws.max_message_size(MAX_MESSAGE_SIZE).on_upgrade(move |socket| visitor(socket, fire, room, shutdown)).into_response()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use warp::test::WsClient;
+
+    fn stroke(x: f32) -> Stroke {
+        Stroke {
+            last_pos: [x, 0.5],
+            pos: [x, 0.6],
+        }
+    }
+
+    fn config() -> FireConfig {
+        FireConfig {
+            max_room_size: 2,
+            max_rooms: 2,
+            ..FireConfig::default()
+        }
+    }
+
+    /// Joins the default room, `warp::test::ws` leaves out the query string
+    async fn connect(fire: &Arc<Fire>) -> WsClient {
+        let routes = routes(Some(fire.clone()), Shutdown::on_signal());
+
+        warp::test::ws().path("/ws/fire").handshake(routes).await.expect("handshake")
+    }
+
+    async fn event(client: &mut WsClient) -> FireEvent {
+        let message = client.recv().await.expect("a message");
+        serde_json::from_str(message.to_str().expect("text")).expect("a fire event")
+    }
+
+    #[tokio::test]
+    async fn relays_strokes_to_the_rest_of_the_room() {
+        let fire = Arc::new(Fire::new(&config()).unwrap());
+
+        let mut a = connect(&fire).await;
+        assert_eq!(event(&mut a).await, FireEvent::Peers { count: 1 });
+
+        let mut b = connect(&fire).await;
+        assert_eq!(event(&mut a).await, FireEvent::Peers { count: 2 });
+        assert_eq!(event(&mut b).await, FireEvent::Peers { count: 2 });
+
+        a.send_text(serde_json::to_string(&stroke(0.25)).unwrap()).await;
+        assert_eq!(event(&mut b).await, FireEvent::Stroke(stroke(0.25)));
+
+        // not echoed back, so the next thing `a` hears is `b`'s stroke
+        b.send_text(serde_json::to_string(&stroke(0.75)).unwrap()).await;
+        assert_eq!(event(&mut a).await, FireEvent::Stroke(stroke(0.75)));
+
+        drop(b);
+        assert_eq!(event(&mut a).await, FireEvent::Peers { count: 1 });
+    }
+
+    #[test]
+    fn rooms_are_capped() {
+        let fire = Arc::new(Fire::new(&config()).unwrap());
+
+        let a = fire.join("one").expect("room for two");
+        let b = fire.join("one").expect("room for two");
+        assert!(fire.join("one").is_none());
+
+        let _c = fire.join("two").expect("room for two rooms");
+        assert!(fire.join("three").is_none());
+
+        // the last one out closes the room, making space for another
+        drop((a, b));
+        assert!(fire.join("three").is_some());
+    }
+
+    #[test]
+    fn room_names_are_slugs() {
+        assert!(valid_room("doom-fire"));
+        assert!(!valid_room(""));
+        assert!(!valid_room("Lobby"));
+        assert!(!valid_room("a room"));
+        assert!(!valid_room(&"a".repeat(33)));
+    }
+
+    #[tokio::test]
+    async fn turns_away_visitors_of_full_rooms() {
+        let fire = Arc::new(Fire::new(&config()).unwrap());
+
+        let _a = connect(&fire).await;
+        let _b = connect(&fire).await;
+
+        let mut c = connect(&fire).await;
+        c.recv_closed().await.expect("room is full");
+    }
+
+    #[tokio::test]
+    async fn drops_strokes_over_the_rate_limit() {
+        let config = FireConfig {
+            strokes_per_second: 0.001,
+            burst: 3,
+            ..config()
+        };
+        let fire = Arc::new(Fire::new(&config).unwrap());
+
+        let mut a = connect(&fire).await;
+        let mut b = connect(&fire).await;
+        assert_eq!(event(&mut a).await, FireEvent::Peers { count: 1 });
+        assert_eq!(event(&mut a).await, FireEvent::Peers { count: 2 });
+        assert_eq!(event(&mut b).await, FireEvent::Peers { count: 2 });
+
+        for i in 0..10 {
+            a.send_text(serde_json::to_string(&stroke(i as f32 / 10.0)).unwrap()).await;
+        }
+
+        for i in 0..3 {
+            assert_eq!(event(&mut b).await, FireEvent::Stroke(stroke(i as f32 / 10.0)));
+        }
+
+        // the rest were dropped, so the next message to reach `b` is the count after `a` leaves
+        drop(a);
+        assert_eq!(event(&mut b).await, FireEvent::Peers { count: 1 });
+    }
+
+    #[tokio::test]
+    async fn closes_on_anything_but_strokes() {
+        let fire = Arc::new(Fire::new(&config()).unwrap());
+
+        let mut a = connect(&fire).await;
+        assert_eq!(event(&mut a).await, FireEvent::Peers { count: 1 });
+
+        a.send_text(r#"{"last_pos":[0.5,0.5],"pos":[2.0,0.5]}"#).await;
+        a.recv_closed().await.expect("not a stroke");
+    }
+}
@@ -0,0 +1,144 @@
+//! Liveness and readiness probes
+//!
+//! `/healthz` only shows the process is serving requests. `/readyz` additionally checks that the deployed bundle is
+//! intact, so an orchestrator holds traffic back from an instance with a missing or unreadable `dist` directory.
+//! A client embedded in the binary cannot go missing, so then only shutdown makes an instance unready.
+
+use std::path::{Path, PathBuf};
+
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+use warp::http::{header, HeaderValue, StatusCode};
+use warp::{Filter, Rejection, Reply};
+
+use crate::shutdown::Shutdown;
+
+#[derive(Debug, Serialize)]
+struct Check {
+    ok: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<PathBuf>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Check {
+    fn from_result(result: Result<PathBuf, String>) -> Check {
+        match result {
+            Ok(path) => Check { ok: true, path: Some(path), error: None },
+            Err(error) => Check { ok: false, path: None, error: Some(error) },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Checks {
+    dist: Check,
+    index: Check,
+    wasm: Check,
+}
+
+#[derive(Debug, Serialize)]
+struct Status {
+    status: &'static str,
+    version: &'static str,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checks: Option<Checks>,
+}
+
+async fn check_dist(dist: &Path) -> Result<PathBuf, String> {
+    match fs::metadata(dist).await {
+        Ok(metadata) if metadata.is_dir() => Ok(dist.to_owned()),
+        Ok(_) => Err("not a directory".to_owned()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Opens the file and reads its first bytes, which catches permission problems as well as missing files
+async fn check_readable(path: PathBuf) -> Result<PathBuf, String> {
+    let mut file = fs::File::open(&path).await.map_err(|e| e.to_string())?;
+    let mut buf = [0; 64];
+
+    match file.read(&mut buf).await {
+        Ok(0) => Err("file is empty".to_owned()),
+        Ok(_) => Ok(path),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// The wasm bundle's file name depends on the build, so any readable `.wasm` file at the top level will do
+async fn check_wasm(dist: &Path) -> Result<PathBuf, String> {
+    let mut entries = fs::read_dir(dist).await.map_err(|e| e.to_string())?;
+    let mut last_error = None;
+
+    while let Some(entry) = entries.next_entry().await.map_err(|e| e.to_string())? {
+        let path = entry.path();
+
+        if path.extension().is_some_and(|ext| ext == "wasm") {
+            match check_readable(path).await {
+                Ok(path) => return Ok(path),
+                Err(e) => last_error = Some(e),
+            }
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| "no .wasm file found".to_owned()))
+}
+
+fn json(status: StatusCode, body: &Status) -> warp::reply::Response {
+    let mut res = warp::reply::with_status(warp::reply::json(body), status).into_response();
+    res.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    res
+}
+
+async fn readiness(dist: Option<&Path>, shutdown: &Shutdown) -> warp::reply::Response {
+    let checks = match dist {
+        Some(dist) => Some(Checks {
+            dist: Check::from_result(check_dist(dist).await),
+            index: Check::from_result(check_readable(dist.join("index.html")).await),
+            wasm: Check::from_result(check_wasm(dist).await),
+        }),
+        None => None,
+    };
+
+    let (code, status) = if shutdown.is_triggered() {
+        (StatusCode::SERVICE_UNAVAILABLE, "shutting down")
+    } else if checks.as_ref().is_none_or(|checks| checks.dist.ok && checks.index.ok && checks.wasm.ok) {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    };
+
+    let body = Status {
+        status,
+        version: env!("CARGO_PKG_VERSION"),
+        checks,
+    };
+
+    json(code, &body)
+}
+
+/// `GET /healthz` and `GET /readyz`, with `dist` to check unless the client is embedded
+pub fn routes(dist: Option<PathBuf>, shutdown: Shutdown) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    let healthz = warp::path("healthz").and(warp::path::end()).map(|| {
+        let body = Status {
+            status: "ok",
+            version: env!("CARGO_PKG_VERSION"),
+            checks: None,
+        };
+
+        json(StatusCode::OK, &body)
+    });
+
+    let readyz = warp::path("readyz").and(warp::path::end()).and_then(move || {
+        let dist = dist.clone();
+        let shutdown = shutdown.clone();
+
+        async move { Ok::<_, Rejection>(readiness(dist.as_deref(), &shutdown).await) }
+    });
+
+    warp::get().and(healthz.or(readyz).unify())
+}
@@ -0,0 +1,47 @@
+// Injected into index.html by `nova_website --dev`, see server/src/dev.rs
+(function () {
+    "use strict";
+
+    var url = (location.protocol === "https:" ? "wss://" : "ws://") + location.host + "/__livereload";
+    var connected = false;
+
+    function swapStyles(paths) {
+        document.querySelectorAll('link[rel="stylesheet"]').forEach(function (link) {
+            var href = new URL(link.href);
+
+            if (href.origin === location.origin && paths.indexOf(href.pathname.replace(/^\//, "")) !== -1) {
+                href.searchParams.set("livereload", Date.now());
+                link.href = href.href;
+            }
+        });
+    }
+
+    function connect() {
+        var socket = new WebSocket(url);
+
+        socket.onopen = function () {
+            // the server restarted while we were away, so anything may have changed
+            if (connected) {
+                location.reload();
+            }
+
+            connected = true;
+        };
+
+        socket.onmessage = function (event) {
+            var change = JSON.parse(event.data);
+
+            if (change.type === "css") {
+                swapStyles(change.paths);
+            } else {
+                location.reload();
+            }
+        };
+
+        socket.onclose = function () {
+            setTimeout(connect, 1000);
+        };
+    }
+
+    connect();
+})();
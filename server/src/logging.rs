@@ -0,0 +1,218 @@
+//! Log output in plain text, JSON or logfmt, and per-request access logs
+//!
+//! Access log records carry their fields as structured key-values under the `access` target, so every format
+//! renders them natively instead of parsing a preformatted message.
+
+use std::fmt::{self, Write as _};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use log::kv::{self, Key, Value, VisitSource};
+use warp::http::{HeaderMap, HeaderValue, Method, StatusCode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+    Logfmt,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogFormat, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            "logfmt" => Ok(LogFormat::Logfmt),
+            _ => Err(format!("unknown log format {:?}, expected text, json or logfmt", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+
+    /// Filter in `env_logger` syntax, e.g. `info` or `warn,access=info`
+    pub level: String,
+
+    /// Log one line per request under the `access` target
+    pub access_log: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            format: LogFormat::Text,
+            level: "info".to_owned(),
+            access_log: true,
+        }
+    }
+}
+
+/// Collects key-values into `(key, value)` pairs
+struct Collect<'a, 'kvs>(&'a mut Vec<(Key<'kvs>, Value<'kvs>)>);
+
+impl<'a, 'kvs> VisitSource<'kvs> for Collect<'a, 'kvs> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.push((key, value));
+        Ok(())
+    }
+}
+
+fn json_value(value: &Value) -> serde_json::Value {
+    if let Some(v) = value.to_u64() {
+        v.into()
+    } else if let Some(v) = value.to_i64() {
+        v.into()
+    } else if let Some(v) = value.to_f64() {
+        v.into()
+    } else if let Some(v) = value.to_bool() {
+        v.into()
+    } else {
+        value.to_string().into()
+    }
+}
+
+fn logfmt_value(out: &mut String, value: &str) -> fmt::Result {
+    if !value.is_empty() && !value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=' || c.is_control()) {
+        return out.write_str(value);
+    }
+
+    out.write_char('"')?;
+
+    for c in value.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            c => out.write_char(c)?,
+        }
+    }
+
+    out.write_char('"')
+}
+
+/// Installs the global logger
+pub fn init(config: &LoggingConfig) {
+    let format = config.format;
+
+    env_logger::Builder::new()
+        .parse_filters(&config.level)
+        .format(move |buf, record| {
+            let mut pairs = Vec::new();
+            let _ = record.key_values().visit(&mut Collect(&mut pairs));
+
+            let timestamp = buf.timestamp_millis();
+
+            match format {
+                LogFormat::Json => {
+                    let mut object = serde_json::Map::new();
+
+                    object.insert("ts".to_owned(), timestamp.to_string().into());
+                    object.insert("level".to_owned(), record.level().as_str().into());
+                    object.insert("target".to_owned(), record.target().into());
+                    object.insert("msg".to_owned(), record.args().to_string().into());
+
+                    for (key, value) in &pairs {
+                        object.insert(key.to_string(), json_value(value));
+                    }
+
+                    writeln!(buf, "{}", serde_json::Value::Object(object))
+                }
+                LogFormat::Logfmt => {
+                    let mut line = String::new();
+
+                    let _ = write!(line, "ts={} level={} target=", timestamp, record.level().as_str().to_ascii_lowercase());
+                    let _ = logfmt_value(&mut line, record.target());
+                    line.push_str(" msg=");
+                    let _ = logfmt_value(&mut line, &record.args().to_string());
+
+                    for (key, value) in &pairs {
+                        let _ = write!(line, " {}=", key);
+                        let _ = logfmt_value(&mut line, &value.to_string());
+                    }
+
+                    writeln!(buf, "{}", line)
+                }
+                LogFormat::Text => {
+                    write!(buf, "[{} {:<5} {}] {}", timestamp, record.level(), record.target(), record.args())?;
+
+                    for (key, value) in &pairs {
+                        write!(buf, " {}={}", key, value)?;
+                    }
+
+                    writeln!(buf)
+                }
+            }
+        })
+        .init();
+}
+
+/// Identifier echoed back in `X-Request-Id` and attached to the access log
+#[derive(Debug, Clone)]
+pub struct RequestId(pub HeaderValue);
+
+impl RequestId {
+    /// Reuses a well-formed incoming `X-Request-Id`, such as one set by a proxy, or generates a new one
+    pub fn for_request(headers: &HeaderMap) -> RequestId {
+        let incoming = headers.get("x-request-id").filter(|value| {
+            let bytes = value.as_bytes();
+            !bytes.is_empty() && bytes.len() <= 128 && bytes.iter().all(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(b))
+        });
+
+        match incoming {
+            Some(value) => RequestId(value.clone()),
+            None => RequestId(HeaderValue::from_str(&uuid::Uuid::new_v4().to_simple().to_string()).expect("valid request id")),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.to_str().unwrap_or("-")
+    }
+}
+
+/// Everything the access log records about a finished request
+pub struct AccessRecord<'a> {
+    pub id: &'a RequestId,
+    pub remote: SocketAddr,
+    pub method: &'a Method,
+    pub path: &'a str,
+    pub status: StatusCode,
+
+    /// Length of the response body as announced, not a count of what was sent. `None` for chunked bodies, logged as `-`.
+    pub content_length: Option<u64>,
+    pub latency: Duration,
+    pub user_agent: Option<&'a str>,
+    pub referer: Option<&'a str>,
+}
+
+pub fn access(record: &AccessRecord) {
+    let latency_ms = record.latency.as_secs_f64() * 1000.0;
+    let remote = record.remote.ip().to_string();
+
+    // a number when known, so that JSON keeps it numeric
+    let content_length = match record.content_length {
+        Some(len) => Value::from(len),
+        None => Value::from("-"),
+    };
+
+    log::info!(
+        target: "access",
+        request_id = record.id.as_str(),
+        remote = remote.as_str(),
+        method = record.method.as_str(),
+        path = record.path,
+        status = record.status.as_u16(),
+        content_length = content_length,
+        latency_ms = (latency_ms * 1000.0).round() / 1000.0,
+        user_agent = record.user_agent.unwrap_or("-"),
+        referer = record.referer.unwrap_or("-");
+        "{} {} {}", record.method, record.path, record.status.as_u16()
+    );
+}
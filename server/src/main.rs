@@ -1,14 +1,256 @@
+#[macro_use]
+extern crate serde;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::{BoxFuture, FutureExt, TryFutureExt};
+use tokio::net::TcpListener;
 use warp::{Filter, Rejection, Reply};
 
-pub fn files() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-    warp::fs::dir("../client/dist")
+pub mod analytics;
+pub mod assets;
+pub mod blog;
+pub mod cache;
+pub mod compression;
+pub mod config;
+pub mod contact;
+pub mod dev;
+pub mod embed;
+pub mod errors;
+pub mod export;
+pub mod feed;
+pub mod fire;
+pub mod health;
+pub mod logging;
+pub mod metrics;
+pub mod projects;
+pub mod ratelimit;
+pub mod render;
+pub mod security;
+pub mod server;
+pub mod shutdown;
+pub mod sitemap;
+pub mod spa;
+pub mod tls;
+
+#[cfg(test)]
+mod tests;
+
+use analytics::Analytics;
+use assets::Assets;
+use blog::Blog;
+use cache::CachePolicy;
+use config::{Command, Config};
+use contact::Contact;
+use dev::LiveReload;
+use fire::Fire;
+use metrics::{Metrics, RouteClass};
+use projects::Projects;
+use ratelimit::{RateLimiter, TrustedProxies};
+use render::Renderer;
+use security::SecurityHeaders;
+use server::{Listener, Server};
+use shutdown::{Shutdown, Tracker};
+
+pub fn files(assets: Arc<Assets>) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    assets::dir(assets)
+}
+
+pub fn index(renderer: Arc<Renderer>) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    spa::fallback(renderer)
 }
 
-pub fn index() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-    warp::fs::file("../client/dist/index.html")
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(1);
+}
+
+async fn bind(addr: SocketAddr) -> TcpListener {
+    TcpListener::bind(addr).await.unwrap_or_else(|e| fail(format_args!("unable to bind to {}: {}", addr, e)))
 }
 
 #[tokio::main]
 async fn main() {
-    warp::serve(files().or(index())).run(([127, 0, 0, 1], 9009)).await;
+    let (config, command) = Config::load().unwrap_or_else(|e| fail(e));
+
+    logging::init(&config.logging);
+
+    if let Some(Command::Export { outdir }) = command {
+        let manifest = export::export(&config, &outdir).await.unwrap_or_else(|e| fail(e));
+
+        log::info!("exported {} pages and {} files to {:?}", manifest.pages.len(), manifest.files.len(), outdir);
+
+        return;
+    }
+
+    let policy = CachePolicy::new(&config.cache).unwrap_or_else(|e| fail(e));
+    let assets = match embed::files() {
+        Some((files, modified)) => {
+            log::info!("serving {} files embedded in the binary", files.len());
+            Arc::new(Assets::embedded(files, modified, config.compression.clone(), policy))
+        }
+        None => Arc::new(Assets::new(&config.dist, config.compression.clone(), policy)),
+    };
+    let blog = Arc::new(Blog::new(config.blog.clone()));
+    blog.clone().watch();
+
+    let projects = Arc::new(Projects::new(config.projects.clone()));
+    projects.clone().watch();
+
+    let proxies = TrustedProxies::new(&config.rate_limit.trusted_proxies).unwrap_or_else(|e| fail(e));
+
+    let limiter = match config.rate_limit.enabled {
+        true => Some(Arc::new(RateLimiter::new(&config.rate_limit).unwrap_or_else(|e| fail(e)))),
+        false => None,
+    };
+
+    if let Some(ref limiter) = limiter {
+        limiter.clone().watch();
+    }
+
+    let contact = match config.contact.enabled {
+        true => Some(Arc::new(Contact::new(config.contact.clone()).unwrap_or_else(|e| fail(e)))),
+        false => None,
+    };
+
+    let analytics = match config.analytics.enabled {
+        true => Some(Arc::new(Analytics::new(config.analytics.clone(), blog.clone(), projects.clone()).unwrap_or_else(|e| fail(e)))),
+        false => None,
+    };
+
+    if let Some(ref analytics) = analytics {
+        analytics.clone().watch();
+    }
+
+    let fire = match config.fire.enabled {
+        true => Some(Arc::new(Fire::new(&config.fire).unwrap_or_else(|e| fail(e)))),
+        false => None,
+    };
+
+    if config.dev && assets.is_embedded() {
+        log::warn!("dev mode watches {:?}, but the client embedded in the binary is served", config.dist);
+    }
+
+    // a client embedded in the binary leaves nothing on disk to check
+    let dist = (!assets.is_embedded()).then(|| config.dist.clone());
+
+    let reload = config.dev.then(|| Arc::new(LiveReload::new(&config.dist)));
+
+    if let Some(ref reload) = reload {
+        reload.clone().watch();
+    }
+
+    let feeds = feed::base_url(&config.feed).map(|_| config.feed.title.clone());
+    let renderer = Arc::new(Renderer::new(assets.clone(), blog.clone(), projects.clone(), feeds, config.ssr, config.dev));
+
+    let security = Arc::new(SecurityHeaders::new(&config.security).unwrap_or_else(|e| fail(e)));
+
+    let shutdown = Shutdown::on_signal();
+    let tracker = Arc::new(Tracker::default());
+    let metrics = Arc::new(Metrics::new(tracker.clone()));
+
+    if config.feed.base_url.is_none() {
+        log::warn!("not serving the feeds and sitemap.xml, they need feed.base_url for absolute URLs");
+    }
+
+    let feeds = feed::routes(blog.clone(), assets.clone(), config.feed.clone());
+    let sitemap = sitemap::routes(blog.clone(), projects.clone(), assets.clone(), config.feed.clone(), config.robots.clone());
+
+    // client routes go first so the root is rendered rather than served as the bare `index.html`
+    let site = metrics::classify(RouteClass::Api, blog::routes(blog, assets.clone()))
+        .or(metrics::classify(RouteClass::Api, projects::routes(projects, assets.clone())))
+        .or(metrics::classify(RouteClass::Api, contact::routes(contact, proxies)))
+        .or(metrics::classify(RouteClass::Api, analytics::routes(analytics)))
+        .or(metrics::classify(RouteClass::Api, fire::routes(fire, shutdown.clone())))
+        .or(metrics::classify(RouteClass::Api, feeds))
+        .or(metrics::classify(RouteClass::Api, sitemap))
+        .or(metrics::classify(RouteClass::Spa, spa::shell(renderer.clone())))
+        .or(metrics::classify(RouteClass::Static, files(assets)))
+        .or(metrics::classify(RouteClass::Spa, index(renderer)));
+    let routes = security::with_security_headers(security, metrics::instrument(metrics.clone(), errors::recover(ratelimit::limit(limiter, site))));
+
+    let routes = health::routes(dist, shutdown.clone()).or(dev::routes(reload, shutdown.clone())).unify().or(routes).unify();
+
+    let admin_addr = config.metrics.admin_addr(config.address);
+
+    let routes = match (config.metrics.enabled, admin_addr) {
+        (true, None) => metrics::endpoint(metrics.clone()).or(routes).unify().boxed(),
+        _ => routes.boxed(),
+    };
+
+    let server = Server {
+        shutdown: shutdown.clone(),
+        tracker: tracker.clone(),
+        access_log: config.logging.access_log,
+    };
+
+    let listener = |addr, tls| async move { Listener { listener: bind(addr).await, tls } };
+
+    let serving: BoxFuture<Result<(), hyper::Error>> = match config.tls {
+        None => {
+            let listener = listener(config.socket_addr(), None).await;
+
+            log::info!("listening on http://{}", config.socket_addr());
+
+            server.clone().serve(routes, listener).boxed()
+        }
+        Some(ref tls) => {
+            let acceptor = tls::acceptor(tls).unwrap_or_else(|e| fail(e));
+            let routes = routes.with(tls::strict_transport(tls));
+
+            let https = server.clone().serve(routes, listener(config.socket_addr(), Some(acceptor)).await);
+
+            log::info!("listening on https://{}", config.socket_addr());
+
+            match tls.redirect_port {
+                None => https.boxed(),
+                Some(port) => {
+                    let addr = SocketAddr::new(config.address, port);
+                    let http = server.clone().serve(tls::redirect(config.port), listener(addr, None).await);
+
+                    log::info!("redirecting http://{} to HTTPS", addr);
+
+                    futures::future::try_join(https, http).map_ok(|_| ()).boxed()
+                }
+            }
+        }
+    };
+
+    let serving = match admin_addr {
+        Some(addr) if config.metrics.enabled => {
+            let admin = server.serve(metrics::endpoint(metrics), listener(addr, None).await);
+
+            log::info!("serving metrics on http://{}/metrics", addr);
+
+            futures::future::try_join(serving, admin).map_ok(|_| ()).boxed()
+        }
+        _ => serving,
+    };
+
+    let mut serving = serving.fuse();
+
+    // the signal goes first so the open connections are counted before the server starts draining them
+    futures::select_biased! {
+        _ = shutdown.clone().wait().boxed().fuse() => {}
+        result = serving => return result.unwrap_or_else(|e| fail(e)),
+    }
+
+    let open = tracker.connections();
+
+    log::info!(
+        "draining {} open connections with {} requests in flight, waiting up to {}s",
+        open,
+        tracker.requests(),
+        config.drain_timeout
+    );
+
+    let dropped = match tokio::time::timeout(Duration::from_secs(config.drain_timeout), serving).await {
+        Ok(Ok(())) => 0,
+        Ok(Err(e)) => fail(e),
+        Err(_) => tracker.connections(),
+    };
+
+    log::info!("shutdown complete, {} connections drained, {} dropped", open.saturating_sub(dropped), dropped);
 }
@@ -0,0 +1,243 @@
+//! Prometheus metrics in the text exposition format
+//!
+//! Requests are counted by route class, which the routes tag onto their responses with [`classify`]. Requests that
+//! no route accepts are recorded under the `unmatched` class with the status warp will reply with.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use hyper::body::HttpBody;
+use warp::http::{header, HeaderValue, StatusCode};
+use warp::{Filter, Rejection, Reply};
+
+use crate::shutdown::Tracker;
+
+/// Upper bounds of the latency histogram buckets, in seconds
+const BUCKETS: [f64; 12] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+
+    /// Serve `/metrics` on a separate plain HTTP listener instead of the main one
+    pub admin_port: Option<u16>,
+
+    /// Address of the admin listener, defaults to the main address
+    pub admin_address: Option<IpAddr>,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            enabled: true,
+            admin_port: None,
+            admin_address: None,
+        }
+    }
+}
+
+impl MetricsConfig {
+    /// Address of the admin listener, if one is configured
+    pub fn admin_addr(&self, default: IpAddr) -> Option<SocketAddr> {
+        self.admin_port.map(|port| SocketAddr::new(self.admin_address.unwrap_or(default), port))
+    }
+}
+
+/// What kind of route produced a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RouteClass {
+    Static,
+    Spa,
+    Api,
+    Unmatched,
+
+    /// Turned away by the rate limiter
+    Limited,
+}
+
+impl RouteClass {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RouteClass::Static => "static",
+            RouteClass::Spa => "spa",
+            RouteClass::Api => "api",
+            RouteClass::Unmatched => "unmatched",
+            RouteClass::Limited => "limited",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(BUCKETS.iter()) {
+            if value <= *bound {
+                *bucket += 1;
+            }
+        }
+
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct State {
+    requests: BTreeMap<(RouteClass, u16), u64>,
+    latency: BTreeMap<RouteClass, Histogram>,
+    bytes: BTreeMap<RouteClass, u64>,
+}
+
+pub struct Metrics {
+    tracker: Arc<Tracker>,
+    state: Mutex<State>,
+}
+
+impl Metrics {
+    pub fn new(tracker: Arc<Tracker>) -> Metrics {
+        Metrics {
+            tracker,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    pub fn record(&self, class: RouteClass, status: StatusCode, bytes: u64, seconds: f64) {
+        let mut state = self.state.lock().unwrap();
+
+        *state.requests.entry((class, status.as_u16())).or_insert(0) += 1;
+        *state.bytes.entry(class).or_insert(0) += bytes;
+        state.latency.entry(class).or_default().observe(seconds);
+    }
+
+    /// Renders every metric in the Prometheus text format
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+
+        // writing to a String cannot fail
+        let _ = self.write(&state, &mut out);
+
+        out
+    }
+
+    fn write(&self, state: &State, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "# HELP nova_http_requests_total Requests handled, by route class and status.")?;
+        writeln!(out, "# TYPE nova_http_requests_total counter")?;
+
+        for ((class, status), count) in &state.requests {
+            writeln!(out, "nova_http_requests_total{{class=\"{}\",status=\"{}\"}} {}", class.as_str(), status, count)?;
+        }
+
+        writeln!(out, "# HELP nova_http_request_duration_seconds Time until the response headers were ready.")?;
+        writeln!(out, "# TYPE nova_http_request_duration_seconds histogram")?;
+
+        for (class, histogram) in &state.latency {
+            let class = class.as_str();
+
+            for (bound, count) in BUCKETS.iter().zip(histogram.buckets.iter()) {
+                writeln!(out, "nova_http_request_duration_seconds_bucket{{class=\"{}\",le=\"{}\"}} {}", class, bound, count)?;
+            }
+
+            writeln!(out, "nova_http_request_duration_seconds_bucket{{class=\"{}\",le=\"+Inf\"}} {}", class, histogram.count)?;
+            writeln!(out, "nova_http_request_duration_seconds_sum{{class=\"{}\"}} {}", class, histogram.sum)?;
+            writeln!(out, "nova_http_request_duration_seconds_count{{class=\"{}\"}} {}", class, histogram.count)?;
+        }
+
+        writeln!(out, "# HELP nova_http_response_bytes_total Response body bytes, by route class.")?;
+        writeln!(out, "# TYPE nova_http_response_bytes_total counter")?;
+
+        for (class, bytes) in &state.bytes {
+            writeln!(out, "nova_http_response_bytes_total{{class=\"{}\"}} {}", class.as_str(), bytes)?;
+        }
+
+        writeln!(out, "# HELP nova_open_connections Connections currently open across all listeners.")?;
+        writeln!(out, "# TYPE nova_open_connections gauge")?;
+        writeln!(out, "nova_open_connections {}", self.tracker.connections())?;
+
+        writeln!(out, "# HELP nova_requests_in_flight Requests currently being handled.")?;
+        writeln!(out, "# TYPE nova_requests_in_flight gauge")?;
+        writeln!(out, "nova_requests_in_flight {}", self.tracker.requests())?;
+
+        writeln!(out, "# HELP nova_build_info Build information, always 1.")?;
+        writeln!(out, "# TYPE nova_build_info gauge")?;
+        writeln!(
+            out,
+            "nova_build_info{{version=\"{}\",profile=\"{}\"}} 1",
+            env!("CARGO_PKG_VERSION"),
+            if cfg!(debug_assertions) { "debug" } else { "release" }
+        )
+    }
+}
+
+/// Tags responses from `filter` with a route class for [`instrument`]
+pub fn classify<F, R>(class: RouteClass, filter: F) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    filter.map(move |reply: R| {
+        let mut res = reply.into_response();
+        res.extensions_mut().insert(class);
+        res
+    })
+}
+
+/// The status warp's default rejection handling will reply with
+fn rejection_status(rejection: &Rejection) -> StatusCode {
+    if rejection.is_not_found() {
+        StatusCode::NOT_FOUND
+    } else if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        StatusCode::METHOD_NOT_ALLOWED
+    } else {
+        StatusCode::BAD_REQUEST
+    }
+}
+
+/// Records the count, latency and size of every request that reaches `filter`, passing rejections through
+pub fn instrument<F, R>(metrics: Arc<Metrics>, filter: F) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    let outcome = filter.map(|reply: R| Ok(reply.into_response())).or_else(|rejection| async { Ok::<_, Rejection>((Err(rejection),)) });
+
+    warp::any().map(Instant::now).and(outcome).and_then(move |start: Instant, outcome: Result<warp::reply::Response, Rejection>| {
+        let seconds = start.elapsed().as_secs_f64();
+
+        match outcome {
+            Ok(ref res) => {
+                let class = res.extensions().get::<RouteClass>().copied().unwrap_or(RouteClass::Static);
+                let bytes = res
+                    .body()
+                    .size_hint()
+                    .exact()
+                    .or_else(|| res.headers().get(header::CONTENT_LENGTH)?.to_str().ok()?.parse().ok())
+                    .unwrap_or(0);
+
+                metrics.record(class, res.status(), bytes, seconds);
+            }
+            Err(ref rejection) => metrics.record(RouteClass::Unmatched, rejection_status(rejection), 0, seconds),
+        }
+
+        futures::future::ready(outcome)
+    })
+}
+
+/// `GET /metrics`
+pub fn endpoint(metrics: Arc<Metrics>) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    warp::path("metrics").and(warp::path::end()).and(warp::get()).map(move || {
+        let mut res = metrics.render().into_response();
+        res.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain; version=0.0.4; charset=utf-8"));
+        res
+    })
+}
@@ -0,0 +1,333 @@
+//! Portfolio projects from `content/projects.toml`
+//!
+//! Each project is a `[[project]]` table:
+//!
+//! ```text
+//! [[project]]
+//! slug = "doom-fire"
+//! title = "Doom Fire"
+//! description = "The PSX Doom fire effect on a canvas you can draw on."
+//! date = 2020-06-14
+//! tags = ["rust", "wasm"]
+//! thumbnail = "images/doom-fire.png"  # optional, relative to the site root
+//! demo = "doom-fire"                  # optional, id of a demo embedded by the client
+//! links = [{ label = "Source", url = "https://github.com/..." }]
+//! ```
+//!
+//! The manifest is read when the server starts and reread whenever it changes.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use nova_common::api::{Project, ProjectLink, ProjectList, ProjectQuery};
+use nova_common::Endpoint;
+use warp::http::HeaderMap;
+use warp::{Filter, Rejection};
+
+use crate::assets::Assets;
+use crate::blog::{parse_date, valid_slug};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectsConfig {
+    pub manifest: PathBuf,
+
+    /// How often to check the manifest for changes, in seconds. Zero disables reloading.
+    pub reload_interval: u64,
+}
+
+impl Default for ProjectsConfig {
+    fn default() -> Self {
+        ProjectsConfig {
+            manifest: PathBuf::from("../content/projects.toml"),
+            reload_interval: 2,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProjectError {
+    #[error("unable to read {0:?}: {1}")]
+    Read(PathBuf, #[source] io::Error),
+
+    #[error("invalid project manifest {0:?}: {1}")]
+    Parse(PathBuf, #[source] toml::de::Error),
+
+    #[error("invalid date {1:?} for project {0:?}, expected YYYY-MM-DD or RFC 3339")]
+    Date(String, String),
+
+    #[error("invalid project slug {0:?}, expected lowercase letters, digits and dashes")]
+    Slug(String),
+
+    #[error("project slug {0:?} is used more than once")]
+    Duplicate(String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    slug: String,
+    title: String,
+    description: String,
+    date: toml::value::Datetime,
+
+    #[serde(default)]
+    tags: Vec<String>,
+
+    #[serde(default)]
+    links: Vec<ProjectLink>,
+
+    thumbnail: Option<String>,
+    demo: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default, rename = "project")]
+    projects: Vec<Entry>,
+}
+
+/// Projects from the manifest, newest first
+#[derive(Debug, Default)]
+pub struct Index {
+    pub projects: Vec<Project>,
+    by_slug: HashMap<String, usize>,
+}
+
+impl Index {
+    pub fn parse(path: &Path, source: &str) -> Result<Index, ProjectError> {
+        let manifest: Manifest = toml::from_str(source).map_err(|e| ProjectError::Parse(path.to_owned(), e))?;
+
+        let mut projects = Vec::with_capacity(manifest.projects.len());
+
+        for entry in manifest.projects {
+            if !valid_slug(&entry.slug) {
+                return Err(ProjectError::Slug(entry.slug));
+            }
+
+            let date = entry.date.to_string();
+            let date = parse_date(&date).ok_or_else(|| ProjectError::Date(entry.slug.clone(), date))?;
+
+            projects.push(Project {
+                slug: entry.slug,
+                title: entry.title,
+                description: entry.description,
+                date,
+                tags: entry.tags,
+                links: entry.links,
+                thumbnail: entry.thumbnail,
+                demo: entry.demo,
+            });
+        }
+
+        projects.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.slug.cmp(&b.slug)));
+
+        let mut by_slug = HashMap::new();
+
+        for (i, project) in projects.iter().enumerate() {
+            if by_slug.insert(project.slug.clone(), i).is_some() {
+                return Err(ProjectError::Duplicate(project.slug.clone()));
+            }
+        }
+
+        Ok(Index { projects, by_slug })
+    }
+
+    pub fn get(&self, slug: &str) -> Option<&Project> {
+        self.by_slug.get(slug).map(|&i| &self.projects[i])
+    }
+
+    /// Projects carrying `tag`, or all projects, along with every tag
+    pub fn list(&self, tag: Option<&str>) -> ProjectList {
+        let tags: BTreeSet<&String> = self.projects.iter().flat_map(|project| project.tags.iter()).collect();
+
+        ProjectList {
+            projects: self.projects.iter().filter(|project| tag.is_none_or(|tag| project.tags.iter().any(|t| t == tag))).cloned().collect(),
+            tags: tags.into_iter().cloned().collect(),
+            tag: tag.map(str::to_owned),
+        }
+    }
+}
+
+/// Length and modification time of the manifest, `None` when it does not exist
+type Signature = Option<(u64, Option<SystemTime>)>;
+
+pub struct Projects {
+    config: ProjectsConfig,
+    index: RwLock<Arc<Index>>,
+    signature: RwLock<Option<Signature>>,
+}
+
+impl Projects {
+    pub fn new(config: ProjectsConfig) -> Projects {
+        let projects = Projects {
+            config,
+            index: RwLock::new(Arc::default()),
+            signature: RwLock::new(None),
+        };
+
+        projects.reload_if_changed();
+        projects
+    }
+
+    pub fn index(&self) -> Arc<Index> {
+        self.index.read().unwrap().clone()
+    }
+
+    fn signature(&self) -> Signature {
+        let metadata = fs::metadata(&self.config.manifest).ok()?;
+
+        Some((metadata.len(), metadata.modified().ok()))
+    }
+
+    /// Rereads the manifest if it changed. An invalid manifest is logged and the previous projects are kept.
+    pub fn reload_if_changed(&self) -> bool {
+        let signature = self.signature();
+
+        if self.signature.read().unwrap().as_ref() == Some(&signature) {
+            return false;
+        }
+
+        *self.signature.write().unwrap() = Some(signature);
+
+        let path = &self.config.manifest;
+
+        let index = match signature {
+            None => {
+                log::info!("no project manifest at {:?}", path);
+                Index::default()
+            }
+            Some(_) => match fs::read_to_string(path).map_err(|e| ProjectError::Read(path.clone(), e)).and_then(|source| Index::parse(path, &source)) {
+                Ok(index) => {
+                    log::info!("indexed {} projects from {:?}", index.projects.len(), path);
+                    index
+                }
+                Err(e) => {
+                    log::warn!("keeping the previous projects: {}", e);
+                    return false;
+                }
+            },
+        };
+
+        *self.index.write().unwrap() = Arc::new(index);
+
+        true
+    }
+
+    /// Spawns a task that periodically rereads the manifest
+    pub fn watch(self: Arc<Self>) {
+        if self.config.reload_interval == 0 {
+            return;
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.reload_interval));
+
+        tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+
+                let projects = self.clone();
+                let _ = tokio::task::spawn_blocking(move || projects.reload_if_changed()).await;
+            }
+        });
+    }
+}
+
+/// `GET /api/projects` and `GET /api/projects/{slug}`
+pub fn routes(projects: Arc<Projects>, assets: Arc<Assets>) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    let list = {
+        let projects = projects.clone();
+        let assets = assets.clone();
+
+        warp::path::end().and(warp::query::<ProjectQuery>()).and(warp::header::headers_cloned()).and_then(move |query: ProjectQuery, headers: HeaderMap| {
+            let projects = projects.clone();
+            let assets = assets.clone();
+
+            async move {
+                let index = projects.index();
+                let list = index.list(query.tag.as_deref());
+
+                Ok::<_, Rejection>(assets.serve_json("api/projects", &list, &headers).await)
+            }
+        })
+    };
+
+    let project = warp::path::param().and(warp::path::end()).and(warp::header::headers_cloned()).and_then(move |slug: String, headers: HeaderMap| {
+        let projects = projects.clone();
+        let assets = assets.clone();
+
+        async move {
+            let index = projects.index();
+            let project = index.get(&slug).ok_or_else(warp::reject::not_found)?;
+
+            Ok::<_, Rejection>(assets.serve_json("api/projects", project, &headers).await)
+        }
+    });
+
+    warp::get().and(warp::path(Endpoint::PREFIX)).and(warp::path(Endpoint::Projects.segment())).and(list.or(project).unify())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(slug: &str, date: &str, tags: &str) -> String {
+        format!(
+            "[[project]]\nslug = \"{}\"\ntitle = \"{}\"\ndescription = \"A project.\"\ndate = {}\ntags = [{}]\n\n",
+            slug, slug, date, tags
+        )
+    }
+
+    fn parse(source: &str) -> Result<Index, ProjectError> {
+        Index::parse(Path::new("projects.toml"), source)
+    }
+
+    fn slugs(projects: &[Project]) -> Vec<&str> {
+        projects.iter().map(|project| project.slug.as_str()).collect()
+    }
+
+    #[test]
+    fn projects_are_newest_first() {
+        let source = project("old", "2019-03-01", "") + &project("b-new", "2020-06-14", "") + &project("a-new", "2020-06-14T00:00:00Z", "");
+        let index = parse(&source).expect("valid manifest");
+
+        assert_eq!(slugs(&index.projects), ["a-new", "b-new", "old"]);
+        assert_eq!(index.get("old").map(|project| project.title.as_str()), Some("old"));
+        assert!(index.get("missing").is_none());
+    }
+
+    #[test]
+    fn invalid_manifests_are_refused() {
+        assert!(matches!(parse(&project("Doom Fire", "2020-06-14", "")), Err(ProjectError::Slug(slug)) if slug == "Doom Fire"));
+        assert!(matches!(parse(&project("../up", "2020-06-14", "")), Err(ProjectError::Slug(_))));
+
+        let duplicate = project("doom-fire", "2020-06-14", "") + &project("doom-fire", "2021-01-01", "");
+        assert!(matches!(parse(&duplicate), Err(ProjectError::Duplicate(slug)) if slug == "doom-fire"));
+
+        assert!(matches!(parse(&project("doom-fire", "12:30:00", "")), Err(ProjectError::Date(slug, _)) if slug == "doom-fire"));
+        assert!(matches!(parse("[[project]]\nslug = \"doom-fire\"\n"), Err(ProjectError::Parse(..))));
+    }
+
+    #[test]
+    fn lists_filter_by_tag() {
+        let source = project("fire", "2020-06-14", "\"wasm\", \"graphics\"") + &project("site", "2020-05-01", "\"wasm\"") + &project("cli", "2020-04-01", "\"rust\"");
+        let index = parse(&source).expect("valid manifest");
+
+        let all = index.list(None);
+        assert_eq!(slugs(&all.projects), ["fire", "site", "cli"]);
+        assert_eq!(all.tags, ["graphics", "rust", "wasm"]);
+        assert_eq!(all.tag, None);
+
+        let wasm = index.list(Some("wasm"));
+        assert_eq!(slugs(&wasm.projects), ["fire", "site"]);
+        assert_eq!(wasm.tags, all.tags);
+        assert_eq!(wasm.tag.as_deref(), Some("wasm"));
+
+        assert!(index.list(Some("WASM")).projects.is_empty());
+    }
+}
@@ -0,0 +1,391 @@
+//! Token-bucket rate limiting per client IP and route prefix
+//!
+//! Each rule gives every client a bucket of `burst` requests that refills at `rate` per second, and a request is
+//! counted against the rule with the longest matching prefix. Clients over the limit are rejected with
+//! [`RateLimited`], which tells them how long to wait with a 429 and `Retry-After`. Behind a reverse proxy the
+//! client is taken from `X-Forwarded-For`, but only when the connection comes from one of `trusted_proxies`, since
+//! anyone else can put anything in that header.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ipnet::IpNet;
+use warp::http::HeaderMap;
+use warp::path::FullPath;
+use warp::{Filter, Rejection, Reply};
+
+use crate::errors::RateLimited;
+use crate::server;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+
+    /// Proxies whose `X-Forwarded-For` is believed, as addresses or CIDR ranges
+    pub trusted_proxies: Vec<String>,
+
+    /// How often idle buckets are dropped, in seconds
+    pub prune_interval: u64,
+
+    pub rules: Vec<RateLimitRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitRule {
+    /// Request path prefix, like `/api/`
+    pub prefix: String,
+
+    /// Requests per second a client may sustain
+    pub rate: f64,
+
+    /// Requests a client may make at once after being idle
+    pub burst: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        let rule = |prefix: &str, rate, burst| RateLimitRule {
+            prefix: prefix.to_owned(),
+            rate,
+            burst,
+        };
+
+        RateLimitConfig {
+            enabled: true,
+            trusted_proxies: Vec::new(),
+            prune_interval: 60,
+            rules: vec![rule("/", 50.0, 200), rule("/api/", 10.0, 40)],
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RateLimitError {
+    #[error("rate limit prefix {0:?} must start with `/`")]
+    Prefix(String),
+
+    #[error("rate limit for {0:?} needs a positive rate and burst")]
+    Rate(String),
+
+    #[error("invalid trusted proxy {0:?}, expected an IP address or CIDR range")]
+    Proxy(String),
+}
+
+/// Networks of the trusted reverse proxies
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(Arc<Vec<IpNet>>);
+
+impl TrustedProxies {
+    pub fn new(proxies: &[String]) -> Result<TrustedProxies, RateLimitError> {
+        let networks = proxies
+            .iter()
+            .map(|proxy| match proxy.parse::<IpAddr>() {
+                Ok(ip) => Ok(IpNet::from(ip)),
+                Err(_) => proxy.parse::<IpNet>().map_err(|_| RateLimitError::Proxy(proxy.clone())),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(TrustedProxies(Arc::new(networks)))
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(&ip))
+    }
+
+    /// The client a request is from: the last `X-Forwarded-For` hop not added by a trusted proxy, or the peer itself
+    pub fn client(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.contains(peer) {
+            return peer;
+        }
+
+        let hops = headers.get_all("x-forwarded-for").iter().filter_map(|value| value.to_str().ok()).flat_map(|value| value.split(','));
+        let mut client = peer;
+
+        // walk back from the hop nearest to us for as long as the hops are trusted
+        for hop in hops.collect::<Vec<_>>().into_iter().rev() {
+            match hop.trim().parse::<IpAddr>() {
+                Ok(ip) => {
+                    client = ip;
+
+                    if !self.contains(ip) {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+
+        client
+    }
+}
+
+/// Extracts the client address, see [`TrustedProxies::client`]
+pub fn client_ip(proxies: TrustedProxies) -> impl Filter<Extract = (Option<IpAddr>,), Error = Infallible> + Clone {
+    server::remote_addr()
+        .and(warp::header::headers_cloned())
+        .map(move |remote: Option<SocketAddr>, headers: HeaderMap| remote.map(|remote| proxies.client(remote.ip(), &headers)))
+}
+
+/// IPv6 clients usually have a whole /64 to pick addresses from, so they share a bucket
+pub(crate) fn bucket_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ip) => {
+            let s = ip.segments();
+            IpAddr::V6(Ipv6Addr::new(s[0], s[1], s[2], s[3], 0, 0, 0, 0))
+        }
+        ip => ip,
+    }
+}
+
+/// A token bucket holding up to `burst` tokens, refilled at `rate` per second
+#[derive(Debug, Clone, Copy)]
+pub struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    pub fn full(burst: u32, now: Instant) -> Bucket {
+        Bucket {
+            tokens: f64::from(burst),
+            updated: now,
+        }
+    }
+
+    /// Tokens available at `now`
+    fn level(&self, rate: f64, burst: u32, now: Instant) -> f64 {
+        (self.tokens + now.saturating_duration_since(self.updated).as_secs_f64() * rate).min(f64::from(burst))
+    }
+
+    /// Takes a token, or returns how long until one is available
+    pub fn take(&mut self, rate: f64, burst: u32, now: Instant) -> Result<(), Duration> {
+        self.tokens = self.level(rate, burst, now);
+        self.updated = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+pub struct RateLimiter {
+    /// Longest prefix first
+    rules: Vec<RateLimitRule>,
+    proxies: TrustedProxies,
+    prune_interval: Duration,
+    buckets: Mutex<HashMap<(usize, IpAddr), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Result<RateLimiter, RateLimitError> {
+        for rule in &config.rules {
+            if !rule.prefix.starts_with('/') {
+                return Err(RateLimitError::Prefix(rule.prefix.clone()));
+            }
+
+            if !(rule.rate.is_finite() && rule.rate > 0.0) || rule.burst == 0 {
+                return Err(RateLimitError::Rate(rule.prefix.clone()));
+            }
+        }
+
+        let mut rules = config.rules.clone();
+        rules.sort_by_key(|rule| std::cmp::Reverse(rule.prefix.len()));
+
+        Ok(RateLimiter {
+            rules,
+            proxies: TrustedProxies::new(&config.trusted_proxies)?,
+            prune_interval: Duration::from_secs(config.prune_interval.max(1)),
+            buckets: Mutex::default(),
+        })
+    }
+
+    pub fn proxies(&self) -> &TrustedProxies {
+        &self.proxies
+    }
+
+    /// Takes a token for a request to `path` from `ip`, or returns how long until one is available
+    pub fn check(&self, path: &str, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let (index, rule) = match self.rules.iter().enumerate().find(|(_, rule)| path.starts_with(&rule.prefix)) {
+            Some(found) => found,
+            None => return Ok(()),
+        };
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry((index, bucket_key(ip))).or_insert_with(|| Bucket::full(rule.burst, now));
+
+        bucket.take(rule.rate, rule.burst, now)
+    }
+
+    /// Drops the buckets that have refilled completely, which behave exactly like new ones
+    pub fn prune(&self, now: Instant) -> usize {
+        let mut buckets = self.buckets.lock().unwrap();
+        let before = buckets.len();
+
+        buckets.retain(|&(index, _), bucket| {
+            let rule = &self.rules[index];
+            bucket.level(rule.rate, rule.burst, now) < f64::from(rule.burst)
+        });
+
+        before - buckets.len()
+    }
+
+    /// Spawns a task that periodically prunes idle buckets
+    pub fn watch(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(self.prune_interval);
+
+        tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+
+                let pruned = self.prune(Instant::now());
+
+                if pruned > 0 {
+                    log::debug!("pruned {} idle rate limit buckets", pruned);
+                }
+            }
+        });
+    }
+}
+
+/// Rejects clients over their limit with [`RateLimited`], and passes everything else on to `filter`
+pub fn limit<F, R>(limiter: Option<Arc<RateLimiter>>, filter: F) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    let proxies = limiter.as_ref().map(|limiter| limiter.proxies().clone()).unwrap_or_default();
+
+    let allowed = warp::path::full().and(client_ip(proxies)).and_then(move |path: FullPath, ip: Option<IpAddr>| {
+        let outcome = match (&limiter, ip) {
+            (Some(limiter), Some(ip)) => limiter.check(path.as_str(), ip, Instant::now()),
+            _ => Ok(()),
+        };
+
+        futures::future::ready(outcome.map_err(|wait| warp::reject::custom(RateLimited(wait))))
+    });
+
+    allowed.untuple_one().and(filter).map(Reply::into_response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(prefix: &str, rate: f64, burst: u32) -> RateLimitRule {
+        RateLimitRule {
+            prefix: prefix.to_owned(),
+            rate,
+            burst,
+        }
+    }
+
+    fn limiter(rules: Vec<RateLimitRule>) -> RateLimiter {
+        RateLimiter::new(&RateLimitConfig {
+            rules,
+            ..RateLimitConfig::default()
+        })
+        .expect("valid rate limit")
+    }
+
+    fn forwarded(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", value.parse().expect("valid header"));
+        headers
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().expect("valid address")
+    }
+
+    #[test]
+    fn buckets_refill_at_the_rate() {
+        let start = Instant::now();
+        let mut bucket = Bucket::full(2, start);
+
+        assert!(bucket.take(4.0, 2, start).is_ok());
+        assert!(bucket.take(4.0, 2, start).is_ok());
+        assert_eq!(bucket.take(4.0, 2, start), Err(Duration::from_millis(250)));
+
+        // a quarter of a second buys one token back
+        let later = start + Duration::from_millis(250);
+
+        assert!(bucket.take(4.0, 2, later).is_ok());
+        assert!(bucket.take(4.0, 2, later).is_err());
+
+        // and a long pause no more than the burst
+        let much_later = later + Duration::from_secs(60);
+
+        assert!(bucket.take(4.0, 2, much_later).is_ok());
+        assert!(bucket.take(4.0, 2, much_later).is_ok());
+        assert!(bucket.take(4.0, 2, much_later).is_err());
+    }
+
+    #[test]
+    fn the_longest_prefix_counts() {
+        let limiter = limiter(vec![rule("/", 1.0, 3), rule("/api/", 1.0, 1)]);
+        let now = Instant::now();
+        let client = ip("192.0.2.1");
+
+        assert!(limiter.check("/api/posts", client, now).is_ok());
+        assert!(limiter.check("/api/projects", client, now).is_err());
+
+        // the site's bucket is separate from the api's
+        for _ in 0..3 {
+            assert!(limiter.check("/about", client, now).is_ok());
+        }
+
+        assert!(limiter.check("/about", client, now).is_err());
+        assert!(limiter.check("/api/posts", ip("192.0.2.2"), now).is_ok());
+    }
+
+    #[test]
+    fn ipv6_clients_share_their_64() {
+        let limiter = limiter(vec![rule("/", 1.0, 1)]);
+        let now = Instant::now();
+
+        assert!(limiter.check("/", ip("2001:db8::1"), now).is_ok());
+        assert!(limiter.check("/", ip("2001:db8::2"), now).is_err());
+        assert!(limiter.check("/", ip("2001:db8:0:1::1"), now).is_ok());
+    }
+
+    #[test]
+    fn invalid_rules_are_refused() {
+        let config = |rule| RateLimitConfig {
+            rules: vec![rule],
+            ..RateLimitConfig::default()
+        };
+
+        assert!(matches!(RateLimiter::new(&config(rule("api/", 1.0, 1))), Err(RateLimitError::Prefix(_))));
+        assert!(matches!(RateLimiter::new(&config(rule("/", 0.0, 1))), Err(RateLimitError::Rate(_))));
+        assert!(matches!(RateLimiter::new(&config(rule("/", f64::NAN, 1))), Err(RateLimitError::Rate(_))));
+        assert!(matches!(RateLimiter::new(&config(rule("/", 1.0, 0))), Err(RateLimitError::Rate(_))));
+        assert!(matches!(TrustedProxies::new(&["10.0.0.0/33".to_owned()]), Err(RateLimitError::Proxy(_))));
+    }
+
+    #[test]
+    fn forwarded_for_is_only_believed_from_trusted_proxies() {
+        let proxies = TrustedProxies::new(&["10.0.0.0/8".to_owned(), "192.0.2.10".to_owned()]).expect("valid proxies");
+
+        // an untrusted peer is the client whatever it claims
+        assert_eq!(proxies.client(ip("198.51.100.7"), &forwarded("203.0.113.1")), ip("198.51.100.7"));
+
+        // a trusted one without the header is the client too
+        assert_eq!(proxies.client(ip("10.1.2.3"), &HeaderMap::new()), ip("10.1.2.3"));
+
+        assert_eq!(proxies.client(ip("10.1.2.3"), &forwarded("203.0.113.1")), ip("203.0.113.1"));
+
+        // hops added by trusted proxies are skipped, and anything a client put in front of its address is ignored
+        assert_eq!(proxies.client(ip("10.1.2.3"), &forwarded("6.6.6.6, 203.0.113.1, 192.0.2.10")), ip("203.0.113.1"));
+
+        // a hop that is not an address stops the walk at the last trusted one
+        assert_eq!(proxies.client(ip("10.1.2.3"), &forwarded("203.0.113.1, garbage, 10.0.0.5")), ip("10.0.0.5"));
+    }
+}
@@ -0,0 +1,851 @@
+//! Server-side rendering of the client views into `index.html`, as a fallback for crawlers and visitors without
+//! JavaScript
+//!
+//! This is not SSR with hydration. The client crate targets wasm and cannot be linked into the server, so the markup
+//! of `MainView` and the views it routes to is a second copy of it, written by hand. The client mounts onto the
+//! rendered `#app` element and replaces its contents with the live view, throwing the rendered markup away.
+//!
+//! The tests at the bottom keep the copies together. They pin the markup of every [`Page`], and compare it with the
+//! client's source: every string of `client/src/views` must show up in the rendered markup unless it belongs to a state
+//! only the client reaches, like loading, and every class rendered here must exist in `client/src`. A change to the
+//! client's markup therefore fails the tests until it is made here too.
+
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use hyper::body::{Body, Bytes};
+use nova_common::api::{PostList, PostSummary, Project};
+use nova_common::Page;
+use warp::http::{header, HeaderMap, Response, StatusCode};
+use warp::Rejection;
+
+use crate::assets::Assets;
+use crate::blog::{Blog, Index as BlogIndex, Post};
+use crate::dev;
+use crate::errors::Internal;
+use crate::projects::{Index as ProjectIndex, Projects};
+
+pub const SITE_NAME: &str = "Nova Dev";
+
+/// Every page that should exist as its own document, including one per project and published post
+pub fn pages(blog: &BlogIndex, projects: &ProjectIndex) -> Vec<Page> {
+    let mut pages = Page::fixed().to_vec();
+    pages.extend(projects.projects.iter().map(|project| Page::Project(project.slug.clone())));
+    pages.extend(blog.posts.iter().map(|post| Page::Post(post.summary.slug.clone())));
+    pages
+}
+
+/// A page rendered for a particular state of the content
+pub struct Document {
+    pub title: String,
+    pub description: String,
+    pub status: StatusCode,
+
+    /// Markup of the `#app` element
+    pub markup: String,
+}
+
+impl Document {
+    pub fn new(page: &Page, blog: &Blog, projects: &ProjectIndex) -> Document {
+        let (title, description, status) = Document::head(page, &blog.index(), projects);
+
+        Document {
+            title,
+            description,
+            status,
+            markup: main_view(page, blog, projects),
+        }
+    }
+
+    /// Title, description and status of `page`, without rendering it
+    pub fn head(page: &Page, blog: &BlogIndex, projects: &ProjectIndex) -> (String, String, StatusCode) {
+        let found = |title: String, description: &str| (title, description.to_owned(), StatusCode::OK);
+
+        match page {
+            Page::Index => found(SITE_NAME.to_owned(), "Personal site of Nova, built with Rust and WebAssembly."),
+            Page::Portfolio => found(
+                format!("Portfolio · {}", SITE_NAME),
+                "Projects and experiments by Nova, including a DoomFire simulation you can draw on.",
+            ),
+            Page::Project(slug) => match projects.get(slug) {
+                Some(project) => found(format!("{} · {}", project.title, SITE_NAME), &project.description),
+                None => (format!("Project not found · {}", SITE_NAME), "This project does not exist.".to_owned(), StatusCode::NOT_FOUND),
+            },
+            Page::About => found(format!("About · {}", SITE_NAME), "About Nova."),
+            Page::Blog => found(format!("Blog · {}", SITE_NAME), "Writing by Nova."),
+            Page::Post(slug) => match blog.get(slug) {
+                Some(post) => found(format!("{} · {}", post.summary.title, SITE_NAME), &post.summary.summary),
+                None => (format!("Post not found · {}", SITE_NAME), "This post does not exist.".to_owned(), StatusCode::NOT_FOUND),
+            },
+            Page::Contact => found(format!("Contact · {}", SITE_NAME), "Get in touch with Nova."),
+            Page::NotFound(_) => (format!("Page not found · {}", SITE_NAME), "This page does not exist.".to_owned(), StatusCode::NOT_FOUND),
+        }
+    }
+}
+
+/// Escapes text for use in element content and double-quoted attributes
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Markup of `MainView` for `page`, see `client/src/views/mod.rs`
+pub fn main_view(page: &Page, blog: &Blog, projects: &ProjectIndex) -> String {
+    let mut out = String::new();
+
+    let nav_item = |out: &mut String, route: Page, label: &str| {
+        let class = if route == *page { "nav-link active" } else { "nav-link" };
+        let _ = write!(out, r#"<li class="nav-item"><a class="{}" href="{}">{}</a></li>"#, class, route.path(), label);
+    };
+
+    out.push_str(r#"<header class="navbar flex-row navbar-dark navbar-expand-sm bg-dark" style="border-bottom: 1px solid #888;">"#);
+    out.push_str(r#"<a class="navbar-brand" href="/">@Nova</a>"#);
+    out.push_str(r#"<button type="button" class="navbar-toggler" aria-controls="" aria-expanded="true" aria-label="Toggle Navbar">"#);
+    out.push_str(r#"<span class="navbar-toggler-icon"></span></button>"#);
+    out.push_str(r#"<div class="navbar-collapse collapse show" id="navbar-collapse"><ul class="navbar-nav mr-auto">"#);
+
+    nav_item(&mut out, Page::Index, "Home");
+    nav_item(&mut out, Page::About, "About");
+    nav_item(&mut out, Page::Portfolio, "Portfolio");
+    nav_item(&mut out, Page::Blog, "Blog");
+    nav_item(&mut out, Page::Contact, "Contact");
+
+    out.push_str(r#"</ul><hr><span class="navbar-text">Powered by Rust/WASM</span>"#);
+    out.push_str(r#"<a href="https://github.com/rust-lang/rust" target="_blank">"#);
+    out.push_str(r#"<img style="height:40px" src="https://www.rust-lang.org/logos/rust-logo-blk.svg"></a>"#);
+    out.push_str("</div></header>");
+
+    match page {
+        Page::NotFound(path) if path.is_empty() => return out + "Page not found",
+        Page::NotFound(path) => {
+            let _ = write!(out, "Page &#39;{}&#39; not found", escape(path));
+            return out;
+        }
+        Page::Blog => return out + &blog_view(&blog.index().list(1, blog.config().per_page, None)),
+        Page::Post(slug) => return out + &post_view(blog.index().get(slug).map(|post| &**post)),
+        Page::Project(slug) => return out + &project_view(projects.get(slug)),
+        Page::Contact => return out + &contact_view(),
+        _ => {}
+    }
+
+    let hidden = |route: Page| if *page == route { r#"class="""# } else { r#"class="hidden""# };
+
+    let _ = write!(out, "<div {}>Hello, Index!</div>", hidden(Page::Index));
+    let _ = write!(out, "<div {}>Hello, About!</div>", hidden(Page::About));
+    let _ = write!(out, "<div {}>{}</div>", hidden(Page::Portfolio), portfolio_view(projects));
+
+    out
+}
+
+/// Date and tag badges, `badge` being the class of each tag
+fn meta(out: &mut String, date: &DateTime<Utc>, tags: &[String], badge: &str) {
+    let _ = write!(
+        out,
+        r#"<p class="text-muted"><time datetime="{}">{}</time>"#,
+        date.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        date.format("%Y-%m-%d")
+    );
+
+    for tag in tags {
+        let _ = write!(out, r#" <span class="{}">{}</span>"#, badge, escape(tag));
+    }
+
+    out.push_str("</p>");
+}
+
+fn post_meta(out: &mut String, post: &PostSummary) {
+    meta(out, &post.date, &post.tags, "badge badge-secondary blog-tag");
+}
+
+/// Markup of the canvas a demo renders into, see `demo` in `client/src/views/portfolio.rs`
+fn demo(id: &str) -> &'static str {
+    match id {
+        "doom-fire" => r#"<canvas width="600" height="400" style="border: 1px solid black; touch-action: none;"></canvas>"#,
+        _ => "",
+    }
+}
+
+/// Markup of `PortfolioView` once the projects have loaded, unfiltered, see `client/src/views/portfolio.rs`
+pub fn portfolio_view(projects: &ProjectIndex) -> String {
+    let list = projects.list(None);
+    let mut out = String::from(r#"<div class="container portfolio"><h1>Portfolio</h1>"#);
+
+    if !list.tags.is_empty() {
+        out.push_str(r#"<nav class="portfolio-filter"><button type="button" class="btn btn-sm btn-secondary">All</button>"#);
+
+        for tag in &list.tags {
+            let _ = write!(out, r#" <button type="button" class="btn btn-sm btn-outline-secondary">{}</button>"#, escape(tag));
+        }
+
+        out.push_str("</nav>");
+    }
+
+    if list.projects.is_empty() {
+        out.push_str(r#"<p class="text-muted">Nothing here yet.</p>"#);
+    }
+
+    out.push_str(r#"<div class="row portfolio-grid">"#);
+
+    for project in &list.projects {
+        out.push_str(r#"<div class="col-sm-6 col-lg-4 mb-4"><div class="card h-100 project-card">"#);
+
+        if let Some(ref src) = project.thumbnail {
+            let _ = write!(out, r#"<img class="card-img-top" src="{}" alt="{}">"#, escape(src), escape(&project.title));
+        }
+
+        let _ = write!(
+            out,
+            r#"<div class="card-body"><h5 class="card-title"><a href="/portfolio/{}">{}</a></h5><p class="card-text">{}</p>"#,
+            escape(&project.slug),
+            escape(&project.title),
+            escape(&project.description)
+        );
+        meta(&mut out, &project.date, &project.tags, "badge badge-secondary");
+        out.push_str("</div></div></div>");
+    }
+
+    out.push_str("</div></div>");
+    out
+}
+
+/// Markup of `ProjectView` once the project has loaded, see `client/src/views/portfolio.rs`
+pub fn project_view(project: Option<&Project>) -> String {
+    let project = match project {
+        Some(project) => project,
+        None => return r#"<div class="container project"><h1>Project not found</h1><p><a href="/portfolio">All projects</a></p></div>"#.to_owned(),
+    };
+
+    let mut out = format!(r#"<article class="container project"><h1>{}</h1>"#, escape(&project.title));
+    meta(&mut out, &project.date, &project.tags, "badge badge-secondary");
+
+    match (project.demo.as_deref(), project.thumbnail.as_ref()) {
+        (Some(id), _) => {
+            let _ = write!(out, r#"<div class="project-demo">{}</div>"#, demo(id));
+        }
+        (None, Some(src)) => {
+            let _ = write!(out, r#"<img class="img-fluid project-thumbnail" src="{}" alt="{}">"#, escape(src), escape(&project.title));
+        }
+        (None, None) => {}
+    }
+
+    let _ = write!(out, r#"<p>{}</p><ul class="list-inline project-links">"#, escape(&project.description));
+
+    for link in &project.links {
+        let _ = write!(
+            out,
+            r#"<li class="list-inline-item"><a href="{}" target="_blank" rel="noopener">{}</a></li>"#,
+            escape(&link.url),
+            escape(&link.label)
+        );
+    }
+
+    out.push_str(r#"</ul><p><a href="/portfolio">All projects</a></p></article>"#);
+    out
+}
+
+/// Markup of `BlogView` once its first page has loaded, see `client/src/views/blog.rs`
+pub fn blog_view(list: &PostList) -> String {
+    let mut out = String::from(r#"<div class="container blog"><h1>Blog</h1>"#);
+
+    if list.posts.is_empty() {
+        out.push_str(r#"<p class="text-muted">Nothing here yet.</p>"#);
+    }
+
+    out.push_str(r#"<ul class="list-unstyled blog-posts">"#);
+
+    for post in &list.posts {
+        let _ = write!(out, r#"<li class="blog-post"><h2><a href="/blog/{}">{}</a></h2>"#, escape(&post.slug), escape(&post.title));
+        post_meta(&mut out, post);
+        let _ = write!(out, "<p>{}</p></li>", escape(&post.summary));
+    }
+
+    out.push_str(r#"</ul><nav class="blog-pages">"#);
+
+    if list.page < list.total_pages {
+        out.push_str(r#"<button type="button" class="btn btn-link">Older posts</button>"#);
+    }
+
+    out.push_str("</nav></div>");
+    out
+}
+
+/// Markup of `PostView` once the post has loaded, see `client/src/views/blog.rs`
+pub fn post_view(post: Option<&Post>) -> String {
+    let post = match post {
+        Some(post) => post,
+        None => return r#"<div class="container blog"><h1>Post not found</h1><p><a href="/blog">All posts</a></p></div>"#.to_owned(),
+    };
+
+    let mut out = format!(r#"<article class="container blog-post"><h1>{}</h1>"#, escape(&post.summary.title));
+    post_meta(&mut out, &post.summary);
+    let _ = write!(out, r#"<div class="blog-content">{}</div><p><a href="/blog">All posts</a></p></article>"#, post.html);
+    out
+}
+
+/// Markup of an empty `ContactView`, see `client/src/views/contact.rs`
+pub fn contact_view() -> String {
+    let mut out = String::from(r#"<div class="container contact"><h1>Contact</h1><form class="contact-form" novalidate>"#);
+
+    for (id, name, label, kind) in &[
+        ("contact-name", "name", "Name", "text"),
+        ("contact-email", "email", "Email", "email"),
+        ("contact-subject", "subject", "Subject (optional)", "text"),
+        ("contact-message", "message", "Message", "textarea"),
+    ] {
+        let _ = write!(out, r#"<div class="form-group"><label for="{}">{}</label>"#, id, label);
+
+        let _ = match *kind {
+            "textarea" => write!(out, r#"<textarea id="{}" name="{}" class="form-control" rows="6"></textarea>"#, id, name),
+            kind => write!(out, r#"<input id="{}" name="{}" type="{}" class="form-control" value="">"#, id, name, kind),
+        };
+
+        out.push_str("</div>");
+    }
+
+    out.push_str(r#"<div class="contact-hp" aria-hidden="true"><label>Leave this empty"#);
+    out.push_str(r#"<input type="text" name="website" tabindex="-1" autocomplete="off" value=""></label></div>"#);
+    out.push_str(r#"<button type="submit" class="btn btn-primary">Send</button></form></div>"#);
+    out
+}
+
+/// `<link>`s advertising the feeds titled `title` to browsers and feed readers
+pub fn feed_links(title: &str) -> String {
+    let title = escape(title);
+
+    format!(
+        r#"<link rel="alternate" type="application/atom+xml" title="{0}" href="/feed.atom"><link rel="alternate" type="application/rss+xml" title="{0}" href="/feed.xml">"#,
+        title
+    )
+}
+
+/// Inserts `markup` into the `#app` element of the template and sets the page's title and description, linking the
+/// feeds titled `feeds` if they are served.
+///
+/// Templates without an `#app` element get one at the start of the body.
+pub fn inject(template: &str, document: &Document, feeds: Option<&str>) -> String {
+    let mut html = template.to_owned();
+
+    let title = format!("<title>{}</title>", escape(&document.title));
+
+    match (html.find("<title>"), html.find("</title>")) {
+        (Some(start), Some(end)) if start < end => html.replace_range(start..end + "</title>".len(), &title),
+        _ => insert_before(&mut html, "</head>", &title),
+    }
+
+    let meta = format!(r#"<meta name="description" content="{}">"#, escape(&document.description));
+    insert_before(&mut html, "</head>", &meta);
+
+    if let Some(title) = feeds {
+        insert_before(&mut html, "</head>", &feed_links(title));
+    }
+
+    let app = format!(r#"<div id="app">{}</div>"#, document.markup);
+
+    if let Some(start) = html.find(r#"<div id="app"></div>"#) {
+        html.replace_range(start..start + r#"<div id="app"></div>"#.len(), &app);
+    } else if let Some(body) = html.find("<body") {
+        let at = html[body..].find('>').map_or(html.len(), |end| body + end + 1);
+        html.insert_str(at, &app);
+    } else {
+        html.push_str(&app);
+    }
+
+    html
+}
+
+fn insert_before(html: &mut String, needle: &str, text: &str) {
+    match html.find(needle) {
+        Some(at) => html.insert_str(at, text),
+        None => html.push_str(text),
+    }
+}
+
+/// Renders pages into the dist directory's `index.html`, rereading the template when it changes
+pub struct Renderer {
+    assets: Arc<Assets>,
+    blog: Arc<Blog>,
+    projects: Arc<Projects>,
+
+    /// Title of the feeds linked from every page, `None` when they are not served
+    feeds: Option<String>,
+    enabled: bool,
+
+    /// Add the live reload script to every page
+    dev: bool,
+    template: Mutex<Option<(Option<SystemTime>, Arc<str>)>>,
+}
+
+impl Renderer {
+    pub fn new(assets: Arc<Assets>, blog: Arc<Blog>, projects: Arc<Projects>, feeds: Option<String>, enabled: bool, dev: bool) -> Renderer {
+        Renderer {
+            assets,
+            blog,
+            projects,
+            feeds,
+            enabled,
+            dev,
+            template: Mutex::new(None),
+        }
+    }
+
+    pub async fn template(&self) -> Option<Arc<str>> {
+        let modified = self.assets.modified("index.html").await?;
+
+        if let Some((cached_modified, ref template)) = *self.template.lock().unwrap() {
+            if cached_modified == modified && modified.is_some() {
+                return Some(template.clone());
+            }
+        }
+
+        let template: Arc<str> = String::from_utf8(self.assets.read("index.html").await?.to_vec()).ok()?.into();
+        *self.template.lock().unwrap() = Some((modified, template.clone()));
+
+        Some(template)
+    }
+
+    /// The complete document for `page`, along with its HTML
+    pub async fn render(&self, page: &Page) -> Option<(Document, String)> {
+        let template = self.template().await?;
+        let document = Document::new(page, &self.blog, &self.projects.index());
+        let mut html = inject(&template, &document, self.feeds.as_deref());
+
+        if self.dev {
+            insert_before(&mut html, "</body>", dev::SCRIPT_TAG);
+        }
+
+        Some((document, html))
+    }
+
+    /// Responds with the document for `page`, or the bare `index.html` if rendering is disabled.
+    /// Without a readable `index.html` the request is rejected with [`Internal`].
+    pub async fn respond(&self, page: &Page, headers: &HeaderMap) -> Result<Response<Body>, Rejection> {
+        let internal = || warp::reject::custom(Internal);
+        let status = Document::head(page, &self.blog.index(), &self.projects.index()).2;
+
+        let html = match (self.enabled, self.dev, self.feeds.as_deref()) {
+            (true, _, _) => Some(self.render(page).await.ok_or_else(internal)?.1),
+            (false, false, None) => None,
+            (false, dev, feeds) => {
+                let mut html = self.template().await.ok_or_else(internal)?.to_string();
+
+                if let Some(title) = feeds {
+                    insert_before(&mut html, "</head>", &feed_links(title));
+                }
+
+                if dev {
+                    insert_before(&mut html, "</body>", dev::SCRIPT_TAG);
+                }
+
+                Some(html)
+            }
+        };
+
+        // an error page must carry the full document, never a 304 or a partial body
+        let mut headers = headers.clone();
+
+        if status != StatusCode::OK {
+            for name in &[header::IF_NONE_MATCH, header::IF_MODIFIED_SINCE, header::RANGE, header::IF_RANGE] {
+                headers.remove(name);
+            }
+        }
+
+        let mut res = match html {
+            Some(html) => self.assets.serve_generated("index.html", mime_guess::mime::TEXT_HTML_UTF_8, Bytes::from(html), &headers).await,
+            None => self.assets.serve("index.html", &headers).await.map_err(|_| internal())?,
+        };
+
+        if res.status() == StatusCode::OK {
+            *res.status_mut() = status;
+        }
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Pins the markup of every page, so changing it is a deliberate step that is a reminder of the client views
+
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+    use crate::blog::BlogConfig;
+
+    const POST: &str = "+++\ntitle = \"Hello, <World>\"\ndate = 2020-07-01\ntags = [\"rust\"]\n+++\n\nFirst *post*.\n";
+
+    const PROJECTS: &str = r#"
+[[project]]
+slug = "doom-fire"
+title = "Doom Fire"
+description = "The PSX Doom fire effect."
+date = 2020-06-14
+tags = ["wasm"]
+demo = "doom-fire"
+links = [{ label = "Source", url = "https://example.com/doom-fire" }]
+"#;
+
+    const HEADER: &str = concat!(
+        r#"<header class="navbar flex-row navbar-dark navbar-expand-sm bg-dark" style="border-bottom: 1px solid #888;">"#,
+        r#"<a class="navbar-brand" href="/">@Nova</a>"#,
+        r#"<button type="button" class="navbar-toggler" aria-controls="" aria-expanded="true" aria-label="Toggle Navbar">"#,
+        r#"<span class="navbar-toggler-icon"></span></button>"#,
+        r#"<div class="navbar-collapse collapse show" id="navbar-collapse"><ul class="navbar-nav mr-auto">"#,
+        r#"<li class="nav-item"><a class="nav-link" href="/">Home</a></li>"#,
+        r#"<li class="nav-item"><a class="nav-link" href="/about">About</a></li>"#,
+        r#"<li class="nav-item"><a class="nav-link" href="/portfolio">Portfolio</a></li>"#,
+        r#"<li class="nav-item"><a class="nav-link" href="/blog">Blog</a></li>"#,
+        r#"<li class="nav-item"><a class="nav-link" href="/contact">Contact</a></li>"#,
+        r#"</ul><hr><span class="navbar-text">Powered by Rust/WASM</span>"#,
+        r#"<a href="https://github.com/rust-lang/rust" target="_blank">"#,
+        r#"<img style="height:40px" src="https://www.rust-lang.org/logos/rust-logo-blk.svg"></a>"#,
+        "</div></header>",
+    );
+
+    const PORTFOLIO: &str = concat!(
+        r#"<div class="container portfolio"><h1>Portfolio</h1>"#,
+        r#"<nav class="portfolio-filter"><button type="button" class="btn btn-sm btn-secondary">All</button>"#,
+        r#" <button type="button" class="btn btn-sm btn-outline-secondary">wasm</button></nav>"#,
+        r#"<div class="row portfolio-grid"><div class="col-sm-6 col-lg-4 mb-4"><div class="card h-100 project-card">"#,
+        r#"<div class="card-body"><h5 class="card-title"><a href="/portfolio/doom-fire">Doom Fire</a></h5>"#,
+        r#"<p class="card-text">The PSX Doom fire effect.</p>"#,
+        r#"<p class="text-muted"><time datetime="2020-06-14T00:00:00Z">2020-06-14</time> <span class="badge badge-secondary">wasm</span></p>"#,
+        "</div></div></div></div></div>",
+    );
+
+    const BLOG: &str = concat!(
+        r#"<div class="container blog"><h1>Blog</h1><ul class="list-unstyled blog-posts">"#,
+        r#"<li class="blog-post"><h2><a href="/blog/hello-world">Hello, &lt;World&gt;</a></h2>"#,
+        r#"<p class="text-muted"><time datetime="2020-07-01T00:00:00Z">2020-07-01</time> <span class="badge badge-secondary blog-tag">rust</span></p>"#,
+        "<p>First post.</p></li>",
+        r#"</ul><nav class="blog-pages"></nav></div>"#,
+    );
+
+    const POST_VIEW: &str = concat!(
+        r#"<article class="container blog-post"><h1>Hello, &lt;World&gt;</h1>"#,
+        r#"<p class="text-muted"><time datetime="2020-07-01T00:00:00Z">2020-07-01</time> <span class="badge badge-secondary blog-tag">rust</span></p>"#,
+        "<div class=\"blog-content\"><p>First <em>post</em>.</p>\n</div>",
+        r#"<p><a href="/blog">All posts</a></p></article>"#,
+    );
+
+    const PROJECT_VIEW: &str = concat!(
+        r#"<article class="container project"><h1>Doom Fire</h1>"#,
+        r#"<p class="text-muted"><time datetime="2020-06-14T00:00:00Z">2020-06-14</time> <span class="badge badge-secondary">wasm</span></p>"#,
+        r#"<div class="project-demo"><canvas width="600" height="400" style="border: 1px solid black; touch-action: none;"></canvas></div>"#,
+        r#"<p>The PSX Doom fire effect.</p><ul class="list-inline project-links">"#,
+        r#"<li class="list-inline-item"><a href="https://example.com/doom-fire" target="_blank" rel="noopener">Source</a></li>"#,
+        r#"</ul><p><a href="/portfolio">All projects</a></p></article>"#,
+    );
+
+    const CONTACT: &str = concat!(
+        r#"<div class="container contact"><h1>Contact</h1><form class="contact-form" novalidate>"#,
+        r#"<div class="form-group"><label for="contact-name">Name</label>"#,
+        r#"<input id="contact-name" name="name" type="text" class="form-control" value=""></div>"#,
+        r#"<div class="form-group"><label for="contact-email">Email</label>"#,
+        r#"<input id="contact-email" name="email" type="email" class="form-control" value=""></div>"#,
+        r#"<div class="form-group"><label for="contact-subject">Subject (optional)</label>"#,
+        r#"<input id="contact-subject" name="subject" type="text" class="form-control" value=""></div>"#,
+        r#"<div class="form-group"><label for="contact-message">Message</label>"#,
+        r#"<textarea id="contact-message" name="message" class="form-control" rows="6"></textarea></div>"#,
+        r#"<div class="contact-hp" aria-hidden="true"><label>Leave this empty"#,
+        r#"<input type="text" name="website" tabindex="-1" autocomplete="off" value=""></label></div>"#,
+        r#"<button type="submit" class="btn btn-primary">Send</button></form></div>"#,
+    );
+
+    /// A project with a thumbnail instead of a demo, which the other manifest does not cover
+    const THUMBNAIL: &str = r#"
+[[project]]
+slug = "lamp"
+title = "Lamp"
+description = "A lamp."
+date = 2020-05-01
+thumbnail = "images/lamp.png"
+"#;
+
+    /// Strings of `client/src/views` that belong to states the server never renders: loading, failures, filters,
+    /// later pages and a sent form, along with the headers, formats and canvas and socket arguments that never become
+    /// markup
+    const CLIENT_ONLY: &[&str] = &[
+        "Loading…",
+        "Posts could not be loaded.",
+        "Projects could not be loaded.",
+        "Posts tagged ",
+        "blog-filter",
+        "Show all",
+        "Newer posts",
+        "Sending…",
+        "Thanks, your message is on its way.",
+        "The form could not be loaded, please reload the page.",
+        "The message could not be sent, please try again later.",
+        "alert alert-danger",
+        "alert alert-success",
+        "form-control is-invalid",
+        "invalid-feedback",
+        "sent",
+        "small",
+        "Content-Type",
+        "application/json",
+        "%Y-%m-%d",
+        "div",
+        "2d",
+        "wss",
+    ];
+
+    /// The string literals of a Rust source file, leaving out comments
+    fn literals(source: &str) -> Vec<String> {
+        let mut literals = Vec::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '/' if chars.peek() == Some(&'/') => {
+                    chars.by_ref().find(|&c| c == '\n');
+                }
+                '"' => {
+                    let mut literal = String::new();
+
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '\\' => literal.extend(chars.next()),
+                            '"' => break,
+                            c => literal.push(c),
+                        }
+                    }
+
+                    literals.push(literal);
+                }
+                _ => {}
+            }
+        }
+
+        literals
+    }
+
+    /// The string literals of every Rust file under `dir`, by file
+    fn client_literals(dir: &Path) -> Vec<(PathBuf, Vec<String>)> {
+        let mut files = Vec::new();
+
+        for entry in fs::read_dir(dir).expect("client sources") {
+            let path = entry.expect("client source").path();
+
+            if path.is_dir() {
+                files.extend(client_literals(&path));
+            } else if path.extension().is_some_and(|ext| ext == "rs") {
+                let source = fs::read_to_string(&path).expect("readable client source");
+                files.push((path, literals(&source)));
+            }
+        }
+
+        files.sort();
+        files
+    }
+
+    /// The markup of every page and of the states the fixtures of the pinned tests leave out
+    fn everything_rendered() -> String {
+        let (blog, projects) = (blog(), projects());
+        let thumbnail = ProjectIndex::parse(Path::new("projects.toml"), THUMBNAIL).expect("valid test manifest");
+
+        let mut pages = pages(&blog.index(), &projects);
+        pages.push(Page::Project("missing".to_owned()));
+        pages.push(Page::Post("missing".to_owned()));
+        pages.push(Page::NotFound("/missing".to_owned()));
+        pages.push(Page::NotFound(String::new()));
+
+        let mut out: String = pages.iter().map(|page| Document::new(page, &blog, &projects).markup).collect();
+
+        out.push_str(&portfolio_view(&thumbnail));
+        out.push_str(&project_view(thumbnail.get("lamp")));
+        out.push_str(&portfolio_view(&ProjectIndex::default()));
+        out.push_str(&blog_view(&PostList {
+            posts: Vec::new(),
+            page: 1,
+            per_page: 10,
+            total: 20,
+            total_pages: 2,
+            tag: None,
+        }));
+
+        out
+    }
+
+    /// A blog of one post, read from a temporary directory that is removed again
+    fn blog() -> Blog {
+        let content = std::env::temp_dir().join(format!("nova-test-{}", uuid::Uuid::new_v4().to_simple()));
+
+        fs::create_dir_all(content.join("posts")).expect("create posts directory");
+        fs::write(content.join("posts/hello-world.md"), POST).expect("write post");
+
+        let blog = Blog::new(BlogConfig {
+            content: content.clone(),
+            ..BlogConfig::default()
+        });
+
+        let _ = fs::remove_dir_all(content);
+        blog
+    }
+
+    fn projects() -> ProjectIndex {
+        ProjectIndex::parse(Path::new("projects.toml"), PROJECTS).expect("valid test manifest")
+    }
+
+    /// The header with the link to `active` marked, if it is in the navbar
+    fn header(active: &Page) -> String {
+        let link = format!(r#"<a class="nav-link" href="{}">"#, active.path());
+
+        match Page::fixed().contains(active) {
+            true => HEADER.replacen(&link, &format!(r#"<a class="nav-link active" href="{}">"#, active.path()), 1),
+            false => HEADER.to_owned(),
+        }
+    }
+
+    /// The three views the client keeps mounted, all but `shown` hidden
+    fn tabs(shown: &Page) -> String {
+        let class = |page: Page| if page == *shown { "" } else { "hidden" };
+
+        format!(
+            r#"<div class="{}">Hello, Index!</div><div class="{}">Hello, About!</div><div class="{}">{}</div>"#,
+            class(Page::Index),
+            class(Page::About),
+            class(Page::Portfolio),
+            PORTFOLIO
+        )
+    }
+
+    #[test]
+    fn every_page_is_pinned() {
+        let (blog, projects) = (blog(), projects());
+
+        let cases = vec![
+            (Page::Index, "Nova Dev", StatusCode::OK, tabs(&Page::Index)),
+            (Page::Portfolio, "Portfolio · Nova Dev", StatusCode::OK, tabs(&Page::Portfolio)),
+            (Page::About, "About · Nova Dev", StatusCode::OK, tabs(&Page::About)),
+            (Page::Blog, "Blog · Nova Dev", StatusCode::OK, BLOG.to_owned()),
+            (Page::Contact, "Contact · Nova Dev", StatusCode::OK, CONTACT.to_owned()),
+            (Page::Project("doom-fire".to_owned()), "Doom Fire · Nova Dev", StatusCode::OK, PROJECT_VIEW.to_owned()),
+            (Page::Post("hello-world".to_owned()), "Hello, <World> · Nova Dev", StatusCode::OK, POST_VIEW.to_owned()),
+            (
+                Page::Project("missing".to_owned()),
+                "Project not found · Nova Dev",
+                StatusCode::NOT_FOUND,
+                r#"<div class="container project"><h1>Project not found</h1><p><a href="/portfolio">All projects</a></p></div>"#.to_owned(),
+            ),
+            (
+                Page::Post("missing".to_owned()),
+                "Post not found · Nova Dev",
+                StatusCode::NOT_FOUND,
+                r#"<div class="container blog"><h1>Post not found</h1><p><a href="/blog">All posts</a></p></div>"#.to_owned(),
+            ),
+            (Page::NotFound(String::new()), "Page not found · Nova Dev", StatusCode::NOT_FOUND, "Page not found".to_owned()),
+            (
+                Page::NotFound("/no/<such>".to_owned()),
+                "Page not found · Nova Dev",
+                StatusCode::NOT_FOUND,
+                "Page &#39;/no/&lt;such&gt;&#39; not found".to_owned(),
+            ),
+        ];
+
+        for (page, title, status, body) in cases {
+            let document = Document::new(&page, &blog, &projects);
+
+            assert_eq!(document.title, title, "{:?}", page);
+            assert_eq!(document.status, status, "{:?}", page);
+            assert_eq!(document.markup, header(&page) + &body, "{:?}", page);
+        }
+    }
+
+    #[test]
+    fn every_page_is_listed() {
+        let (blog, projects) = (blog(), projects());
+
+        let mut expected = Page::fixed().to_vec();
+        expected.push(Page::Project("doom-fire".to_owned()));
+        expected.push(Page::Post("hello-world".to_owned()));
+
+        assert_eq!(pages(&blog.index(), &projects), expected);
+    }
+
+    #[test]
+    fn documents_are_injected_into_the_template() {
+        let document = Document {
+            title: "A & B".to_owned(),
+            description: "\"quoted\"".to_owned(),
+            status: StatusCode::OK,
+            markup: "<p>hi</p>".to_owned(),
+        };
+
+        let template = r#"<html><head><title>Nova Dev</title></head><body><div id="app"></div></body></html>"#;
+
+        assert_eq!(
+            inject(template, &document, None),
+            r#"<html><head><title>A &amp; B</title><meta name="description" content="&quot;quoted&quot;"></head><body><div id="app"><p>hi</p></div></body></html>"#
+        );
+
+        assert_eq!(
+            inject("<html><head></head><body class=\"x\"></body></html>", &document, None),
+            r#"<html><head><title>A &amp; B</title><meta name="description" content="&quot;quoted&quot;"></head><body class="x"><div id="app"><p>hi</p></div></body></html>"#
+        );
+
+        let html = inject(template, &document, Some("Nova & Co"));
+
+        assert!(html.contains(r#"<link rel="alternate" type="application/atom+xml" title="Nova &amp; Co" href="/feed.atom">"#));
+        assert!(html.contains(r#"<link rel="alternate" type="application/rss+xml" title="Nova &amp; Co" href="/feed.xml"></head>"#));
+    }
+
+    #[test]
+    fn client_markup_is_rendered() {
+        let rendered = everything_rendered();
+        let views = Path::new(env!("CARGO_MANIFEST_DIR")).join("../client/src/views");
+        let mut missing = Vec::new();
+
+        for (path, literals) in client_literals(&views) {
+            for literal in literals {
+                if literal.trim().is_empty() || CLIENT_ONLY.contains(&literal.as_str()) {
+                    continue;
+                }
+
+                // format strings are matched piece by piece
+                if !literal.split("{}").all(|piece| rendered.contains(&escape(piece))) {
+                    missing.push(format!("{}: {:?}", path.display(), literal));
+                }
+            }
+        }
+
+        assert!(missing.is_empty(), "the client views have markup this module does not render:\n{}", missing.join("\n"));
+    }
+
+    #[test]
+    fn rendered_classes_exist_in_the_client() {
+        let rendered = everything_rendered();
+        let client = Path::new(env!("CARGO_MANIFEST_DIR")).join("../client/src");
+
+        let known: Vec<String> = client_literals(&client).into_iter().flat_map(|(_, literals)| literals).collect();
+        let known: Vec<&str> = known.iter().flat_map(|literal| literal.split_whitespace()).collect();
+
+        let mut unknown: Vec<&str> = rendered
+            .split(r#"class=""#)
+            .skip(1)
+            .filter_map(|rest| rest.split('"').next())
+            .flat_map(str::split_whitespace)
+            .filter(|class| !known.contains(class))
+            .collect();
+
+        unknown.sort_unstable();
+        unknown.dedup();
+
+        assert!(unknown.is_empty(), "classes rendered here are not used by the client: {:?}", unknown);
+    }
+}
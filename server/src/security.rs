@@ -0,0 +1,165 @@
+//! Security headers for the single-page app
+//!
+//! The default Content-Security-Policy allows exactly what the Yew/WASM client needs: same-origin scripts plus
+//! `wasm-unsafe-eval` for `WebAssembly.instantiate`, inline `style` attributes emitted by components, the worker
+//! bundle and the Rust logo in the navbar.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use warp::http::{header::HeaderName, HeaderMap, HeaderValue};
+use warp::path::FullPath;
+use warp::{Filter, Rejection, Reply};
+
+pub const DEFAULT_CSP: &str = "default-src 'self'; \
+                               script-src 'self' 'wasm-unsafe-eval'; \
+                               style-src 'self' 'unsafe-inline'; \
+                               img-src 'self' data: https://www.rust-lang.org; \
+                               font-src 'self'; \
+                               connect-src 'self'; \
+                               worker-src 'self' blob:; \
+                               object-src 'none'; \
+                               base-uri 'self'; \
+                               form-action 'self'; \
+                               frame-ancestors 'none'";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SecurityOverride {
+    /// Request path prefix the override applies to
+    pub prefix: String,
+
+    /// Headers to set for matching paths. An empty value stops the header from being sent.
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityConfig {
+    pub content_security_policy: Option<String>,
+    pub referrer_policy: Option<String>,
+    pub permissions_policy: Option<String>,
+    pub frame_options: Option<String>,
+
+    /// Send COOP/COEP/CORP so the page is cross-origin isolated and may use `SharedArrayBuffer`.
+    ///
+    /// Note that every cross-origin subresource (such as the navbar logo) must then opt in with CORP or CORS.
+    pub cross_origin_isolation: bool,
+
+    /// Later entries take precedence over earlier ones
+    pub overrides: Vec<SecurityOverride>,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        SecurityConfig {
+            content_security_policy: Some(DEFAULT_CSP.to_owned()),
+            referrer_policy: Some("strict-origin-when-cross-origin".to_owned()),
+            permissions_policy: Some("camera=(), microphone=(), geolocation=(), payment=(), usb=()".to_owned()),
+            frame_options: Some("DENY".to_owned()),
+            cross_origin_isolation: false,
+            overrides: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SecurityError {
+    #[error("invalid header name {0:?}")]
+    Name(String),
+
+    #[error("invalid value for header {0}: {1:?}")]
+    Value(String, String),
+}
+
+/// `None` suppresses the header
+type HeaderList = Vec<(HeaderName, Option<HeaderValue>)>;
+
+pub struct SecurityHeaders {
+    base: HeaderList,
+    overrides: Vec<(String, HeaderList)>,
+}
+
+fn parse(name: &str, value: &str) -> Result<(HeaderName, Option<HeaderValue>), SecurityError> {
+    let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| SecurityError::Name(name.to_owned()))?;
+
+    if value.is_empty() {
+        return Ok((name, None));
+    }
+
+    let value = HeaderValue::from_str(value).map_err(|_| SecurityError::Value(name.to_string(), value.to_owned()))?;
+
+    Ok((name, Some(value)))
+}
+
+impl SecurityHeaders {
+    pub fn new(config: &SecurityConfig) -> Result<SecurityHeaders, SecurityError> {
+        let mut base = vec![parse("x-content-type-options", "nosniff")?];
+
+        let optional = [
+            ("content-security-policy", &config.content_security_policy),
+            ("referrer-policy", &config.referrer_policy),
+            ("permissions-policy", &config.permissions_policy),
+            ("x-frame-options", &config.frame_options),
+        ];
+
+        for (name, value) in optional.iter() {
+            if let Some(value) = value {
+                base.push(parse(name, value)?);
+            }
+        }
+
+        if config.cross_origin_isolation {
+            base.push(parse("cross-origin-opener-policy", "same-origin")?);
+            base.push(parse("cross-origin-embedder-policy", "require-corp")?);
+            base.push(parse("cross-origin-resource-policy", "same-origin")?);
+        }
+
+        let overrides = config
+            .overrides
+            .iter()
+            .map(|o| {
+                let headers = o.headers.iter().map(|(name, value)| parse(name, value)).collect::<Result<_, _>>()?;
+                Ok((o.prefix.clone(), headers))
+            })
+            .collect::<Result<_, SecurityError>>()?;
+
+        Ok(SecurityHeaders { base, overrides })
+    }
+
+    /// Adds the configured headers for `path`, leaving any the handler already set untouched
+    pub fn apply(&self, path: &str, headers: &mut HeaderMap) {
+        let mut resolved: Vec<&(HeaderName, Option<HeaderValue>)> = self.base.iter().collect();
+
+        for (prefix, list) in &self.overrides {
+            if path.starts_with(prefix.as_str()) {
+                for entry in list {
+                    resolved.retain(|(name, _)| *name != entry.0);
+                    resolved.push(entry);
+                }
+            }
+        }
+
+        for (name, value) in resolved {
+            if let Some(value) = value {
+                headers.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+        }
+    }
+}
+
+/// Wraps `filter` so its responses carry the security headers
+pub fn with_security_headers<F, R>(
+    security: Arc<SecurityHeaders>,
+    filter: F,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    warp::path::full().and(filter).map(move |path: FullPath, reply: R| {
+        let mut res = reply.into_response();
+        security.apply(path.as_str(), res.headers_mut());
+        res
+    })
+}
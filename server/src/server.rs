@@ -0,0 +1,247 @@
+//! Connection handling shared by the plain and TLS listeners
+//!
+//! warp's own `serve_incoming` discards the peer address of custom transports, so connections are driven
+//! through hyper directly and the address is passed to filters through the request extensions instead.
+
+use std::convert::Infallible;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use hyper::body::HttpBody;
+use hyper::service::{make_service_fn, service_fn, Service};
+use hyper::{Body, Request};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+use warp::http::{header, HeaderValue};
+use warp::{Filter, Rejection, Reply};
+
+use crate::logging::{self, AccessRecord, RequestId};
+use crate::shutdown::{Guard, Shutdown, Tracker};
+
+/// Peer address of the connection a request arrived on
+#[derive(Debug, Clone, Copy)]
+pub struct RemoteAddr(pub SocketAddr);
+
+/// Length of a response body, from its `Content-Length` header for streamed bodies that do not know it themselves
+fn content_length(res: &hyper::Response<Body>) -> Option<u64> {
+    res.body().size_hint().exact().or_else(|| res.headers().get(header::CONTENT_LENGTH)?.to_str().ok()?.parse().ok())
+}
+
+/// Extracts the peer address of the current connection
+pub fn remote_addr() -> impl Filter<Extract = (Option<SocketAddr>,), Error = Infallible> + Clone {
+    warp::ext::optional::<RemoteAddr>().map(|addr: Option<RemoteAddr>| addr.map(|addr| addr.0))
+}
+
+enum Stream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+pub struct Connection {
+    stream: Stream,
+    addr: SocketAddr,
+    _guard: Guard,
+}
+
+impl Connection {
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut().stream {
+            Stream::Plain(ref mut stream) => Pin::new(stream).poll_read(cx, buf),
+            Stream::Tls(ref mut stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut().stream {
+            Stream::Plain(ref mut stream) => Pin::new(stream).poll_write(cx, buf),
+            Stream::Tls(ref mut stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        match self.get_mut().stream {
+            Stream::Plain(ref mut stream) => Pin::new(stream).poll_flush(cx),
+            Stream::Tls(ref mut stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        match self.get_mut().stream {
+            Stream::Plain(ref mut stream) => Pin::new(stream).poll_shutdown(cx),
+            Stream::Tls(ref mut stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// A bound socket, optionally terminating TLS
+pub struct Listener {
+    pub listener: TcpListener,
+    pub tls: Option<TlsAcceptor>,
+}
+
+/// How long a client has to complete the TLS handshake, so that idle connections cannot hold up a task and the drain
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Accepts connections until shutdown, performing TLS handshakes off the accept loop if an acceptor is given
+fn incoming(listener: Listener, shutdown: Shutdown, tracker: Arc<Tracker>) -> mpsc::Receiver<io::Result<Connection>> {
+    let Listener { mut listener, tls } = listener;
+    let (mut tx, rx) = mpsc::channel(64);
+
+    tokio::spawn(async move {
+        loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = shutdown.clone().wait() => break,
+            };
+
+            let (stream, addr) = match accepted {
+                Ok(conn) => conn,
+                Err(e) => {
+                    // usually file descriptor exhaustion, so back off instead of spinning
+                    log::error!("error accepting connection: {}", e);
+                    tokio::time::delay_for(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+
+            let _ = stream.set_nodelay(true);
+
+            let guard = tracker.connection();
+
+            match tls {
+                None => {
+                    let conn = Connection {
+                        stream: Stream::Plain(stream),
+                        addr,
+                        _guard: guard,
+                    };
+
+                    if tx.send(Ok(conn)).await.is_err() {
+                        break;
+                    }
+                }
+                Some(ref acceptor) => {
+                    let acceptor = acceptor.clone();
+                    let mut tx = tx.clone();
+
+                    tokio::spawn(async move {
+                        match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                            Ok(Ok(stream)) => {
+                                let conn = Connection {
+                                    stream: Stream::Tls(Box::new(stream)),
+                                    addr,
+                                    _guard: guard,
+                                };
+
+                                let _ = tx.send(Ok(conn)).await;
+                            }
+                            Ok(Err(e)) => log::debug!("TLS handshake with {} failed: {}", addr, e),
+                            Err(_) => log::debug!("TLS handshake with {} timed out", addr),
+                        }
+                    });
+                }
+            }
+        }
+    });
+
+    rx
+}
+
+/// Shared state for every listener of the process
+#[derive(Clone)]
+pub struct Server {
+    pub shutdown: Shutdown,
+    pub tracker: Arc<Tracker>,
+    pub access_log: bool,
+}
+
+impl Server {
+    /// Serves `filter` on every connection accepted by `listener` until shutdown is triggered,
+    /// then waits for open connections to finish their current requests.
+    pub async fn serve<F>(self, filter: F, listener: Listener) -> Result<(), hyper::Error>
+    where
+        F: Filter<Error = Rejection> + Clone + Send + Sync + 'static,
+        F::Extract: Reply,
+    {
+        let Server { shutdown, tracker, access_log } = self;
+
+        let service = warp::service(filter);
+        let incoming = incoming(listener, shutdown.clone(), tracker.clone());
+
+        let make_service = make_service_fn(move |conn: &Connection| {
+            let service = service.clone();
+            let tracker = tracker.clone();
+            let remote = conn.remote_addr();
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |mut req: Request<Body>| {
+                    let start = Instant::now();
+                    let id = RequestId::for_request(req.headers());
+
+                    req.extensions_mut().insert(RemoteAddr(remote));
+                    req.extensions_mut().insert(id.clone());
+
+                    // only captured when they will be logged
+                    let details = match access_log {
+                        false => None,
+                        true => {
+                            let header = |name| req.headers().get(name).and_then(|v: &HeaderValue| v.to_str().ok()).map(str::to_owned);
+
+                            Some((
+                                req.method().clone(),
+                                req.uri().path_and_query().map_or_else(|| req.uri().path().to_owned(), |pq| pq.as_str().to_owned()),
+                                header(header::USER_AGENT),
+                                header(header::REFERER),
+                            ))
+                        }
+                    };
+
+                    let guard = tracker.request();
+                    let response = service.clone().call(req);
+
+                    async move {
+                        let mut res = response.await?;
+                        drop(guard);
+
+                        if let Some((method, path, user_agent, referer)) = details {
+                            logging::access(&AccessRecord {
+                                id: &id,
+                                remote,
+                                method: &method,
+                                path: &path,
+                                status: res.status(),
+                                content_length: content_length(&res),
+                                latency: start.elapsed(),
+                                user_agent: user_agent.as_deref(),
+                                referer: referer.as_deref(),
+                            });
+                        }
+
+                        res.headers_mut().insert("x-request-id", id.0);
+
+                        Ok::<_, Infallible>(res)
+                    }
+                }))
+            }
+        });
+
+        hyper::Server::builder(hyper::server::accept::from_stream(incoming))
+            .serve(make_service)
+            .with_graceful_shutdown(shutdown.wait())
+            .await
+    }
+}
@@ -0,0 +1,104 @@
+//! Graceful shutdown on SIGINT/SIGTERM and tracking of open connections and in-flight requests
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Resolves once shutdown has been requested. Cheap to clone.
+#[derive(Clone)]
+pub struct Shutdown {
+    rx: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    /// Returns a handle that is triggered by SIGINT or SIGTERM
+    pub fn on_signal() -> Shutdown {
+        let (tx, rx) = watch::channel(false);
+
+        tokio::spawn(async move {
+            let signal = wait_for_signal().await;
+            log::info!("received {}, shutting down", signal);
+            let _ = tx.broadcast(true);
+
+            // keep the sender alive so receivers don't observe a closed channel as a second shutdown
+            futures::future::pending::<()>().await;
+        });
+
+        Shutdown { rx }
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    pub async fn wait(mut self) {
+        while !*self.rx.borrow() {
+            if self.rx.recv().await.is_none() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> &'static str {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut term = signal(SignalKind::terminate()).expect("unable to install SIGTERM handler");
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+        _ = term.recv() => "SIGTERM",
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> &'static str {
+    let _ = tokio::signal::ctrl_c().await;
+    "Ctrl-C"
+}
+
+/// Counts of open connections and in-flight requests across all listeners
+#[derive(Debug, Default)]
+pub struct Tracker {
+    connections: AtomicUsize,
+    requests: AtomicUsize,
+}
+
+impl Tracker {
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    pub fn connection(self: &Arc<Self>) -> Guard {
+        self.connections.fetch_add(1, Ordering::SeqCst);
+        Guard(self.clone(), Kind::Connection)
+    }
+
+    pub fn request(self: &Arc<Self>) -> Guard {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        Guard(self.clone(), Kind::Request)
+    }
+}
+
+enum Kind {
+    Connection,
+    Request,
+}
+
+/// Decrements its counter when dropped
+pub struct Guard(Arc<Tracker>, Kind);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        match self.1 {
+            Kind::Connection => self.0.connections.fetch_sub(1, Ordering::SeqCst),
+            Kind::Request => self.0.requests.fetch_sub(1, Ordering::SeqCst),
+        };
+    }
+}
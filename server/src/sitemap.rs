@@ -0,0 +1,274 @@
+//! `/sitemap.xml` and `/robots.txt`
+//!
+//! The sitemap lists every page the client router knows about, including each post and project, with `lastmod` taken
+//! from the content dates. Like the feeds it needs absolute URLs, so it is only served when `feed.base_url` is
+//! configured, never built from the request's `Host` header. `robots.txt` keeps crawlers out of the configured paths
+//! and points them at the sitemap when there is one.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use hyper::body::Bytes;
+use nova_common::Page;
+use warp::http::HeaderMap;
+use warp::{Filter, Rejection};
+
+use crate::assets::Assets;
+use crate::blog::{Blog, Index as BlogIndex};
+use crate::feed::{self, FeedConfig};
+use crate::projects::{Index as ProjectIndex, Projects};
+use crate::render::{self, escape};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RobotsConfig {
+    /// Path prefixes crawlers are asked to stay out of
+    pub disallow: Vec<String>,
+
+    /// Lines appended to `robots.txt` as they are, e.g. rules for a specific user agent
+    pub extra: Vec<String>,
+}
+
+impl Default for RobotsConfig {
+    fn default() -> Self {
+        RobotsConfig {
+            disallow: vec!["/api/".to_owned()],
+            extra: Vec::new(),
+        }
+    }
+}
+
+/// When the content of `page` last changed, as far as the server can tell
+fn last_modified(page: &Page, blog: &BlogIndex, projects: &ProjectIndex) -> Option<DateTime<Utc>> {
+    let newest_post = || blog.posts.iter().map(|post| post.updated).max();
+    let newest_project = || projects.projects.iter().map(|project| project.date).max();
+
+    match page {
+        Page::Index => newest_post().max(newest_project()),
+        Page::Blog => newest_post(),
+        Page::Post(slug) => blog.get(slug).map(|post| post.updated),
+        Page::Portfolio => newest_project(),
+        Page::Project(slug) => projects.get(slug).map(|project| project.date),
+        Page::About | Page::Contact | Page::NotFound(_) => None,
+    }
+}
+
+/// Sitemap of every page, see <https://www.sitemaps.org/protocol.html>
+pub fn sitemap(blog: &BlogIndex, projects: &ProjectIndex, base: &str) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+
+    out.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+
+    for page in render::pages(blog, projects) {
+        out.push_str("  <url>\n");
+        out.push_str(&format!("    <loc>{}{}</loc>\n", escape(base), escape(&page.path())));
+
+        if let Some(date) = last_modified(&page, blog, projects) {
+            out.push_str(&format!("    <lastmod>{}</lastmod>\n", date.to_rfc3339_opts(SecondsFormat::Secs, true)));
+        }
+
+        out.push_str("  </url>\n");
+    }
+
+    out.push_str("</urlset>\n");
+    out
+}
+
+/// `robots.txt` allowing everything but `disallow`, and pointing at the sitemap when the site's URL is known
+pub fn robots(config: &RobotsConfig, base: Option<&str>) -> String {
+    let mut out = String::from("User-agent: *\n");
+
+    if config.disallow.is_empty() {
+        out.push_str("Disallow:\n");
+    }
+
+    for path in &config.disallow {
+        out.push_str(&format!("Disallow: {}\n", path));
+    }
+
+    for line in &config.extra {
+        out.push_str(line);
+        out.push('\n');
+    }
+
+    if let Some(base) = base {
+        out.push_str(&format!("\nSitemap: {}/sitemap.xml\n", base));
+    }
+
+    out
+}
+
+/// Serves `robots.txt`, and the sitemap when `base_url` is configured
+pub fn routes(
+    blog: Arc<Blog>,
+    projects: Arc<Projects>,
+    assets: Arc<Assets>,
+    feed: FeedConfig,
+    robots_config: RobotsConfig,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    let feed = Arc::new(feed);
+    let robots_config = Arc::new(robots_config);
+
+    let sitemap = {
+        let feed = feed.clone();
+        let assets = assets.clone();
+
+        warp::path!("sitemap.xml").and(warp::header::headers_cloned()).and_then(move |headers: HeaderMap| {
+            let blog = blog.clone();
+            let projects = projects.clone();
+            let assets = assets.clone();
+            let feed = feed.clone();
+
+            async move {
+                let base = feed::base_url(&feed).ok_or_else(warp::reject::not_found)?;
+                let body = sitemap(&blog.index(), &projects.index(), base);
+                let mime = "application/xml; charset=utf-8".parse().expect("valid sitemap mime type");
+
+                Ok::<_, Rejection>(assets.serve_generated("sitemap.xml", mime, Bytes::from(body), &headers).await)
+            }
+        })
+    };
+
+    let robots = warp::path!("robots.txt").and(warp::header::headers_cloned()).and_then(move |headers: HeaderMap| {
+        let assets = assets.clone();
+        let feed = feed.clone();
+        let robots_config = robots_config.clone();
+
+        async move {
+            let body = robots(&robots_config, feed::base_url(&feed));
+
+            Ok::<_, Rejection>(assets.serve_generated("robots.txt", mime_guess::mime::TEXT_PLAIN_UTF_8, Bytes::from(body), &headers).await)
+        }
+    });
+
+    warp::get().or(warp::head()).unify().and(sitemap.or(robots).unify())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use roxmltree::Document;
+    use warp::http::StatusCode;
+
+    use super::*;
+    use crate::blog::{BlogConfig, Post};
+    use crate::cache::{CacheConfig, CachePolicy};
+    use crate::compression::CompressionConfig;
+    use crate::projects::ProjectsConfig;
+
+    const SITEMAP: &str = "http://www.sitemaps.org/schemas/sitemap/0.9";
+
+    fn blog() -> BlogIndex {
+        let post = |slug: &str, front: &str| Arc::new(Post::parse(Path::new(&format!("posts/{}.md", slug)), &format!("+++\n{}\n+++\n", front)).expect("valid test post"));
+
+        BlogIndex::new(vec![
+            post("hello", "title = \"Hello\"\ndate = 2020-07-01"),
+            post("revised", "title = \"Revised\"\ndate = 2020-07-03\nupdated = 2020-08-01T12:30:00Z"),
+        ])
+    }
+
+    fn projects() -> ProjectIndex {
+        ProjectIndex::parse(Path::new("projects.toml"), "[[project]]\nslug = \"doom-fire\"\ntitle = \"Doom Fire\"\ndescription = \"\"\ndate = 2020-06-14\n")
+            .expect("valid test manifest")
+    }
+
+    #[test]
+    fn sitemap_lists_every_page() {
+        let xml = sitemap(&blog(), &projects(), "https://nova.example");
+        let doc = Document::parse(&xml).expect("well-formed sitemap");
+
+        assert_eq!(doc.root_element().tag_name().namespace(), Some(SITEMAP));
+
+        let urls: Vec<(&str, Option<&str>)> = doc
+            .root_element()
+            .children()
+            .filter(|node| node.is_element())
+            .map(|url| {
+                let child = |name| url.children().find(|node| node.tag_name().name() == name).and_then(|node| node.text());
+                (child("loc").expect("every url has a loc"), child("lastmod"))
+            })
+            .collect();
+
+        assert_eq!(
+            urls,
+            [
+                ("https://nova.example/", Some("2020-08-01T12:30:00Z")),
+                ("https://nova.example/portfolio", Some("2020-06-14T00:00:00Z")),
+                ("https://nova.example/about", None),
+                ("https://nova.example/blog", Some("2020-08-01T12:30:00Z")),
+                ("https://nova.example/contact", None),
+                ("https://nova.example/portfolio/doom-fire", Some("2020-06-14T00:00:00Z")),
+                ("https://nova.example/blog/revised", Some("2020-08-01T12:30:00Z")),
+                ("https://nova.example/blog/hello", Some("2020-07-01T00:00:00Z")),
+            ]
+        );
+    }
+
+    #[test]
+    fn robots_points_at_a_known_sitemap() {
+        let config = RobotsConfig {
+            disallow: vec!["/api/".to_owned(), "/drafts/".to_owned()],
+            extra: vec!["User-agent: BadBot".to_owned(), "Disallow: /".to_owned()],
+        };
+
+        assert_eq!(
+            robots(&config, Some("https://nova.example")),
+            "User-agent: *\nDisallow: /api/\nDisallow: /drafts/\nUser-agent: BadBot\nDisallow: /\n\nSitemap: https://nova.example/sitemap.xml\n"
+        );
+
+        let config = RobotsConfig {
+            disallow: Vec::new(),
+            extra: Vec::new(),
+        };
+
+        assert_eq!(robots(&config, None), "User-agent: *\nDisallow:\n");
+    }
+
+    fn routes(base_url: Option<&str>) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+        let nowhere = std::env::temp_dir().join("nova-test-missing");
+
+        let policy = CachePolicy::new(&CacheConfig::default()).expect("default cache policy");
+        let assets = Arc::new(Assets::new(&nowhere, CompressionConfig::default(), policy));
+
+        let blog = Arc::new(Blog::new(BlogConfig {
+            content: nowhere.clone(),
+            ..BlogConfig::default()
+        }));
+
+        let projects = Arc::new(Projects::new(ProjectsConfig {
+            manifest: nowhere.join("projects.toml"),
+            ..ProjectsConfig::default()
+        }));
+
+        let feed = FeedConfig {
+            base_url: base_url.map(str::to_owned),
+            ..FeedConfig::default()
+        };
+
+        super::routes(blog, projects, assets, feed, RobotsConfig::default())
+    }
+
+    #[tokio::test]
+    async fn urls_never_come_from_the_host_header() {
+        let routes = routes(None);
+
+        let res = warp::test::request().path("/sitemap.xml").header("host", "evil.example").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let res = warp::test::request().path("/robots.txt").header("host", "evil.example").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!std::str::from_utf8(res.body()).expect("utf-8 robots.txt").contains("evil.example"));
+
+        let routes = self::routes(Some("https://nova.example/"));
+
+        let res = warp::test::request().path("/sitemap.xml").header("host", "evil.example").reply(&routes).await;
+        let body = std::str::from_utf8(res.body()).expect("utf-8 sitemap");
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(body.contains("<loc>https://nova.example/</loc>") && !body.contains("evil.example"));
+
+        let res = warp::test::request().path("/robots.txt").header("host", "evil.example").reply(&routes).await;
+        assert!(std::str::from_utf8(res.body()).expect("utf-8 robots.txt").ends_with("Sitemap: https://nova.example/sitemap.xml\n"));
+    }
+}
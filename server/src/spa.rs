@@ -0,0 +1,63 @@
+//! Route-aware fallback to the single-page app shell
+//!
+//! Paths the client router knows get the rendered shell with 200, other extensionless paths get it with 404 so the
+//! client can render its not-found view, and anything that looks like a file or an API call is a plain 404.
+
+use std::sync::Arc;
+
+use nova_common::Page;
+use percent_encoding::percent_decode_str;
+use warp::http::HeaderMap;
+use warp::path::FullPath;
+use warp::{Filter, Rejection};
+
+use crate::render::Renderer;
+
+/// Matches `path` against the client routes, see [`Page::from_path`](nova_common::Page::from_path)
+pub fn is_client_route(path: &str) -> bool {
+    !matches!(Page::from_path(path), Page::NotFound(_))
+}
+
+/// Whether the last path segment has a file extension, like `/app.jss` or `/fonts/icons.woff2`
+pub fn looks_like_asset(path: &str) -> bool {
+    path.rsplit('/').next().is_some_and(|name| name.contains('.'))
+}
+
+/// GET or HEAD with the percent-decoded request path
+fn page_request() -> impl Filter<Extract = (String, HeaderMap), Error = Rejection> + Clone {
+    warp::get()
+        .or(warp::head())
+        .unify()
+        .and(warp::path::full())
+        .map(|path: FullPath| percent_decode_str(path.as_str()).decode_utf8_lossy().into_owned())
+        .and(warp::header::headers_cloned())
+}
+
+/// Serves the shell for the client routes, rejecting every other path
+pub fn shell(renderer: Arc<Renderer>) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    page_request().and_then(move |path: String, headers: HeaderMap| {
+        let renderer = renderer.clone();
+
+        async move {
+            match Page::from_path(&path) {
+                Page::NotFound(_) => Err(warp::reject::not_found()),
+                page => renderer.respond(&page, &headers).await,
+            }
+        }
+    })
+}
+
+/// Serves the shell with a 404 status for unknown routes, and a plain 404 for assets and the API
+pub fn fallback(renderer: Arc<Renderer>) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    page_request().and_then(move |path: String, headers: HeaderMap| {
+        let renderer = renderer.clone();
+
+        async move {
+            if looks_like_asset(&path) || path.starts_with("/api/") {
+                return Err(warp::reject::not_found());
+            }
+
+            renderer.respond(&Page::from_path(&path), &headers).await
+        }
+    })
+}
@@ -0,0 +1,363 @@
+//! Routing tests for the static files and the app shell, run with `warp::test` against a dist directory written to a
+//! temporary directory, with a file next to it that must never be reachable
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use hyper::body::{Body, Bytes};
+use warp::http::{header, Response, StatusCode};
+use warp::{Filter, Rejection};
+
+use crate::assets::Assets;
+use crate::blog::{Blog, BlogConfig};
+use crate::cache::{CacheConfig, CachePolicy};
+use crate::compression::CompressionConfig;
+use crate::projects::{Projects, ProjectsConfig};
+use crate::render::Renderer;
+use crate::{errors, files, index, spa};
+
+const TEMPLATE: &str = r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta charset="utf-8">
+    <title>Nova Dev</title>
+    <link rel="stylesheet" href="main.css">
+</head>
+<body>
+    <div id="app"></div>
+</body>
+</html>
+"#;
+
+const SECRET: &str = "[package]\nname = \"do-not-serve\"\n";
+
+/// Temporary site root holding `dist/` and a `Cargo.toml` beside it, removed when dropped
+struct Fixture {
+    root: PathBuf,
+}
+
+impl Fixture {
+    fn new() -> Fixture {
+        let root = std::env::temp_dir().join(format!("nova-test-{}", uuid::Uuid::new_v4().to_simple()));
+        let fixture = Fixture { root };
+
+        fixture.write("Cargo.toml", SECRET.as_bytes());
+        fixture.write("dist/index.html", TEMPLATE.as_bytes());
+        fixture.write("dist/main.css", "body { color: #333; }\n".repeat(100).as_bytes());
+        fixture.write("dist/app_bg.wasm", &wasm());
+        fixture.write("dist/images/logo.svg", br#"<svg xmlns="http://www.w3.org/2000/svg"/>"#);
+        fixture.write("dist/docs/index.html", b"<p>docs</p>");
+
+        fixture
+    }
+
+    fn write(&self, path: &str, data: &[u8]) {
+        let path = self.root.join(path);
+
+        fs::create_dir_all(path.parent().expect("fixture files are in a directory")).expect("create fixture directory");
+        fs::write(path, data).expect("write fixture file");
+    }
+
+    fn dist(&self) -> PathBuf {
+        self.root.join("dist")
+    }
+
+    /// The site's routes as `main` puts them together, without rate limiting and instrumentation
+    fn routes(&self) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+        routes(&self.root, &self.dist())
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+/// Some bytes that are not text, long enough for ranges
+fn wasm() -> Vec<u8> {
+    (0..=255u8).cycle().take(1000).collect()
+}
+
+fn routes(root: &Path, dist: &Path) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    let policy = CachePolicy::new(&CacheConfig::default()).expect("default cache policy");
+    let assets = Arc::new(Assets::new(dist, CompressionConfig::default(), policy));
+
+    let blog = Arc::new(Blog::new(BlogConfig {
+        content: root.to_owned(),
+        ..BlogConfig::default()
+    }));
+
+    let projects = Arc::new(Projects::new(ProjectsConfig {
+        manifest: root.join("projects.toml"),
+        ..ProjectsConfig::default()
+    }));
+
+    let renderer = Arc::new(Renderer::new(assets.clone(), blog, projects, None, true, false));
+
+    errors::recover(spa::shell(renderer.clone()).or(files(assets)).or(index(renderer)))
+}
+
+fn header(res: &Response<Bytes>, name: header::HeaderName) -> &str {
+    res.headers().get(&name).map_or("", |value| value.to_str().expect("visible header value"))
+}
+
+fn body(res: &Response<Bytes>) -> &str {
+    std::str::from_utf8(res.body()).expect("utf-8 body")
+}
+
+#[tokio::test]
+async fn root_is_the_rendered_shell() {
+    let fixture = Fixture::new();
+    let res = warp::test::request().path("/").reply(&fixture.routes()).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(header(&res, header::CONTENT_TYPE), "text/html; charset=utf-8");
+    assert!(body(&res).contains("<title>Nova Dev</title>"));
+    assert!(!body(&res).contains(r#"<div id="app"></div>"#), "the app container should hold the rendered page");
+}
+
+#[tokio::test]
+async fn client_routes_are_rendered() {
+    let fixture = Fixture::new();
+
+    for path in &["/about", "/about/", "/portfolio", "/blog", "/contact"] {
+        let res = warp::test::request().path(path).reply(&fixture.routes()).await;
+
+        assert_eq!(res.status(), StatusCode::OK, "{}", path);
+        assert!(header(&res, header::CONTENT_TYPE).starts_with("text/html"), "{}", path);
+        assert!(body(&res).contains(r#"<div id="app">"#), "{}", path);
+    }
+
+    let res = warp::test::request().path("/about").reply(&fixture.routes()).await;
+    assert!(body(&res).contains("<title>About · Nova Dev</title>"));
+}
+
+#[tokio::test]
+async fn unknown_routes_get_the_shell_with_404() {
+    let fixture = Fixture::new();
+
+    for path in &["/no/such/page", "/blog/missing-post", "/portfolio/missing-project"] {
+        let res = warp::test::request().path(path).reply(&fixture.routes()).await;
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND, "{}", path);
+        assert!(header(&res, header::CONTENT_TYPE).starts_with("text/html"), "{}", path);
+        assert!(body(&res).contains(r#"<div id="app">"#), "{} should be handled by the client", path);
+        assert!(body(&res).contains("not found"), "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn missing_assets_and_api_calls_are_not_the_shell() {
+    let fixture = Fixture::new();
+
+    let res = warp::test::request().path("/missing.js").reply(&fixture.routes()).await;
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert!(!body(&res).contains(r#"<div id="app">"#));
+
+    let res = warp::test::request().path("/api/missing").reply(&fixture.routes()).await;
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert_eq!(header(&res, header::CONTENT_TYPE), "application/problem+json");
+}
+
+#[tokio::test]
+async fn files_have_their_content_types() {
+    let fixture = Fixture::new();
+
+    let cases = [
+        ("/main.css", "text/css"),
+        ("/app_bg.wasm", "application/wasm"),
+        ("/images/logo.svg", "image/svg+xml"),
+        ("/index.html", "text/html"),
+    ];
+
+    for (path, mime) in &cases {
+        let res = warp::test::request().path(path).reply(&fixture.routes()).await;
+
+        assert_eq!(res.status(), StatusCode::OK, "{}", path);
+        assert_eq!(header(&res, header::CONTENT_TYPE), *mime, "{}", path);
+        assert!(!header(&res, header::ETAG).is_empty(), "{}", path);
+    }
+
+    let res = warp::test::request().path("/app_bg.wasm").reply(&fixture.routes()).await;
+    assert_eq!(res.body().as_ref(), &wasm()[..]);
+}
+
+#[tokio::test]
+async fn directories_serve_their_index() {
+    let fixture = Fixture::new();
+
+    for path in &["/docs", "/docs/"] {
+        let res = warp::test::request().path(path).reply(&fixture.routes()).await;
+
+        assert_eq!(res.status(), StatusCode::OK, "{}", path);
+        assert_eq!(body(&res), "<p>docs</p>", "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn paths_cannot_escape_dist() {
+    let fixture = Fixture::new();
+
+    let paths = [
+        "/../Cargo.toml",
+        "/images/../../Cargo.toml",
+        "/%2e%2e/Cargo.toml",
+        "/%2E%2E/Cargo.toml",
+        "/..%2fCargo.toml",
+        "/images/..%2f..%2fCargo.toml",
+        "/..%5cCargo.toml",
+        "/%2fetc/passwd",
+    ];
+
+    for path in &paths {
+        let res = warp::test::request().path(path).reply(&fixture.routes()).await;
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND, "{}", path);
+        assert!(!body(&res).contains("do-not-serve"), "{} leaked a file outside of dist", path);
+    }
+}
+
+#[tokio::test]
+async fn head_is_answered_like_get() {
+    let fixture = Fixture::new();
+
+    for path in &["/", "/about", "/main.css", "/app_bg.wasm"] {
+        let get = warp::test::request().path(path).reply(&fixture.routes()).await;
+        let head = warp::test::request().method("HEAD").path(path).reply(&fixture.routes()).await;
+
+        assert_eq!(head.status(), StatusCode::OK, "{}", path);
+        assert_eq!(header(&head, header::CONTENT_TYPE), header(&get, header::CONTENT_TYPE), "{}", path);
+        assert_eq!(header(&head, header::CONTENT_LENGTH), header(&get, header::CONTENT_LENGTH), "{}", path);
+        assert_eq!(header(&head, header::ETAG), header(&get, header::ETAG), "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn other_methods_are_not_allowed() {
+    let fixture = Fixture::new();
+
+    for method in &["POST", "PUT", "DELETE"] {
+        let res = warp::test::request().method(method).path("/main.css").reply(&fixture.routes()).await;
+
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED, "{}", method);
+    }
+}
+
+#[tokio::test]
+async fn ranges_are_served_partially() {
+    let fixture = Fixture::new();
+    let data = wasm();
+
+    let range = |range: &'static str| warp::test::request().path("/app_bg.wasm").header("range", range);
+
+    let res = range("bytes=0-9").reply(&fixture.routes()).await;
+
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(header(&res, header::CONTENT_RANGE), "bytes 0-9/1000");
+    assert_eq!(header(&res, header::CONTENT_LENGTH), "10");
+    assert_eq!(res.body().as_ref(), &data[..10]);
+
+    let res = range("bytes=990-").reply(&fixture.routes()).await;
+
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(header(&res, header::CONTENT_RANGE), "bytes 990-999/1000");
+    assert_eq!(res.body().as_ref(), &data[990..]);
+
+    let res = range("bytes=-5").reply(&fixture.routes()).await;
+
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.body().as_ref(), &data[995..]);
+
+    // an end past the file is cut short rather than refused
+    let res = range("bytes=500-5000").reply(&fixture.routes()).await;
+
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(header(&res, header::CONTENT_RANGE), "bytes 500-999/1000");
+
+    let res = range("bytes=1000-1100").reply(&fixture.routes()).await;
+
+    assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(header(&res, header::CONTENT_RANGE), "bytes */1000");
+}
+
+#[tokio::test]
+async fn several_ranges_get_the_whole_file() {
+    let fixture = Fixture::new();
+
+    let res = warp::test::request().path("/app_bg.wasm").header("range", "bytes=0-9,20-29").reply(&fixture.routes()).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get(header::CONTENT_RANGE), None);
+    assert_eq!(header(&res, header::CONTENT_LENGTH), "1000");
+    assert_eq!(res.body().as_ref(), &wasm()[..]);
+}
+
+#[tokio::test]
+async fn large_files_are_streamed_intact() {
+    let fixture = Fixture::new();
+    let data: Vec<u8> = (0..=250u8).cycle().take(200_000).collect();
+
+    fixture.write("dist/large.bin", &data);
+
+    let res = warp::test::request().path("/large.bin").reply(&fixture.routes()).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(header(&res, header::CONTENT_LENGTH), "200000");
+    assert_eq!(res.body().as_ref(), &data[..]);
+
+    // spans chunk boundaries on both ends
+    let res = warp::test::request().path("/large.bin").header("range", "bytes=65000-140000").reply(&fixture.routes()).await;
+
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.body().as_ref(), &data[65000..=140000]);
+}
+
+#[tokio::test]
+async fn stale_if_range_gets_the_whole_file() {
+    let fixture = Fixture::new();
+
+    let full = warp::test::request().path("/app_bg.wasm").reply(&fixture.routes()).await;
+    assert_eq!(header(&full, header::ACCEPT_RANGES), "bytes");
+
+    let etag = header(&full, header::ETAG).to_owned();
+
+    let res = warp::test::request().path("/app_bg.wasm").header("range", "bytes=0-9").header("if-range", etag.as_str()).reply(&fixture.routes()).await;
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+
+    let res = warp::test::request().path("/app_bg.wasm").header("range", "bytes=0-9").header("if-range", "\"outdated\"").reply(&fixture.routes()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.body().len(), 1000);
+}
+
+#[tokio::test]
+async fn matching_etags_are_not_modified() {
+    let fixture = Fixture::new();
+
+    let res = warp::test::request().path("/main.css").reply(&fixture.routes()).await;
+    let etag = header(&res, header::ETAG).to_owned();
+
+    let res = warp::test::request().path("/main.css").header("if-none-match", etag.as_str()).reply(&fixture.routes()).await;
+
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert!(res.body().is_empty());
+}
+
+#[tokio::test]
+async fn text_is_compressed_when_accepted() {
+    let fixture = Fixture::new();
+
+    let res = warp::test::request().path("/main.css").header("accept-encoding", "gzip").reply(&fixture.routes()).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(header(&res, header::CONTENT_ENCODING), "gzip");
+    assert_eq!(header(&res, header::VARY), "accept-encoding");
+    assert!(res.body().len() < 2200);
+
+    // files this small are not worth compressing
+    let res = warp::test::request().path("/images/logo.svg").header("accept-encoding", "gzip").reply(&fixture.routes()).await;
+    assert_eq!(header(&res, header::CONTENT_ENCODING), "");
+}
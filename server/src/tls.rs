@@ -0,0 +1,359 @@
+//! HTTPS termination with hot-reloadable certificates and the plain HTTP redirect listener
+
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use tokio_rustls::rustls::{
+    internal::pemfile,
+    sign::{self, CertifiedKey},
+    ClientHello, NoClientAuth, ResolvesServerCert, ServerConfig,
+};
+use tokio_rustls::TlsAcceptor;
+use warp::http::Uri;
+use warp::{Filter, Rejection, Reply};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM-encoded certificate chain
+    pub cert: PathBuf,
+
+    /// PEM-encoded PKCS#8 or RSA private key
+    pub key: PathBuf,
+
+    /// Port for a plain HTTP listener that redirects to HTTPS
+    pub redirect_port: Option<u16>,
+
+    /// `max-age` of the `Strict-Transport-Security` header, in seconds
+    pub hsts_max_age: u64,
+    pub hsts_include_subdomains: bool,
+
+    /// How often to check the certificate and key for changes, in seconds. Zero disables reloading.
+    pub reload_interval: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            cert: PathBuf::from("cert.pem"),
+            key: PathBuf::from("key.pem"),
+            redirect_port: None,
+            hsts_max_age: 60 * 60 * 24 * 365,
+            hsts_include_subdomains: false,
+            reload_interval: 30,
+        }
+    }
+}
+
+impl TlsConfig {
+    pub fn hsts(&self) -> String {
+        if self.hsts_include_subdomains {
+            format!("max-age={}; includeSubDomains", self.hsts_max_age)
+        } else {
+            format!("max-age={}", self.hsts_max_age)
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TlsError {
+    #[error("unable to read {0:?}: {1}")]
+    Io(PathBuf, #[source] io::Error),
+
+    #[error("no valid certificates found in {0:?}")]
+    InvalidCert(PathBuf),
+
+    #[error("no valid private key found in {0:?}")]
+    InvalidKey(PathBuf),
+}
+
+fn load_certs(path: &Path) -> Result<Vec<tokio_rustls::rustls::Certificate>, TlsError> {
+    let file = File::open(path).map_err(|e| TlsError::Io(path.to_owned(), e))?;
+
+    match pemfile::certs(&mut BufReader::new(file)) {
+        Ok(certs) if !certs.is_empty() => Ok(certs),
+        _ => Err(TlsError::InvalidCert(path.to_owned())),
+    }
+}
+
+fn load_key(path: &Path) -> Result<Box<dyn sign::SigningKey>, TlsError> {
+    let read = |parse: fn(&mut dyn io::BufRead) -> Result<Vec<_>, ()>| -> Result<_, TlsError> {
+        let file = File::open(path).map_err(|e| TlsError::Io(path.to_owned(), e))?;
+        Ok(parse(&mut BufReader::new(file)).unwrap_or_default())
+    };
+
+    let mut keys = read(pemfile::pkcs8_private_keys)?;
+
+    if keys.is_empty() {
+        keys = read(pemfile::rsa_private_keys)?;
+    }
+
+    keys.first()
+        .and_then(|key| sign::any_supported_type(key).ok())
+        .ok_or_else(|| TlsError::InvalidKey(path.to_owned()))
+}
+
+fn load_certified_key(config: &TlsConfig) -> Result<CertifiedKey, TlsError> {
+    Ok(CertifiedKey::new(load_certs(&config.cert)?, Arc::new(load_key(&config.key)?)))
+}
+
+fn modified(config: &TlsConfig) -> Option<(SystemTime, SystemTime)> {
+    let mtime = |path| fs::metadata(path).and_then(|m| m.modified()).ok();
+
+    Some((mtime(&config.cert)?, mtime(&config.key)?))
+}
+
+/// Certificate resolver that can be swapped out while the server is running
+pub struct ReloadingResolver {
+    config: TlsConfig,
+    current: RwLock<CertifiedKey>,
+    modified: Mutex<Option<(SystemTime, SystemTime)>>,
+}
+
+impl ResolvesServerCert for ReloadingResolver {
+    fn resolve(&self, _: ClientHello) -> Option<CertifiedKey> {
+        self.current.read().ok().map(|current| current.clone())
+    }
+}
+
+impl ReloadingResolver {
+    pub fn new(config: TlsConfig) -> Result<ReloadingResolver, TlsError> {
+        Ok(ReloadingResolver {
+            current: RwLock::new(load_certified_key(&config)?),
+            modified: Mutex::new(modified(&config)),
+            config,
+        })
+    }
+
+    /// Reloads the certificate and key if either file has changed since they were last loaded.
+    ///
+    /// On failure the previous certificate stays in use.
+    pub fn reload_if_changed(&self) -> Result<bool, TlsError> {
+        let latest = modified(&self.config);
+
+        let mut last = self.modified.lock().unwrap();
+
+        if latest.is_none() || latest == *last {
+            return Ok(false);
+        }
+
+        let key = load_certified_key(&self.config)?;
+
+        *self.current.write().unwrap() = key;
+        *last = latest;
+
+        Ok(true)
+    }
+
+    /// Spawns a task that periodically reloads the certificate
+    pub fn watch(self: Arc<Self>) {
+        if self.config.reload_interval == 0 {
+            return;
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.reload_interval));
+
+        tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+
+                match self.reload_if_changed() {
+                    Ok(true) => log::info!("reloaded TLS certificate from {:?}", self.config.cert),
+                    Ok(false) => {}
+                    Err(e) => log::warn!("error reloading TLS certificate, keeping previous: {}", e),
+                }
+            }
+        });
+    }
+}
+
+/// Builds a TLS acceptor whose certificate is reloaded in the background when the files change
+pub fn acceptor(config: &TlsConfig) -> Result<TlsAcceptor, TlsError> {
+    let resolver = Arc::new(ReloadingResolver::new(config.clone())?);
+    resolver.clone().watch();
+
+    let mut server_config = ServerConfig::new(NoClientAuth::new());
+    server_config.cert_resolver = resolver;
+    server_config.set_protocols(&[b"h2".to_vec(), b"http/1.1".to_vec()]);
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Adds the `Strict-Transport-Security` header to every response
+pub fn strict_transport(config: &TlsConfig) -> warp::filters::reply::WithHeader {
+    warp::reply::with::header("strict-transport-security", config.hsts())
+}
+
+/// Permanently redirects every request to the same host and path over HTTPS on `https_port`
+pub fn redirect(https_port: u16) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let query = warp::query::raw().or(warp::any().map(String::new)).unify();
+
+    warp::header::<String>("host")
+        .and(warp::path::full())
+        .and(query)
+        .and_then(move |host: String, path: warp::path::FullPath, query: String| async move {
+            // strip any port from the host, taking care not to mangle IPv6 literals
+            let hostname = match host.rfind(':') {
+                Some(idx) if !host[idx..].contains(']') => &host[..idx],
+                _ => &host[..],
+            };
+
+            let authority = match https_port {
+                443 => hostname.to_owned(),
+                port => format!("{}:{}", hostname, port),
+            };
+
+            let path_and_query = match query.is_empty() {
+                true => path.as_str().to_owned(),
+                false => format!("{}?{}", path.as_str(), query),
+            };
+
+            Uri::builder()
+                .scheme("https")
+                .authority(authority.as_str())
+                .path_and_query(path_and_query.as_str())
+                .build()
+                .map(warp::redirect)
+                .map_err(|_| warp::reject::not_found())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use warp::http::StatusCode;
+
+    use super::*;
+
+    /// Directory holding a self-signed certificate and its key, removed when dropped
+    struct Fixture {
+        dir: PathBuf,
+        config: TlsConfig,
+    }
+
+    impl Fixture {
+        fn new() -> Fixture {
+            let dir = std::env::temp_dir().join(format!("nova-test-{}", uuid::Uuid::new_v4().to_simple()));
+            fs::create_dir_all(&dir).expect("create fixture directory");
+
+            let config = TlsConfig {
+                cert: dir.join("cert.pem"),
+                key: dir.join("key.pem"),
+                ..TlsConfig::default()
+            };
+
+            Fixture { dir, config }
+        }
+
+        /// Writes a new certificate and key, modified at `secs` after the epoch, returning the certificate
+        fn issue(&self, secs: u64) -> Vec<u8> {
+            let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).expect("self-signed certificate");
+
+            // every serialization is signed anew, so the PEM written is the one to compare against
+            let pem = cert.serialize_pem().expect("certificate PEM");
+
+            self.write(&self.config.cert, &pem, secs);
+            self.write(&self.config.key, &cert.serialize_private_key_pem(), secs);
+
+            pemfile::certs(&mut pem.as_bytes()).expect("certificate DER").remove(0).0
+        }
+
+        fn write(&self, path: &Path, pem: &str, secs: u64) {
+            fs::write(path, pem).expect("write fixture file");
+
+            let file = File::options().write(true).open(path).expect("open fixture file");
+            file.set_modified(UNIX_EPOCH + Duration::from_secs(secs)).expect("set modification time");
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn served(resolver: &ReloadingResolver) -> Vec<u8> {
+        resolver.current.read().unwrap().cert[0].0.clone()
+    }
+
+    #[test]
+    fn replaced_certificates_are_reloaded() {
+        let fixture = Fixture::new();
+        let first = fixture.issue(1_000_000);
+
+        let resolver = ReloadingResolver::new(fixture.config.clone()).expect("valid certificate");
+        assert_eq!(served(&resolver), first);
+        assert!(!resolver.reload_if_changed().expect("unchanged"));
+
+        let second = fixture.issue(2_000_000);
+        assert_ne!(first, second);
+
+        assert!(resolver.reload_if_changed().expect("reloaded"));
+        assert_eq!(served(&resolver), second);
+        assert!(!resolver.reload_if_changed().expect("unchanged"));
+    }
+
+    #[test]
+    fn broken_certificates_keep_the_previous_one() {
+        let fixture = Fixture::new();
+        let first = fixture.issue(1_000_000);
+
+        let resolver = ReloadingResolver::new(fixture.config.clone()).expect("valid certificate");
+
+        fixture.write(&fixture.config.cert, "not a certificate", 2_000_000);
+
+        assert!(matches!(resolver.reload_if_changed(), Err(TlsError::InvalidCert(_))));
+        assert_eq!(served(&resolver), first);
+    }
+
+    #[test]
+    fn missing_files_are_errors() {
+        let fixture = Fixture::new();
+
+        assert!(matches!(ReloadingResolver::new(fixture.config.clone()), Err(TlsError::Io(..))));
+
+        fixture.issue(1_000_000);
+        fixture.write(&fixture.config.key, "", 1_000_000);
+
+        assert!(matches!(ReloadingResolver::new(fixture.config.clone()), Err(TlsError::InvalidKey(_))));
+    }
+
+    #[tokio::test]
+    async fn plain_http_is_redirected() {
+        let cases = [
+            (443, "nova.example", "/blog?page=2", "https://nova.example/blog?page=2"),
+            (443, "nova.example:80", "/", "https://nova.example/"),
+            (8443, "nova.example:8080", "/about", "https://nova.example:8443/about"),
+            (443, "[::1]:80", "/", "https://[::1]/"),
+            (8443, "[::1]", "/", "https://[::1]:8443/"),
+        ];
+
+        for (port, host, path, location) in &cases {
+            let res = warp::test::request().header("host", *host).path(path).reply(&redirect(*port)).await;
+
+            assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY, "{}{}", host, path);
+            assert_eq!(res.headers()["location"], *location, "{}{}", host, path);
+        }
+    }
+
+    #[tokio::test]
+    async fn responses_carry_hsts() {
+        let routes = |config: &TlsConfig| warp::any().map(warp::reply).with(strict_transport(config));
+
+        let res = warp::test::request().reply(&routes(&TlsConfig::default())).await;
+        assert_eq!(res.headers()["strict-transport-security"], "max-age=31536000");
+
+        let config = TlsConfig {
+            hsts_max_age: 60,
+            hsts_include_subdomains: true,
+            ..TlsConfig::default()
+        };
+
+        let res = warp::test::request().reply(&routes(&config)).await;
+        assert_eq!(res.headers()["strict-transport-security"], "max-age=60; includeSubDomains");
+    }
+}